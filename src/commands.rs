@@ -1,2 +1,3 @@
 pub mod convert_page_to_html;
 pub mod convert_page_to_markdown;
+pub mod generate_stylesheet;
@@ -1,316 +1,1344 @@
-use async_recursion::async_recursion;
+use async_trait::async_trait;
 use lambda_runtime::Error;
 use serde_json::Value;
 
-use crate::helpers::fetch_title::fetch_title;
-use crate::helpers::get_all_blocks::get_all_blocks;
+use crate::helpers::embed::{detect_embed, is_allowed_host, EmbedKind};
+use crate::helpers::escape::{escape_html, is_safe_attribute_name, link_attributes, sanitize_url};
+use crate::helpers::fetch_file_size::format_file_size;
+use crate::helpers::fetch_link_metadata::LinkMetadata;
+use crate::helpers::get_page::get_page;
+use crate::helpers::html_document::html_document;
+use crate::helpers::inline_assets::inline_assets;
+use crate::helpers::link_preview::link_preview_metadata;
+use crate::helpers::link_rewriter::block_anchor;
+use crate::helpers::responsive_image::{srcset, Placeholder, ResponsiveImage};
 
-use crate::models::block::Block;
+use crate::models::block::{
+    BaseBlock, Block, BookmarkBlock, BreadcrumbBlock, BulletedListItemBlock, CalloutBlock,
+    ChildDatabaseBlock, ChildPageBlock, CodeBlock, ColumnBlock, ColumnListBlock, DividerBlock,
+    EmbedBlock, EquationBlock, FileBlock, FileField, Heading1Block, Heading2Block, Heading3Block,
+    ImageBlock, ImageField, LinkPreviewBlock, MentionBlock, NumberedListItemBlock, ParagraphBlock,
+    PdfBlock, QuoteBlock, SyncedBlock, TableBlock, TableField, TableOfContentsBlock, TableRowBlock,
+    TableRowField, TemplateBlock, ToDoBlock, ToggleBlock, UnsupportedBlock, VideoBlock, VideoField,
+};
 
+use crate::models::common::Color;
 use crate::models::objects::FileObject;
+use crate::models::rich_text::{RichText, RichTextElement};
 
-pub async fn convert_page_to_html_command(event: Value) -> Result<String, Error> {
-    let notion_api_key = event["NOTION_API_KEY"]
-        .as_str()
-        .ok_or_else(|| Error::from("The NOTION_API_KEY field is missing or not a string"))?;
+use crate::context::{option_from_event, ConvertContext};
+use crate::options::{AssetOptions, HtmlOptions};
+use crate::renderer::{render_each, render_page, RenderContext, Renderer};
 
+pub async fn convert_page_to_html_command(event: Value) -> Result<String, Error> {
     let block_id = event["block_id"]
         .as_str()
         .ok_or_else(|| Error::from("The NOTION_API_KEY field is missing or not a string"))?;
 
-    let html = convert_page_to_html(notion_api_key, block_id).await;
-    match html {
-        Ok(html) => Ok(html),
-        Err(e) => Err(Error::from(e)),
+    let options: HtmlOptions = option_from_event(&event, "html_options")?;
+
+    let mut context = ConvertContext::from_event(&event).await?;
+    if options
+        .document
+        .as_ref()
+        .is_some_and(|document| document.inline_assets)
+    {
+        // Inlined files need no store, and resized copies would only be inlined as well.
+        context.asset_localizer = None;
+    }
+
+    let renderer = HtmlRenderer::new(options);
+    let html = render_page(&renderer, block_id, &context).await;
+    context.report_unresolved_links();
+    let html = match html {
+        Ok(html) => html,
+        Err(e) => return Err(Error::from(e)),
+    };
+
+    match &renderer.options.document {
+        Some(document) => {
+            let mut page = get_page(&context.notion_api_key, block_id).await?;
+            context.resolve_page(&mut page).await;
+            let html = if document.inline_assets {
+                let asset_options: AssetOptions = option_from_event(&event, "asset_options")?;
+                let fetch_options = asset_options
+                    .inline_fetch_options(&context.fetch_options, document.inline_max_bytes);
+                inline_assets(
+                    &html,
+                    document.inline_max_bytes,
+                    document.inline_max_total_bytes,
+                    &fetch_options,
+                )
+                .await
+            } else {
+                html
+            };
+            Ok(html_document(&page, &html, &renderer.options, document))
+        }
+        None => Ok(html),
     }
 }
 
-#[async_recursion]
-pub async fn convert_page_to_html(notion_api_key: &str, block_id: &str) -> Result<String, Error> {
-    let mut html: Vec<String> = Vec::new();
-    let blocks = get_all_blocks(notion_api_key, block_id).await.unwrap();
-
-    for block in &blocks {
-        match block {
-            Block::Bookmark(bookmark_block) => {
-                let title = fetch_title(&bookmark_block.bookmark.url).await.unwrap();
-                html.push(format!(
-                    "<a href='{}' class='notion-bookmark'>{}</a>",
-                    bookmark_block.bookmark.url, title
-                ));
-            }
+/// ## HtmlRenderer - Struct
+///
+/// The built-in HTML [`Renderer`]. Blocks get the `notion-*` classes that
+/// `generate_stylesheet` styles, or whatever `options` make of them.
+pub struct HtmlRenderer {
+    pub options: HtmlOptions,
+}
 
-            Block::Breadcrumb(_) => {
-                println!("Breadcrumb is unsupported!");
-            }
+impl HtmlRenderer {
+    pub fn new(options: HtmlOptions) -> Self {
+        HtmlRenderer { options }
+    }
 
-            Block::BulletedListItem(bulleted_list_item_block) => {
-                html.push(format!(
-                    "<li class='notion-bulleted-list-item'>{}</li>",
-                    bulleted_list_item_block.bulleted_list_item.to_html()
-                ));
-            }
+    /// The attributes of the element of a block: its classes (`class_names` with the prefix),
+    /// then the attributes of [`Renderer::block_attributes`]. A `class` among those is added
+    /// to the classes, and unsafe ones are left out.
+    fn attributes(
+        &self,
+        block_type: &str,
+        base: &BaseBlock,
+        class_names: &[&str],
+        cx: &RenderContext<'_>,
+    ) -> String {
+        let mut classes: Vec<String> = class_names
+            .iter()
+            .filter(|name| !name.is_empty())
+            .map(|name| self.options.class_name(name))
+            .collect();
+        let mut attributes = String::new();
 
-            Block::Callout(callout_block) => {
-                let mut inner_html = String::new();
-                for rich_text in &callout_block.callout.rich_text {
-                    inner_html.push_str(&rich_text.to_html())
-                }
-                html.push(format!("<div class='notion-callout'>{}</div>", inner_html));
+        for (name, value) in cx.renderer.block_attributes(block_type, base, cx) {
+            if name.eq_ignore_ascii_case("class") {
+                classes.push(value);
+            } else if is_safe_attribute_name(&name) {
+                attributes.push_str(&format!(" {}='{}'", name, escape_html(&value)));
+            } else {
+                println!("The {:?} attribute is not allowed!", name);
             }
+        }
 
-            Block::ChildDatabase(_) => {
-                println!("ChildDatabase is unsupported!");
-            }
+        format!(
+            "{}{}",
+            self.options.raw_class_attribute(&classes),
+            attributes
+        )
+    }
+}
 
-            Block::ChildPage(_) => {
-                println!("ChildPage is unsupported!");
-            }
+#[async_trait]
+impl Renderer for HtmlRenderer {
+    fn base(&self) -> &dyn Renderer {
+        self
+    }
 
-            Block::Code(code_block) => {
-                let mut code_text = String::new();
-                for rich_text in &code_block.code.rich_text {
-                    code_text.push_str(&rich_text.to_plaintext());
-                }
-                html.push(format!(
-                    "<div class='notion-code'><pre class='{}'><code class='language-{}'>{}</code></pre></div>",
-                    code_block.code.language.to_class_name(),
-                    code_block.code.language.to_class_name(),
-                    code_text
-                ));
-            }
+    /// Consecutive list items are wrapped in their list.
+    async fn blocks(&self, blocks: &[Block], cx: &RenderContext<'_>) -> Result<String, Error> {
+        let html = render_each(blocks, cx).await?;
+        Ok(wrap_list_items(blocks, html, &self.options))
+    }
 
-            Block::Column(column_block) => {
-                html.push(String::from("<div class='notion-column'>"));
-                let child_html =
-                    convert_page_to_html(notion_api_key, &column_block.base.id).await?;
-                html.push(child_html);
-                html.push(String::from("</div>"));
-            }
+    async fn bookmark(
+        &self,
+        block: &BookmarkBlock,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        let metadata = cx
+            .link_metadata
+            .get(&block.bookmark.url)
+            .cloned()
+            .unwrap_or_default();
+        Ok(element(
+            &self.options.element("bookmark", "figure"),
+            &self.attributes("bookmark", &block.base, &["bookmark"], cx),
+            &render_link_card(
+                &block.bookmark.url,
+                &block.bookmark.caption,
+                &metadata,
+                "bookmark",
+                &self.options,
+                cx,
+            ),
+        ))
+    }
 
-            Block::ColumnList(colmn_list_block) => {
-                html.push(String::from("<div class='notion-column-list'>"));
-                let child_html =
-                    convert_page_to_html(notion_api_key, &colmn_list_block.base.id).await?;
-                html.push(child_html);
-                html.push(String::from("</div>"));
-            }
+    async fn breadcrumb(
+        &self,
+        _block: &BreadcrumbBlock,
+        _cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        println!("Breadcrumb is unsupported!");
+        Ok(String::new())
+    }
 
-            Block::Divider(_) => {
-                html.push(format!("<hr class='notion-divider' />"));
-            }
+    async fn bulleted_list_item(
+        &self,
+        block: &BulletedListItemBlock,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        Ok(element(
+            "li",
+            &self.attributes(
+                "bulleted_list_item",
+                &block.base,
+                &[
+                    "bulleted-list-item",
+                    color_name(block.bulleted_list_item.color),
+                ],
+                cx,
+            ),
+            &cx.renderer
+                .rich_text(&block.bulleted_list_item.rich_text, cx),
+        ))
+    }
 
-            Block::Embed(_) => {
-                println!("Embed is unsupported!");
-            }
+    async fn callout(&self, block: &CalloutBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        Ok(element(
+            &self.options.element("callout", "div"),
+            &self.attributes(
+                "callout",
+                &block.base,
+                &["callout", color_name(block.callout.color)],
+                cx,
+            ),
+            &cx.renderer.rich_text(&block.callout.rich_text, cx),
+        ))
+    }
 
-            Block::Equation(_) => {
-                println!("Equation is unsupported!");
-            }
+    async fn child_database(
+        &self,
+        _block: &ChildDatabaseBlock,
+        _cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        println!("ChildDatabase is unsupported!");
+        Ok(String::new())
+    }
 
-            Block::File(_) => {
-                println!("File is unsupported!");
-            }
+    async fn child_page(
+        &self,
+        _block: &ChildPageBlock,
+        _cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        println!("ChildPage is unsupported!");
+        Ok(String::new())
+    }
 
-            Block::Heading1(heading_1) => {
-                html.push(format!(
-                    "<h1 class='notion-heading-1'>{}</h1>",
-                    heading_1.heading_1.to_html()
-                ));
-            }
+    async fn code(&self, block: &CodeBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        let code_text: String = block
+            .code
+            .rich_text
+            .iter()
+            .map(|rich_text| rich_text.to_plaintext())
+            .collect();
+        let language = block.code.language.to_class_name();
+        // `language-*` is what syntax highlighters look for, so it stays without classes too.
+        Ok(element(
+            &self.options.element("code", "div"),
+            &self.attributes("code", &block.base, &["code"], cx),
+            &format!(
+                "<pre{}><code class='language-{}'>{}</code></pre>",
+                self.options.raw_class_attribute(&[language.to_string()]),
+                language,
+                escape_html(&code_text)
+            ),
+        ))
+    }
 
-            Block::Heading2(heading_2) => {
-                html.push(format!(
-                    "<h2 class='notion-heading-2'>{}</h2>",
-                    heading_2.heading_2.to_html()
-                ));
-            }
+    async fn column(&self, block: &ColumnBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        Ok(element(
+            &self.options.element("column", "div"),
+            &self.attributes("column", &block.base, &["column"], cx),
+            &cx.render_children(&block.base.id).await?,
+        ))
+    }
 
-            Block::Heading3(heading_3) => {
-                html.push(format!(
-                    "<h3 class='notion-heading-3'>{}</h3>",
-                    heading_3.heading_3.to_html()
-                ));
-            }
+    async fn column_list(
+        &self,
+        block: &ColumnListBlock,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        Ok(element(
+            &self.options.element("column_list", "div"),
+            &self.attributes("column_list", &block.base, &["column-list"], cx),
+            &cx.render_children(&block.base.id).await?,
+        ))
+    }
 
-            Block::Image(image_block) => match &image_block.image {
-                FileObject::File { file } => {
-                    html.push(format!(
-                        "<img src='{}' alt='' class='notion-image' />",
-                        file.url
-                    ));
-                }
-
-                FileObject::External { external } => {
-                    html.push(format!(
-                        "<img src='{}' alt='' class='notion-image' />",
-                        external.url
-                    ));
-                }
-            },
-
-            Block::LinkPreview(_) => {
-                println!("LinkPreview is unsupported!");
-            }
+    async fn divider(&self, block: &DividerBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        Ok(format!(
+            "<hr{} />",
+            self.attributes("divider", &block.base, &["divider"], cx)
+        ))
+    }
 
-            Block::NumberedListItem(numberted_list_item_block) => {
-                html.push(format!(
-                    "<li class='notion-numbered-list-item'>{}</li>",
-                    numberted_list_item_block.numbered_list_item.to_html()
-                ));
-            }
+    async fn embed(&self, block: &EmbedBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        Ok(element(
+            &self.options.element("embed", "figure"),
+            &self.attributes("embed", &block.base, &["embed"], cx),
+            &format!(
+                "{}{}",
+                render_embed(&block.embed.url, &self.options),
+                render_caption(&block.embed.caption, &self.options, cx)
+            ),
+        ))
+    }
 
-            Block::Mention(_) => {
-                println!("Mention is unsupported!");
-            }
+    async fn equation(
+        &self,
+        _block: &EquationBlock,
+        _cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        println!("Equation is unsupported!");
+        Ok(String::new())
+    }
 
-            Block::Paragraph(paragraph_block) => {
-                html.push(format!(
-                    "<p class='notion-paragraph'>{}</p>",
-                    paragraph_block.paragraph.to_html()
-                ));
-            }
+    async fn file(&self, block: &FileBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        let size = cx.context.file_size(block.file.file.url());
+        Ok(element(
+            &self.options.element("file", "figure"),
+            &self.attributes("file", &block.base, &["file"], cx),
+            &render_file(&block.file, size, &self.options, cx),
+        ))
+    }
 
-            Block::Pdf(_) => {
-                println!("Pdf is unsupported!");
-            }
+    async fn heading_1(
+        &self,
+        block: &Heading1Block,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        let heading_html = element(
+            &self.options.heading_element("heading_1", 1),
+            &self.attributes(
+                "heading_1",
+                &block.base,
+                &["heading-1", color_name(block.heading_1.color)],
+                cx,
+            ),
+            &cx.renderer.rich_text(&block.heading_1.rich_text, cx),
+        );
+        render_heading(
+            heading_html,
+            &block.heading_1,
+            &block.base,
+            &self.options,
+            cx,
+        )
+        .await
+    }
 
-            Block::Quote(quote_block) => {
-                html.push(format!(
-                    "<blockquote class='notion-quote'>{}</blockquote>",
-                    quote_block.quote.to_html()
-                ));
-            }
+    async fn heading_2(
+        &self,
+        block: &Heading2Block,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        let heading_html = element(
+            &self.options.heading_element("heading_2", 2),
+            &self.attributes(
+                "heading_2",
+                &block.base,
+                &["heading-2", color_name(block.heading_2.color)],
+                cx,
+            ),
+            &cx.renderer.rich_text(&block.heading_2.rich_text, cx),
+        );
+        render_heading(
+            heading_html,
+            &block.heading_2,
+            &block.base,
+            &self.options,
+            cx,
+        )
+        .await
+    }
 
-            Block::SyncedBlock(synced_block) => {
-                let child_html =
-                    convert_page_to_html(notion_api_key, &synced_block.base.id).await?;
-                html.push(child_html);
-            }
+    async fn heading_3(
+        &self,
+        block: &Heading3Block,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        let heading_html = element(
+            &self.options.heading_element("heading_3", 3),
+            &self.attributes(
+                "heading_3",
+                &block.base,
+                &["heading-3", color_name(block.heading_3.color)],
+                cx,
+            ),
+            &cx.renderer.rich_text(&block.heading_3.rich_text, cx),
+        );
+        render_heading(
+            heading_html,
+            &block.heading_3,
+            &block.base,
+            &self.options,
+            cx,
+        )
+        .await
+    }
 
-            Block::Table(table_block) => {
-                html.push(String::from("<table class='notion-table'>"));
-                let child_html = convert_page_to_html(notion_api_key, &table_block.base.id).await?;
-                html.push(child_html);
-                html.push(String::from("</tbody>"));
-                html.push(String::from("</table>"));
-            }
+    async fn image(&self, block: &ImageBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        let responsive_image = cx.context.responsive_image(block.image.file.url());
+        Ok(element(
+            &self.options.element("image", "figure"),
+            &self.attributes("image", &block.base, &["image"], cx),
+            &render_image(&block.image, responsive_image.as_ref(), &self.options, cx),
+        ))
+    }
 
-            Block::TableOfContents(_) => {
-                println!("TableOfContents is unsupported!");
-            }
+    async fn link_preview(
+        &self,
+        block: &LinkPreviewBlock,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        let metadata = link_preview_metadata(&block.link_preview.url, cx.link_metadata);
+        Ok(element(
+            &self.options.element("link_preview", "figure"),
+            &self.attributes("link_preview", &block.base, &["link-preview"], cx),
+            &render_link_card(
+                &block.link_preview.url,
+                &[],
+                &metadata,
+                "link-preview",
+                &self.options,
+                cx,
+            ),
+        ))
+    }
 
-            Block::TableRow(table_row_block) => {
-                let mut is_head = false;
-                if html.last() == None {
-                    is_head = true;
-                    html.push(String::from("<thead>"));
-                }
-                html.push(String::from("<tr>"));
-                for cell in &table_row_block.table_row.cells {
-                    for rich_text in cell {
-                        if is_head {
-                            html.push(String::from("<th>"));
-                        } else {
-                            html.push(String::from("<td>"));
-                        }
-                        html.push(format!("{}", rich_text.to_html()));
-                        if is_head {
-                            html.push(String::from("</th>"));
-                        } else {
-                            html.push(String::from("</td>"));
-                        }
-                    }
-                }
-                html.push(String::from("</tr>"));
-                if is_head {
-                    html.push(String::from("</thead>"));
-                    html.push(String::from("<tbody>"));
-                }
-            }
+    async fn numbered_list_item(
+        &self,
+        block: &NumberedListItemBlock,
+        _number: u32,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        Ok(element(
+            "li",
+            &self.attributes(
+                "numbered_list_item",
+                &block.base,
+                &[
+                    "numbered-list-item",
+                    color_name(block.numbered_list_item.color),
+                ],
+                cx,
+            ),
+            &cx.renderer
+                .rich_text(&block.numbered_list_item.rich_text, cx),
+        ))
+    }
 
-            Block::Template(_) => {
-                println!("Template is unsupported!");
-            }
+    async fn mention(
+        &self,
+        _block: &MentionBlock,
+        _cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        println!("Mention is unsupported!");
+        Ok(String::new())
+    }
+
+    async fn paragraph(
+        &self,
+        block: &ParagraphBlock,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        Ok(element(
+            &self.options.element("paragraph", "p"),
+            &self.attributes(
+                "paragraph",
+                &block.base,
+                &["paragraph", color_name(block.paragraph.color)],
+                cx,
+            ),
+            &cx.renderer.rich_text(&block.paragraph.rich_text, cx),
+        ))
+    }
+
+    async fn pdf(&self, block: &PdfBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        Ok(element(
+            &self.options.element("pdf", "figure"),
+            &self.attributes("pdf", &block.base, &["pdf"], cx),
+            &format!(
+                "{}{}",
+                render_pdf(block.pdf.file.url(), &self.options),
+                render_caption(&block.pdf.caption, &self.options, cx)
+            ),
+        ))
+    }
+
+    async fn quote(&self, block: &QuoteBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        Ok(element(
+            &self.options.element("quote", "blockquote"),
+            &self.attributes(
+                "quote",
+                &block.base,
+                &["quote", color_name(block.quote.color)],
+                cx,
+            ),
+            &cx.renderer.rich_text(&block.quote.rich_text, cx),
+        ))
+    }
+
+    async fn synced_block(
+        &self,
+        block: &SyncedBlock,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        cx.render_children(&block.base.id).await
+    }
+
+    async fn table(&self, block: &TableBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        let rows = cx.child_blocks(&block.base.id).await?;
+        Ok(render_table(
+            &block.table,
+            &rows,
+            &self.attributes("table", &block.base, &["table"], cx),
+            |cell| cx.renderer.rich_text(cell, cx),
+        ))
+    }
 
-            Block::ToDo(todo_block) => {
-                html.push(format!(
-                    "<div class='notion-todo'>{}</div>",
-                    todo_block.to_do.to_html()
+    async fn table_of_contents(
+        &self,
+        _block: &TableOfContentsBlock,
+        _cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        println!("TableOfContents is unsupported!");
+        Ok(String::new())
+    }
+
+    async fn table_row(
+        &self,
+        _block: &TableRowBlock,
+        _cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        // Table rows are rendered together with their parent table.
+        Ok(String::new())
+    }
+
+    async fn template(
+        &self,
+        _block: &TemplateBlock,
+        _cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        println!("Template is unsupported!");
+        Ok(String::new())
+    }
+
+    async fn to_do(&self, block: &ToDoBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        let (checked_class, checked_attribute) = if block.to_do.checked {
+            ("todo-checked", " checked")
+        } else {
+            ("", "")
+        };
+        Ok(element(
+            &self.options.element("to_do", "div"),
+            &self.attributes(
+                "to_do",
+                &block.base,
+                &["todo", checked_class, color_name(block.to_do.color)],
+                cx,
+            ),
+            &format!(
+                "<input type='checkbox'{} disabled{} /><span{}>{}</span>",
+                self.options.class_attribute(&["todo-checkbox"]),
+                checked_attribute,
+                self.options.class_attribute(&["todo-text"]),
+                cx.renderer.rich_text(&block.to_do.rich_text, cx)
+            ),
+        ))
+    }
+
+    async fn toggle(&self, block: &ToggleBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        Ok(element(
+            "details",
+            &self.attributes(
+                "toggle",
+                &block.base,
+                &["toggle-block", color_name(block.toggle.color)],
+                cx,
+            ),
+            &format!(
+                "<summary{}>{}</summary>{}",
+                self.options.class_attribute(&["toggle-block-header"]),
+                cx.renderer.rich_text(&block.toggle.rich_text, cx),
+                cx.render_children(&block.base.id).await?
+            ),
+        ))
+    }
+
+    async fn unsupported(
+        &self,
+        _block: &UnsupportedBlock,
+        _cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        println!("This kind of Block is unsupported!");
+        Ok(String::new())
+    }
+
+    async fn video(&self, block: &VideoBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        Ok(element(
+            &self.options.element("video", "figure"),
+            &self.attributes("video", &block.base, &["video"], cx),
+            &format!(
+                "{}{}",
+                render_video(&block.video, &self.options),
+                render_caption(&block.video.caption, &self.options, cx)
+            ),
+        ))
+    }
+
+    /// The `id` that block links point at, `data-block-id` and the `block_attributes`
+    /// of the options. An `id` among those replaces the block's own.
+    fn block_attributes(
+        &self,
+        block_type: &str,
+        base: &BaseBlock,
+        _cx: &RenderContext<'_>,
+    ) -> Vec<(String, String)> {
+        let mut block_attributes: Vec<(String, String)> = self
+            .options
+            .block_attributes
+            .get(block_type)
+            .into_iter()
+            .flatten()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        // Attributes come in a map, so they are sorted to keep the output stable.
+        block_attributes.sort();
+
+        let mut attributes = Vec::new();
+        let has_id = block_attributes
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("id"));
+        if self.options.block_ids && !has_id {
+            attributes.push((String::from("id"), block_anchor(&base.id)));
+        }
+        if self.options.data_block_id {
+            attributes.push((String::from("data-block-id"), base.id.clone()));
+        }
+        attributes.extend(block_attributes);
+        attributes
+    }
+
+    fn rich_text(&self, rich_text: &[RichTextElement], cx: &RenderContext<'_>) -> String {
+        rich_text
+            .iter()
+            .map(|element| {
+                cx.render_element(element)
+                    .unwrap_or_else(|| element.to_html(&self.options))
+            })
+            .collect()
+    }
+
+    fn text_element(&self, element: &RichTextElement, _cx: &RenderContext<'_>) -> Option<String> {
+        Some(element.to_html(&self.options))
+    }
+
+    fn mention_element(
+        &self,
+        element: &RichTextElement,
+        _cx: &RenderContext<'_>,
+    ) -> Option<String> {
+        Some(element.to_html(&self.options))
+    }
+
+    fn equation_element(
+        &self,
+        element: &RichTextElement,
+        _cx: &RenderContext<'_>,
+    ) -> Option<String> {
+        Some(element.to_html(&self.options))
+    }
+}
+
+/// Wraps `content` in the element `name` with `attributes`, e.g. `<p class='notion-paragraph'>`.
+fn element(name: &str, attributes: &str, content: &str) -> String {
+    format!("<{}{}>{}</{}>", name, attributes, content, name)
+}
+
+/// Toggleable headings are rendered as `<details>` with the heading as the summary
+/// and their children inside.
+async fn render_heading(
+    heading_html: String,
+    heading: &RichText,
+    base: &BaseBlock,
+    options: &HtmlOptions,
+    cx: &RenderContext<'_>,
+) -> Result<String, Error> {
+    if heading.is_toggleable != Some(true) {
+        return Ok(heading_html);
+    }
+
+    let children_html = if base.has_children {
+        cx.render_children(&base.id).await?
+    } else {
+        String::new()
+    };
+
+    Ok(format!(
+        "<details{}><summary{}>{}</summary>{}</details>",
+        options.class_attribute(&["toggle-heading"]),
+        options.class_attribute(&["toggle-heading-summary"]),
+        heading_html,
+        children_html
+    ))
+}
+
+/// Renders the inside of a bookmark or link preview card. Inner elements are classed
+/// `{class_name}-title`, `{class_name}-description` and so on.
+fn render_link_card(
+    url: &str,
+    caption: &[RichTextElement],
+    metadata: &LinkMetadata,
+    class_name: &str,
+    options: &HtmlOptions,
+    cx: &RenderContext<'_>,
+) -> String {
+    let class = |part: &str| options.class_attribute(&[&format!("{}-{}", class_name, part)]);
+    let title = metadata.title.as_deref().unwrap_or(url);
+
+    let mut content = format!("<div{}>{}</div>", class("title"), escape_html(title));
+    if let Some(description) = &metadata.description {
+        content.push_str(&format!(
+            "<div{}>{}</div>",
+            class("description"),
+            escape_html(description)
+        ));
+    }
+    content.push_str(&format!("<div{}>", class("link")));
+    if let Some(favicon) = metadata.favicon.as_deref().and_then(sanitize_url) {
+        content.push_str(&format!(
+            "<img src='{}' alt=''{} />",
+            favicon,
+            class("favicon")
+        ));
+    }
+    content.push_str(&format!(
+        "<span{}>{}</span></div>",
+        class("site"),
+        escape_html(metadata.site_name.as_deref().unwrap_or(url))
+    ));
+
+    let image = match metadata.image.as_deref().and_then(sanitize_url) {
+        Some(image) => format!(
+            "<div{}><img src='{}' alt='' /></div>",
+            class("image"),
+            image
+        ),
+        None => String::new(),
+    };
+
+    format!(
+        "<a{}{}><div{}>{}</div>{}</a>{}",
+        link_attributes(url, options.link_target.as_deref()).unwrap_or_default(),
+        class("card"),
+        class("content"),
+        content,
+        image,
+        render_caption(caption, options, cx)
+    )
+}
+
+fn render_caption(
+    caption: &[RichTextElement],
+    options: &HtmlOptions,
+    cx: &RenderContext<'_>,
+) -> String {
+    if caption.is_empty() {
+        return String::new();
+    }
+    format!(
+        "<figcaption{}>{}</figcaption>",
+        options.class_attribute(&["caption"]),
+        cx.renderer.rich_text(caption, cx)
+    )
+}
+
+/// Renders the inside of an image figure, using the plain caption as alt text.
+/// Processed images get `width`/`height` against layout shift, a `srcset` of their
+/// resized copies (WebP ones in a `<picture>` source) and a placeholder.
+fn render_image(
+    image: &ImageField,
+    responsive_image: Option<&ResponsiveImage>,
+    options: &HtmlOptions,
+    cx: &RenderContext<'_>,
+) -> String {
+    let alt: String = image
+        .caption
+        .iter()
+        .map(|rich_text| rich_text.to_plaintext())
+        .collect();
+
+    let mut attributes = String::new();
+    if let Some(responsive_image) = responsive_image {
+        attributes.push_str(&format!(
+            " width='{}' height='{}'",
+            responsive_image.width, responsive_image.height
+        ));
+        if !responsive_image.sources.is_empty() {
+            attributes.push_str(&format!(
+                " srcset='{}' sizes='{}'",
+                escape_html(&srcset(&responsive_image.sources)),
+                escape_html(&responsive_image.sizes)
+            ));
+        }
+        match &responsive_image.placeholder {
+            Some(Placeholder::Blurhash(blurhash)) => {
+                attributes.push_str(&format!(" data-blurhash='{}'", escape_html(blurhash)));
+            }
+            Some(Placeholder::Lqip(data_uri)) => {
+                attributes.push_str(&format!(
+                    " style='background-image: url(\"{}\"); background-size: cover;'",
+                    escape_html(data_uri)
                 ));
             }
+            None => {}
+        }
+    }
+    if options.lazy_load_images {
+        attributes.push_str(" loading='lazy'");
+    }
 
-            Block::Toggle(toggle_block) => {
-                html.push(String::from("<details class='notion-toggle-block'>"));
-                for rich_text in &toggle_block.toggle.rich_text {
-                    html.push(String::from("<summary class='notion-toggle-block-header'>"));
-                    html.push(rich_text.to_html());
-                    html.push(String::from("</summary>"));
-                }
-                let child_html =
-                    convert_page_to_html(notion_api_key, &toggle_block.base.id).await?;
-                html.push(child_html);
-                html.push(String::from("</details>"));
-            }
+    let mut img = format!(
+        "<img src='{}' alt='{}'{}{} />",
+        sanitize_url(image.file.url()).unwrap_or_default(),
+        escape_html(alt.trim()),
+        attributes,
+        options.class_attribute(&["image-content"]),
+    );
 
-            Block::Unsupported(_) => {
-                println!("This kind of Block is unsupported!");
-            }
+    if let Some(responsive_image) = responsive_image {
+        if !responsive_image.webp_sources.is_empty() {
+            img = format!(
+                "<picture><source type='image/webp' srcset='{}' sizes='{}' />{}</picture>",
+                escape_html(&srcset(&responsive_image.webp_sources)),
+                escape_html(&responsive_image.sizes),
+                img
+            );
+        }
+    }
+
+    format!("{}{}", img, render_caption(&image.caption, options, cx))
+}
+
+fn render_embed(url: &str, options: &HtmlOptions) -> String {
+    let is_allowed = is_allowed_host(url, &options.embed_allowed_hosts);
+    match detect_embed(url) {
+        EmbedKind::Player { provider, src } if is_allowed => render_player(provider, &src, options),
+        EmbedKind::VideoFile if is_allowed => render_video_element(url, options),
+        EmbedKind::Unknown if is_allowed => format!(
+            "<iframe src='{}'{} loading='lazy'></iframe>",
+            sanitize_url(url).unwrap_or_default(),
+            options.class_attribute(&["embed-frame"])
+        ),
+        _ => render_link(url, options),
+    }
+}
 
-            _ => {
-                println!("Not Define!!!");
+fn render_video(video: &VideoField, options: &HtmlOptions) -> String {
+    match &video.file {
+        FileObject::File { file } => render_video_element(&file.url, options),
+        FileObject::External { external } => match detect_embed(&external.url) {
+            EmbedKind::Player { provider, src }
+                if is_allowed_host(&external.url, &options.embed_allowed_hosts) =>
+            {
+                render_player(provider, &src, options)
             }
+            EmbedKind::VideoFile => render_video_element(&external.url, options),
+            _ => render_link(&external.url, options),
+        },
+    }
+}
+
+fn render_player(provider: &str, src: &str, options: &HtmlOptions) -> String {
+    format!(
+        "<iframe src='{}'{} allow='fullscreen; picture-in-picture' allowfullscreen loading='lazy'></iframe>",
+        sanitize_url(src).unwrap_or_default(),
+        options.class_attribute(&["embed-player", &format!("embed-{}", provider)])
+    )
+}
+
+fn render_video_element(url: &str, options: &HtmlOptions) -> String {
+    let src = sanitize_url(url).unwrap_or_default();
+    format!(
+        "<video src='{}'{} controls preload='metadata'><a href='{}'>{}</a></video>",
+        src,
+        options.class_attribute(&["video-player"]),
+        src,
+        escape_html(url)
+    )
+}
+
+fn render_pdf(url: &str, options: &HtmlOptions) -> String {
+    let src = sanitize_url(url).unwrap_or_default();
+    format!(
+        "<object data='{}' type='application/pdf'{}><a href='{}'{} download>Download PDF</a></object>",
+        src,
+        options.class_attribute(&["pdf-viewer"]),
+        src,
+        options.class_attribute(&["pdf-download"])
+    )
+}
+
+fn render_link(url: &str, options: &HtmlOptions) -> String {
+    match link_attributes(url, options.link_target.as_deref()) {
+        Some(link_attributes) => format!(
+            "<a{}{}>{}</a>",
+            link_attributes,
+            options.class_attribute(&["embed-link"]),
+            escape_html(url)
+        ),
+        None => escape_html(url),
+    }
+}
+
+/// Renders the inside of a file figure.
+fn render_file(
+    file: &FileField,
+    size: Option<u64>,
+    options: &HtmlOptions,
+    cx: &RenderContext<'_>,
+) -> String {
+    let url = file.file.url();
+
+    let details: Vec<String> = file
+        .extension()
+        .into_iter()
+        .chain(size.map(format_file_size))
+        .collect();
+    let details_html = if details.is_empty() {
+        String::new()
+    } else {
+        format!(
+            "<span{}>{}</span>",
+            options.class_attribute(&["file-details"]),
+            details.join(" · ")
+        )
+    };
+
+    format!(
+        "<a href='{}'{} download>{}</a>{}{}",
+        sanitize_url(url).unwrap_or_default(),
+        options.class_attribute(&["file-link"]),
+        escape_html(&file.file_name()),
+        details_html,
+        render_caption(&file.caption, options, cx)
+    )
+}
+
+/// Also used by the Markdown converter for flavors without pipe tables.
+/// `attributes` go on the `<table>`, and cells are rendered with `render_cell`.
+pub fn render_table(
+    table: &TableField,
+    rows: &[Block],
+    attributes: &str,
+    render_cell: impl Fn(&[RichTextElement]) -> String,
+) -> String {
+    let mut html = format!("<table{}>", attributes);
+
+    html.push_str("<colgroup>");
+    for _ in 0..table.table_width {
+        html.push_str("<col />");
+    }
+    html.push_str("</colgroup>");
+
+    let mut rows = rows.iter().filter_map(|block| match block {
+        Block::TableRow(table_row_block) => Some(&table_row_block.table_row),
+        _ => None,
+    });
+
+    if table.has_column_header {
+        if let Some(header_row) = rows.next() {
+            html.push_str("<thead>");
+            html.push_str(&render_table_row(
+                header_row,
+                table.has_row_header,
+                true,
+                &render_cell,
+            ));
+            html.push_str("</thead>");
         }
     }
 
-    Ok(wrap_list_items(html))
+    html.push_str("<tbody>");
+    for row in rows {
+        html.push_str(&render_table_row(
+            row,
+            table.has_row_header,
+            false,
+            &render_cell,
+        ));
+    }
+    html.push_str("</tbody>");
+
+    html.push_str("</table>");
+    html
 }
 
-fn wrap_list_items(html_lines: Vec<String>) -> String {
-    let mut result = String::new();
-    let mut in_bulleted_list = false;
-    let mut in_numbered_list = false;
-
-    for line in &html_lines {
-        if line.contains("class='notion-bulleted-list-item'") {
-            if !in_bulleted_list {
-                if in_numbered_list {
-                    result.push_str("</ol>");
-                    in_numbered_list = false;
-                }
-                result.push_str("<ul class='notion-bulleted-list'>");
-                in_bulleted_list = true;
-            }
-            result.push_str(line);
-        } else if line.contains("class='notion-numbered-list-item'") {
-            if !in_numbered_list {
-                if in_bulleted_list {
-                    result.push_str("</ul>");
-                    in_bulleted_list = false;
-                }
-                result.push_str("<ol class='notion-numbered-list'>");
-                in_numbered_list = true;
-            }
-            result.push_str(line);
+fn render_table_row(
+    row: &TableRowField,
+    has_row_header: bool,
+    is_column_header: bool,
+    render_cell: &impl Fn(&[RichTextElement]) -> String,
+) -> String {
+    let mut html = String::from("<tr>");
+
+    for (index, cell) in row.cells.iter().enumerate() {
+        let cell_html = render_cell(cell);
+        if is_column_header {
+            html.push_str(&format!("<th scope='col'>{}</th>", cell_html));
+        } else if has_row_header && index == 0 {
+            html.push_str(&format!("<th scope='row'>{}</th>", cell_html));
         } else {
-            if in_bulleted_list {
-                result.push_str("</ul>");
-                in_bulleted_list = false;
+            html.push_str(&format!("<td>{}</td>", cell_html));
+        }
+    }
+
+    html.push_str("</tr>");
+    html
+}
+
+/// Block colors become `notion-<color>` classes, e.g. `notion-blue-background`.
+fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::Default => "",
+        color => color.class_name(),
+    }
+}
+
+/// Joins the rendered `blocks`, wrapping runs of list items in `<ul>` or `<ol>`.
+/// Blocks that rendered nothing do not break up a list.
+fn wrap_list_items(blocks: &[Block], html: Vec<String>, options: &HtmlOptions) -> String {
+    let mut result = String::new();
+    let mut open_list: Option<&str> = None;
+
+    for (block, html) in blocks.iter().zip(html) {
+        if html.is_empty() {
+            continue;
+        }
+
+        let list = match block {
+            Block::BulletedListItem(_) => Some("ul"),
+            Block::NumberedListItem(_) => Some("ol"),
+            _ => None,
+        };
+        if list != open_list {
+            if let Some(open_list) = open_list {
+                result.push_str(&format!("</{}>", open_list));
             }
-            if in_numbered_list {
-                result.push_str("</ol>");
-                in_numbered_list = false;
+            match list {
+                Some("ul") => result.push_str(&format!(
+                    "<ul{}>",
+                    options.class_attribute(&["bulleted-list"])
+                )),
+                Some(_) => result.push_str(&format!(
+                    "<ol{}>",
+                    options.class_attribute(&["numbered-list"])
+                )),
+                None => {}
             }
-            result.push_str(line);
+            open_list = list;
         }
+        result.push_str(&html);
     }
 
-    if in_bulleted_list {
-        result.push_str("</ul>");
-    }
-    if in_numbered_list {
-        result.push_str("</ol>");
+    if let Some(open_list) = open_list {
+        result.push_str(&format!("</{}>", open_list));
     }
 
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{
+        block, block_json, context, mention, styled_text, table_row, text, text_row,
+    };
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    fn plain_cell(cell: &[RichTextElement]) -> String {
+        escape_html(
+            &cell
+                .iter()
+                .map(|element| element.to_plaintext())
+                .collect::<String>(),
+        )
+    }
+
+    #[test]
+    fn tables_follow_their_header_flags() {
+        let rows = [text_row(&["", "Q1", "Q2"]), text_row(&["Sales", "1", "2"])];
+        let table = |has_column_header, has_row_header| TableField {
+            table_width: 3,
+            has_column_header,
+            has_row_header,
+        };
+
+        assert_eq!(
+            render_table(&table(true, true), &rows, "", plain_cell),
+            "<table><colgroup><col /><col /><col /></colgroup>\
+             <thead><tr><th scope='col'></th><th scope='col'>Q1</th><th scope='col'>Q2</th></tr></thead>\
+             <tbody><tr><th scope='row'>Sales</th><td>1</td><td>2</td></tr></tbody></table>"
+        );
+        assert_eq!(
+            render_table(&table(false, false), &rows, " class='t'", plain_cell),
+            "<table class='t'><colgroup><col /><col /><col /></colgroup>\
+             <tbody><tr><td></td><td>Q1</td><td>Q2</td></tr>\
+             <tr><td>Sales</td><td>1</td><td>2</td></tr></tbody></table>"
+        );
+    }
+
+    #[test]
+    fn cells_are_rendered_whole() {
+        let row = table_row(vec![json!([text("a & "), text("b")]), json!([])]);
+        let table = TableField {
+            table_width: 2,
+            has_column_header: false,
+            has_row_header: false,
+        };
+
+        assert_eq!(
+            render_table(&table, &[row], "", plain_cell),
+            "<table><colgroup><col /><col /></colgroup>\
+             <tbody><tr><td>a &amp; b</td><td></td></tr></tbody></table>"
+        );
+    }
+
+    #[tokio::test]
+    async fn cells_are_resolved_like_other_rich_text() {
+        let context = ConvertContext::from_event(&json!({
+            "NOTION_API_KEY": "key",
+            "link_options": { "link_map": { "0123456789abcdef0123456789abcdef": "/about" } }
+        }))
+        .await
+        .unwrap();
+        let renderer = HtmlRenderer::new(HtmlOptions::default());
+        let link_metadata = HashMap::new();
+        let cx = RenderContext {
+            context: &context,
+            renderer: &renderer,
+            link_metadata: &link_metadata,
+        };
+
+        let mut link = text("About");
+        link["href"] = json!("https://www.notion.so/About-0123456789abcdef0123456789abcdef");
+        let url = "https://github.com/a/b/issues/2";
+        let mention = mention(
+            json!({ "type": "link_preview", "link_preview": { "url": url } }),
+            url,
+            Some(url),
+        );
+        let mut rows = vec![table_row(vec![json!([link]), json!([mention])])];
+        context.resolve_blocks(&mut rows).await;
+
+        let table = TableField {
+            table_width: 2,
+            has_column_header: false,
+            has_row_header: false,
+        };
+        let html = render_table(&table, &rows, "", |cell| renderer.rich_text(cell, &cx));
+        assert!(html.contains("href='/about'"), "{}", html);
+        assert!(html.contains(">a/b#2<"), "{}", html);
+        assert!(!html.contains("notion.so"), "{}", html);
+    }
+
+    #[test]
+    fn embeds_fall_back_to_links_outside_the_allowlist() {
+        let options = HtmlOptions::default();
+        assert_eq!(
+            render_embed("https://youtu.be/abc", &options),
+            "<iframe src='https://www.youtube-nocookie.com/embed/abc' class='notion-embed-player notion-embed-youtube' allow='fullscreen; picture-in-picture' allowfullscreen loading='lazy'></iframe>"
+        );
+        assert_eq!(
+            render_embed("https://codepen.io/a/pen/b", &options),
+            "<a href='https://codepen.io/a/pen/b' rel='noopener noreferrer' class='notion-embed-link'>https://codepen.io/a/pen/b</a>"
+        );
+        assert_eq!(
+            render_pdf("https://example.com/a.pdf", &options),
+            "<object data='https://example.com/a.pdf' type='application/pdf' class='notion-pdf-viewer'><a href='https://example.com/a.pdf' class='notion-pdf-download' download>Download PDF</a></object>"
+        );
+
+        let options = HtmlOptions {
+            embed_allowed_hosts: vec![String::from("codepen.io")],
+            ..HtmlOptions::default()
+        };
+        assert!(render_embed("https://codepen.io/a/pen/b", &options)
+            .starts_with("<iframe src='https://codepen.io/a/pen/b'"));
+        assert!(render_embed("https://youtu.be/abc", &options).starts_with("<a href="));
+        assert!(render_embed("https://example.com/clip.webm", &options).starts_with("<a href="));
+        assert!(render_embed("https://codepen.io/clip.webm", &options)
+            .starts_with("<video src='https://codepen.io/clip.webm'"));
+    }
+
+    #[tokio::test]
+    async fn to_dos_are_disabled_checkboxes() {
+        let context = context().await;
+        let renderer = HtmlRenderer::new(HtmlOptions::default());
+        let link_metadata = HashMap::new();
+        let cx = RenderContext {
+            context: &context,
+            renderer: &renderer,
+            link_metadata: &link_metadata,
+        };
+        let to_do = |checked: bool| {
+            let block = block(
+                "to_do",
+                json!({ "rich_text": [text("Buy milk")], "checked": checked, "color": "default" }),
+            );
+            match block {
+                Block::ToDo(to_do) => to_do,
+                _ => unreachable!(),
+            }
+        };
+
+        let checked = renderer.to_do(&to_do(true), &cx).await.unwrap();
+        assert!(checked.starts_with("<div class='notion-todo notion-todo-checked"));
+        assert!(checked
+            .contains("<input type='checkbox' class='notion-todo-checkbox' disabled checked />"));
+
+        let unchecked = renderer.to_do(&to_do(false), &cx).await.unwrap();
+        assert!(!unchecked.contains("checked"));
+        assert!(
+            unchecked.contains("<input type='checkbox' class='notion-todo-checkbox' disabled />")
+        );
+        assert!(unchecked.contains("Buy milk</span>"));
+    }
+
+    #[tokio::test]
+    async fn files_are_download_links_with_details() {
+        let context = context().await;
+        let renderer = HtmlRenderer::new(HtmlOptions::default());
+        let link_metadata = HashMap::new();
+        let cx = RenderContext {
+            context: &context,
+            renderer: &renderer,
+            link_metadata: &link_metadata,
+        };
+
+        let file: FileField = serde_json::from_value(json!({
+            "caption": [text("Signed <copy>")],
+            "name": "Terms & conditions.pdf",
+            "type": "external",
+            "external": { "url": "https://example.com/terms.pdf" }
+        }))
+        .unwrap();
+        assert_eq!(
+            render_file(&file, Some(2048), &renderer.options, &cx),
+            "<a href='https://example.com/terms.pdf' class='notion-file-link' download>Terms &amp; conditions.pdf</a>\
+             <span class='notion-file-details'>PDF · 2.0 KB</span>\
+             <figcaption class='notion-caption'><span class='notion-rich-text'>Signed &lt;copy&gt;</span></figcaption>"
+        );
+
+        let file: FileField = serde_json::from_value(json!({
+            "caption": [],
+            "type": "external",
+            "external": { "url": "https://example.com/notes" }
+        }))
+        .unwrap();
+        assert_eq!(
+            render_file(&file, None, &renderer.options, &cx),
+            "<a href='https://example.com/notes' class='notion-file-link' download>notes</a>"
+        );
+    }
+
+    #[tokio::test]
+    async fn rewritten_block_links_point_at_the_block() {
+        let context = ConvertContext::from_event(&json!({
+            "NOTION_API_KEY": "key",
+            "link_options": { "link_map": { "0123456789abcdef0123456789abcdef": "/guide" } }
+        }))
+        .await
+        .unwrap();
+        let renderer = HtmlRenderer::new(HtmlOptions::default());
+        let link_metadata = HashMap::new();
+        let cx = RenderContext {
+            context: &context,
+            renderer: &renderer,
+            link_metadata: &link_metadata,
+        };
+
+        let mut heading = block_json(
+            "heading_2",
+            json!({ "rich_text": [text("Setup")], "is_toggleable": false, "color": "default" }),
+        );
+        heading["id"] = json!("fedcba98-7654-3210-fedc-ba9876543210");
+        let link = styled_text(
+            "see setup",
+            &[],
+            Some("https://www.notion.so/Guide-0123456789abcdef0123456789abcdef#fedcba9876543210fedcba9876543210"),
+        );
+        let mut blocks = vec![
+            serde_json::from_value(heading).unwrap(),
+            block(
+                "paragraph",
+                json!({ "rich_text": [link], "color": "default" }),
+            ),
+        ];
+        context.resolve_blocks(&mut blocks).await;
+        let html = renderer.blocks(&blocks, &cx).await.unwrap();
+
+        let fragment = scraper::Html::parse_fragment(&html);
+        let href = fragment
+            .select(&scraper::Selector::parse("a").unwrap())
+            .next()
+            .and_then(|link| link.value().attr("href"))
+            .unwrap();
+        let (path, anchor) = href.split_once('#').unwrap();
+        assert_eq!(path, "/guide");
+        let target = fragment
+            .select(&scraper::Selector::parse(&format!("[id='{}']", anchor)).unwrap())
+            .next()
+            .unwrap_or_else(|| panic!("nothing has the id {:?} in {}", anchor, html));
+        assert_eq!(target.value().name(), "h2");
+        assert_eq!(target.text().collect::<String>(), "Setup");
+    }
+
+    #[tokio::test]
+    async fn images_use_their_caption_as_alt_text() {
+        let context = context().await;
+        let renderer = HtmlRenderer::new(HtmlOptions {
+            lazy_load_images: true,
+            ..HtmlOptions::default()
+        });
+        let link_metadata = HashMap::new();
+        let cx = RenderContext {
+            context: &context,
+            renderer: &renderer,
+            link_metadata: &link_metadata,
+        };
+
+        let image: ImageField = serde_json::from_value(json!({
+            "caption": [text(" A 'cat' "), text("on a mat ")],
+            "type": "external",
+            "external": { "url": "https://example.com/cat.jpg" }
+        }))
+        .unwrap();
+        assert_eq!(
+            render_image(&image, None, &renderer.options, &cx),
+            "<img src='https://example.com/cat.jpg' alt='A &#39;cat&#39; on a mat' loading='lazy' class='notion-image-content' />\
+             <figcaption class='notion-caption'><span class='notion-rich-text'> A &#39;cat&#39; </span>\
+             <span class='notion-rich-text'>on a mat </span></figcaption>"
+        );
+
+        let responsive_image = ResponsiveImage {
+            width: 1200,
+            height: 800,
+            sources: Vec::new(),
+            webp_sources: Vec::new(),
+            sizes: String::from("100vw"),
+            placeholder: None,
+        };
+        let image: ImageField = serde_json::from_value(json!({
+            "caption": [],
+            "type": "external",
+            "external": { "url": "https://example.com/cat.jpg" }
+        }))
+        .unwrap();
+        assert_eq!(
+            render_image(&image, Some(&responsive_image), &renderer.options, &cx),
+            "<img src='https://example.com/cat.jpg' alt='' width='1200' height='800' loading='lazy' class='notion-image-content' />"
+        );
+    }
+}
@@ -1,223 +1,1150 @@
-use async_recursion::async_recursion;
+use async_trait::async_trait;
 use lambda_runtime::Error;
 use serde_json::Value;
 
+use crate::helpers::escape::escape_html;
+use crate::helpers::fetch_file_size::format_file_size;
+use crate::helpers::fetch_link_metadata::LinkMetadata;
+use crate::helpers::front_matter::front_matter;
 use crate::helpers::get_all_blocks::get_all_blocks;
+use crate::helpers::get_page::get_page;
+use crate::helpers::inline_markdown::{
+    class_attribute, escape_markdown, link_destination, rich_text_to_markdown_with,
+};
+use crate::helpers::link_preview::link_preview_metadata;
 
-use crate::models::block::Block;
+use crate::models::block::{
+    BaseBlock, Block, BookmarkBlock, BreadcrumbBlock, BulletedListItemBlock, CalloutBlock,
+    CalloutField, ChildDatabaseBlock, ChildPageBlock, CodeBlock, CodeField, ColumnBlock,
+    ColumnListBlock, DividerBlock, EmbedBlock, EquationBlock, FileBlock, FileField, Heading1Block,
+    Heading2Block, Heading3Block, ImageBlock, ImageField, LinkPreviewBlock, ListFormat,
+    MentionBlock, NumberedListItemBlock, ParagraphBlock, PdfBlock, QuoteBlock, SyncedBlock,
+    TableBlock, TableField, TableOfContentsBlock, TableRowBlock, TableRowField, TemplateBlock,
+    ToDoBlock, ToggleBlock, UnsupportedBlock, VideoBlock,
+};
 
-use crate::models::objects::FileObject;
+use crate::models::common::Color;
+use crate::models::rich_text::{RichText, RichTextElement};
 
-use crate::helpers::fetch_title::fetch_title;
+use crate::commands::convert_page_to_html::render_table as render_html_table;
+use crate::context::{option_from_event, ConvertContext};
+use crate::options::{MarkdownFlavor, MarkdownOptions};
+use crate::renderer::{render_each, render_page, RenderContext, Renderer};
 
 pub async fn convert_page_to_markdown_command(event: Value) -> Result<String, Error> {
-    let notion_api_key = event["NOTION_API_KEY"]
-        .as_str()
-        .ok_or_else(|| Error::from("The NOTION_API_KEY field is missing or not a string"))?;
-
     let block_id = event["block_id"]
         .as_str()
         .ok_or_else(|| Error::from("The NOTION_API_KEY field is missing or not a string"))?;
 
-    let html = convert_page_to_markdown(notion_api_key, block_id).await;
+    let options: MarkdownOptions = option_from_event(&event, "markdown_options")?;
+
+    let context = ConvertContext::from_event(&event).await?;
+
+    let front_matter = match &options.front_matter {
+        Some(front_matter_options) => {
+            let mut page = get_page(&context.notion_api_key, block_id).await?;
+            context.resolve_page(&mut page).await;
+            format!("{}\n", front_matter(&page, front_matter_options))
+        }
+        None => String::new(),
+    };
+
+    let html = render_page(&MarkdownRenderer::new(options), block_id, &context).await;
+    context.report_unresolved_links();
     match html {
-        Ok(html) => Ok(html),
+        Ok(html) => Ok(format!("{}{}", front_matter, html)),
         Err(e) => Err(Error::from(e)),
     }
 }
 
-#[async_recursion]
-pub async fn convert_page_to_markdown(
-    notion_api_key: &str,
-    block_id: &str,
-) -> Result<String, Error> {
-    let mut markdown: String = String::new();
-    let blocks = get_all_blocks(notion_api_key, block_id).await.unwrap();
-
-    for block in &blocks {
-        match block {
-            Block::Bookmark(bookmark_block) => {
-                let title = fetch_title(&bookmark_block.bookmark.url).await.unwrap();
-                markdown.push_str(&format!("\n[{}]({})\n", title, bookmark_block.bookmark.url));
-            }
+/// ## MarkdownRenderer - Struct
+///
+/// The built-in Markdown [`Renderer`], writing the syntax of `options.flavor`.
+pub struct MarkdownRenderer {
+    pub options: MarkdownOptions,
+}
 
-            Block::Breadcrumb(_) => {
-                println!("Breadcrumb is unsupported!");
-            }
+impl MarkdownRenderer {
+    pub fn new(options: MarkdownOptions) -> Self {
+        MarkdownRenderer { options }
+    }
+}
 
-            Block::BulletedListItem(bulleted_list_item_block) => {
-                markdown.push_str(&format!(
-                    "- {}\n",
-                    bulleted_list_item_block.bulleted_list_item.to_markdown()
-                ));
-            }
+#[async_trait]
+impl Renderer for MarkdownRenderer {
+    fn base(&self) -> &dyn Renderer {
+        self
+    }
 
-            Block::Callout(_) => {
-                println!("Callout is unsupported!");
-            }
+    /// Blocks keep themselves apart with blank lines, which are collapsed here.
+    ///
+    /// Markdown only numbers lists with digits, so lists in letters or roman numerals are
+    /// written as a whole as `<ol type start>` where Markdown inside HTML is rendered,
+    /// see [`render_html_list`]. Elsewhere they are numbered like any other list.
+    async fn blocks(&self, blocks: &[Block], cx: &RenderContext<'_>) -> Result<String, Error> {
+        let mut markdown = Vec::new();
+        let mut start = 0;
+        let mut index = 0;
+        while index < blocks.len() {
+            let starts_list =
+                index == 0 || !matches!(blocks[index - 1], Block::NumberedListItem(_));
+            let list_type = match &blocks[index] {
+                Block::NumberedListItem(block) if starts_list => {
+                    html_list_type(block.numbered_list_item.list_format, self.options.flavor)
+                }
+                _ => None,
+            };
+            let Some(list_type) = list_type else {
+                index += 1;
+                continue;
+            };
 
-            Block::ChildDatabase(_) => {
-                println!("ChildDatabase is unsupported!");
-            }
+            let end = index
+                + blocks[index..]
+                    .iter()
+                    .take_while(|block| matches!(block, Block::NumberedListItem(_)))
+                    .count();
+            markdown.extend(render_each(&blocks[start..index], cx).await?);
+            markdown
+                .push(render_html_list(&blocks[index..end], list_type, &self.options, cx).await?);
+            start = end;
+            index = end;
+        }
+        markdown.extend(render_each(&blocks[start..], cx).await?);
+        Ok(normalize_newlines(&markdown.concat()))
+    }
 
-            Block::ChildPage(_) => {
-                println!("ChildPage is unsupported!");
-            }
+    async fn bookmark(
+        &self,
+        block: &BookmarkBlock,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        let metadata = cx
+            .link_metadata
+            .get(&block.bookmark.url)
+            .cloned()
+            .unwrap_or_default();
+        Ok(render_link_card(
+            &block.bookmark.url,
+            &block.bookmark.caption,
+            &metadata,
+            self.options.flavor,
+        ))
+    }
 
-            Block::Code(code_block) => {
-                let mut code_text = String::new();
-                for rich_text in &code_block.code.rich_text {
-                    code_text.push_str(&rich_text.to_plaintext());
-                }
-                markdown.push_str(&format!(
-                    "\n```{}\n{}\n```\n",
-                    code_text,
-                    code_block.code.language.to_class_name(),
-                ));
-            }
+    async fn breadcrumb(
+        &self,
+        _block: &BreadcrumbBlock,
+        _cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        println!("Breadcrumb is unsupported!");
+        Ok(String::new())
+    }
 
-            Block::Column(column_block) => {
-                let child_markdown =
-                    convert_page_to_markdown(notion_api_key, &column_block.base.id).await?;
-                markdown.push_str(&format!("\n{}\n\n", child_markdown));
-            }
+    async fn bulleted_list_item(
+        &self,
+        block: &BulletedListItemBlock,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        let item = &block.bulleted_list_item;
+        Ok(render_list_item(
+            "- ",
+            &color_span(
+                cx.renderer.rich_text(&item.rich_text, cx),
+                item.color,
+                &self.options,
+            ),
+            &render_children(&block.base, cx).await?,
+        ))
+    }
 
-            Block::ColumnList(colmn_list_block) => {
-                let child_markdown =
-                    convert_page_to_markdown(notion_api_key, &colmn_list_block.base.id).await?;
-                markdown.push_str(&format!("\n{}\n\n", child_markdown));
-            }
+    async fn callout(&self, block: &CalloutBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        Ok(render_callout(
+            &block.callout,
+            &cx.renderer.rich_text(&block.callout.rich_text, cx),
+            &render_children(&block.base, cx).await?,
+            &self.options,
+        ))
+    }
 
-            Block::Divider(_) => {
-                markdown.push_str(&format!("\n\n---\n\n"));
-            }
+    async fn child_database(
+        &self,
+        _block: &ChildDatabaseBlock,
+        _cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        println!("ChildDatabase is unsupported!");
+        Ok(String::new())
+    }
 
-            Block::Embed(_) => {
-                println!("Embed is unsupported!");
-            }
+    async fn child_page(
+        &self,
+        _block: &ChildPageBlock,
+        _cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        println!("ChildPage is unsupported!");
+        Ok(String::new())
+    }
 
-            Block::Equation(_) => {
-                println!("Equation is unsupported!");
-            }
+    async fn code(&self, block: &CodeBlock, _cx: &RenderContext<'_>) -> Result<String, Error> {
+        Ok(render_code(&block.code, &self.options))
+    }
 
-            Block::File(_) => {
-                println!("File is unsupported!");
-            }
+    async fn column(&self, block: &ColumnBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        Ok(format!(
+            "\n{}\n\n",
+            cx.render_children(&block.base.id).await?
+        ))
+    }
 
-            Block::Heading1(heading_1) => {
-                markdown.push_str(&format!("\n# {}\n\n", heading_1.heading_1.to_markdown()));
+    /// Columns are rendered here rather than as blocks of their own,
+    /// since how they are laid out depends on how many there are.
+    async fn column_list(
+        &self,
+        block: &ColumnListBlock,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        let columns = get_all_blocks(&cx.context.notion_api_key, &block.base.id).await?;
+        let mut column_markdowns = Vec::new();
+        for column in &columns {
+            if let Block::Column(column_block) = column {
+                column_markdowns.push(cx.renderer.column(column_block, cx).await?);
             }
+        }
+        Ok(render_columns(&column_markdowns, &self.options))
+    }
 
-            Block::Heading2(heading_2) => {
-                markdown.push_str(&format!("\n## {}\n\n", heading_2.heading_2.to_markdown()));
-            }
+    async fn divider(
+        &self,
+        _block: &DividerBlock,
+        _cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        Ok(String::from("\n\n---\n\n"))
+    }
 
-            Block::Heading3(heading_3) => {
-                markdown.push_str(&format!("\n### {}\n\n", heading_3.heading_3.to_markdown()));
-            }
+    async fn embed(&self, block: &EmbedBlock, _cx: &RenderContext<'_>) -> Result<String, Error> {
+        Ok(render_link(
+            &block.embed.url,
+            &block.embed.caption,
+            self.options.flavor,
+        ))
+    }
 
-            Block::Image(image_block) => match &image_block.image {
-                FileObject::File { file } => {
-                    markdown.push_str(&format!("\n![alt]({})\n\n", file.url));
-                }
+    async fn equation(
+        &self,
+        block: &EquationBlock,
+        _cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        Ok(render_equation(
+            &block.equation.expression,
+            self.options.flavor,
+        ))
+    }
 
-                FileObject::External { external } => {
-                    markdown.push_str(&format!("\n![alt]({})\n\n", external.url));
-                }
-            },
+    async fn file(&self, block: &FileBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        let size = cx.context.file_size(block.file.file.url());
+        Ok(render_file(&block.file, size, self.options.flavor))
+    }
 
-            Block::LinkPreview(_) => {
-                println!("LinkPreview is unsupported!");
-            }
+    async fn heading_1(
+        &self,
+        block: &Heading1Block,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        let heading = &block.heading_1;
+        let heading_markdown = color_span(
+            cx.renderer.rich_text(&heading.rich_text, cx),
+            heading.color,
+            &self.options,
+        );
+        render_heading(
+            1,
+            heading,
+            &heading_markdown,
+            &block.base,
+            &self.options,
+            cx,
+        )
+        .await
+    }
 
-            Block::NumberedListItem(numberted_list_item_block) => {
-                markdown.push_str(&format!(
-                    "- {}\n",
-                    numberted_list_item_block.numbered_list_item.to_markdown()
-                ));
-            }
+    async fn heading_2(
+        &self,
+        block: &Heading2Block,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        let heading = &block.heading_2;
+        let heading_markdown = color_span(
+            cx.renderer.rich_text(&heading.rich_text, cx),
+            heading.color,
+            &self.options,
+        );
+        render_heading(
+            2,
+            heading,
+            &heading_markdown,
+            &block.base,
+            &self.options,
+            cx,
+        )
+        .await
+    }
 
-            Block::Mention(_) => {
-                println!("Mention is unsupported!");
-            }
+    async fn heading_3(
+        &self,
+        block: &Heading3Block,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        let heading = &block.heading_3;
+        let heading_markdown = color_span(
+            cx.renderer.rich_text(&heading.rich_text, cx),
+            heading.color,
+            &self.options,
+        );
+        render_heading(
+            3,
+            heading,
+            &heading_markdown,
+            &block.base,
+            &self.options,
+            cx,
+        )
+        .await
+    }
 
-            Block::Paragraph(paragraph_block) => {
-                markdown.push_str(&format!(
-                    "\n{}\n\n",
-                    paragraph_block.paragraph.to_markdown()
-                ));
-            }
+    async fn image(&self, block: &ImageBlock, _cx: &RenderContext<'_>) -> Result<String, Error> {
+        Ok(render_image(&block.image, self.options.flavor))
+    }
 
-            Block::Pdf(_) => {
-                println!("Pdf is unsupported!");
-            }
+    async fn link_preview(
+        &self,
+        block: &LinkPreviewBlock,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        let metadata = link_preview_metadata(&block.link_preview.url, cx.link_metadata);
+        Ok(render_link_card(
+            &block.link_preview.url,
+            &[],
+            &metadata,
+            self.options.flavor,
+        ))
+    }
 
-            Block::Quote(quote_block) => {
-                markdown.push_str(&format!("\n> {}\n\n", quote_block.quote.to_markdown()));
-            }
+    async fn numbered_list_item(
+        &self,
+        block: &NumberedListItemBlock,
+        number: u32,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        let item = &block.numbered_list_item;
+        let mut markdown = String::new();
+        // Only a list that starts at 1 can interrupt a paragraph, so one that does not
+        // is kept apart from what comes before it by a blank line.
+        if item.list_start_index == Some(number) && number != 1 {
+            markdown.push('\n');
+        }
 
-            Block::SyncedBlock(synced_block) => {
-                let child_markdown =
-                    convert_page_to_markdown(notion_api_key, &synced_block.base.id).await?;
-                markdown.push_str(&child_markdown);
+        if let Some(list_format @ (ListFormat::Letters | ListFormat::Roman)) = item.list_format {
+            if html_list_type(Some(list_format), self.options.flavor).is_none() {
+                println!(
+                    "{:?} list format is written as numbers in {:?}!",
+                    list_format, self.options.flavor
+                );
             }
+        }
 
-            Block::Table(table_block) => {
-                markdown.push_str("|");
-                let child_markdown =
-                    convert_page_to_markdown(notion_api_key, &table_block.base.id).await?;
-                markdown.push_str(&child_markdown);
-                markdown.push_str("\n");
-            }
+        markdown.push_str(&render_list_item(
+            &format!("{}. ", number),
+            &color_span(
+                cx.renderer.rich_text(&item.rich_text, cx),
+                item.color,
+                &self.options,
+            ),
+            &render_children(&block.base, cx).await?,
+        ));
+        Ok(markdown)
+    }
 
-            Block::TableOfContents(_) => {
-                println!("TableOfContents is unsupported!");
-            }
+    async fn mention(
+        &self,
+        _block: &MentionBlock,
+        _cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        println!("Mention is unsupported!");
+        Ok(String::new())
+    }
 
-            Block::TableRow(table_row_block) => {
-                let mut is_head = false;
-                if markdown.ends_with("\n|") {
-                    is_head = true;
-                    markdown.push_str("\n|");
-                }
-                for cell in &table_row_block.table_row.cells {
-                    for rich_text in cell {
-                        markdown.push_str(&format!(" {} |", rich_text.to_markdown()));
-                    }
-                }
-                markdown.push_str("\n");
-                if is_head {
-                    markdown.push_str("|");
-                    for _ in &table_row_block.table_row.cells {
-                        markdown.push_str(" --- |");
-                    }
-                    markdown.push_str("\n");
-                }
-            }
+    async fn paragraph(
+        &self,
+        block: &ParagraphBlock,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        let paragraph = &block.paragraph;
+        Ok(format!(
+            "\n{}\n\n",
+            color_span(
+                cx.renderer.rich_text(&paragraph.rich_text, cx),
+                paragraph.color,
+                &self.options
+            )
+        ))
+    }
+
+    async fn pdf(&self, block: &PdfBlock, _cx: &RenderContext<'_>) -> Result<String, Error> {
+        Ok(render_link(
+            block.pdf.file.url(),
+            &block.pdf.caption,
+            self.options.flavor,
+        ))
+    }
+
+    async fn quote(&self, block: &QuoteBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        let quote = &block.quote;
+        Ok(format!(
+            "\n> {}\n\n",
+            color_span(
+                cx.renderer.rich_text(&quote.rich_text, cx),
+                quote.color,
+                &self.options
+            )
+        ))
+    }
+
+    async fn synced_block(
+        &self,
+        block: &SyncedBlock,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        cx.render_children(&block.base.id).await
+    }
+
+    async fn table(&self, block: &TableBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        let rows = cx.child_blocks(&block.base.id).await?;
+        let render_cell = |cell: &[RichTextElement]| cx.renderer.rich_text(cell, cx);
+        let table_markdown = if self.options.flavor.has_tables() {
+            render_table(&block.table, &rows, &self.options, render_cell)
+        } else {
+            render_table_in_html(&block.table, &rows, &self.options, render_cell)
+        };
+        Ok(format!("\n{}\n", table_markdown))
+    }
+
+    async fn table_of_contents(
+        &self,
+        _block: &TableOfContentsBlock,
+        _cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        println!("TableOfContents is unsupported!");
+        Ok(String::new())
+    }
+
+    async fn table_row(
+        &self,
+        _block: &TableRowBlock,
+        _cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        // Table rows are rendered together with their parent table.
+        Ok(String::new())
+    }
+
+    async fn template(
+        &self,
+        _block: &TemplateBlock,
+        _cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        println!("Template is unsupported!");
+        Ok(String::new())
+    }
+
+    async fn to_do(&self, block: &ToDoBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        let checkbox = if block.to_do.checked { "x" } else { " " };
+        let item_markdown = format!(
+            "[{}] {}",
+            checkbox,
+            color_span(
+                cx.renderer.rich_text(&block.to_do.rich_text, cx),
+                block.to_do.color,
+                &self.options
+            )
+        );
+        Ok(render_list_item(
+            "- ",
+            &item_markdown,
+            &render_children(&block.base, cx).await?,
+        ))
+    }
+
+    async fn toggle(&self, block: &ToggleBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        Ok(render_toggle(
+            &block.toggle.rich_text,
+            &cx.renderer.rich_text(&block.toggle.rich_text, cx),
+            None,
+            &render_children(&block.base, cx).await?,
+            &self.options,
+        ))
+    }
+
+    async fn unsupported(
+        &self,
+        _block: &UnsupportedBlock,
+        _cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        println!("This kind of Block is unsupported!");
+        Ok(String::new())
+    }
+
+    async fn video(&self, block: &VideoBlock, _cx: &RenderContext<'_>) -> Result<String, Error> {
+        Ok(render_link(
+            block.video.file.url(),
+            &block.video.caption,
+            self.options.flavor,
+        ))
+    }
+
+    fn block_attributes(
+        &self,
+        _block_type: &str,
+        _base: &BaseBlock,
+        _cx: &RenderContext<'_>,
+    ) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    fn rich_text(&self, rich_text: &[RichTextElement], cx: &RenderContext<'_>) -> String {
+        rich_text_to_markdown_with(rich_text, &self.options, |element| {
+            cx.render_element(element)
+        })
+    }
+
+    /// Elements are left to [`rich_text_to_markdown_with`], so that annotations
+    /// shared with their neighbours nest.
+    fn text_element(&self, _element: &RichTextElement, _cx: &RenderContext<'_>) -> Option<String> {
+        None
+    }
+
+    fn mention_element(
+        &self,
+        _element: &RichTextElement,
+        _cx: &RenderContext<'_>,
+    ) -> Option<String> {
+        None
+    }
+
+    fn equation_element(
+        &self,
+        _element: &RichTextElement,
+        _cx: &RenderContext<'_>,
+    ) -> Option<String> {
+        None
+    }
+}
+
+/// Toggleable headings become toggles with the heading as the summary, see [`render_toggle`].
+/// Other headings only have children in pages from before headings could be toggled;
+/// those children follow the heading as ordinary content.
+async fn render_heading(
+    level: usize,
+    heading: &RichText,
+    heading_markdown: &str,
+    base: &BaseBlock,
+    options: &MarkdownOptions,
+    cx: &RenderContext<'_>,
+) -> Result<String, Error> {
+    let children = render_children(base, cx).await?;
+    if heading.is_toggleable == Some(true) {
+        return Ok(render_toggle(
+            &heading.rich_text,
+            heading_markdown,
+            Some(level),
+            &children,
+            options,
+        ));
+    }
+
+    let mut markdown = format!("\n{} {}\n\n", "#".repeat(level), heading_markdown);
+    if !children.is_empty() {
+        markdown.push_str(&format!("\n{}\n\n", children));
+    }
+    Ok(markdown)
+}
+
+/// The children of a list item, toggle or callout, which are nested into it.
+async fn render_children(base: &BaseBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+    if !base.has_children {
+        return Ok(String::new());
+    }
+    cx.render_children(&base.id).await
+}
+
+/// Writes a list item with `marker`, e.g. `- ` or `3. `. Continuation lines and children are
+/// indented by the width of the marker, as CommonMark requires to keep them in the item.
+fn render_list_item(marker: &str, item_markdown: &str, children: &str) -> String {
+    let indent = " ".repeat(marker.len());
+    let mut markdown = format!(
+        "{}{}\n",
+        marker,
+        indent_lines(item_markdown, &indent, false)
+    );
+
+    let children = children.trim_matches('\n');
+    if !children.is_empty() {
+        markdown.push_str(&indent_lines(children, &indent, true));
+        markdown.push('\n');
+    }
+    markdown
+}
 
-            Block::Template(_) => {
-                println!("Template is unsupported!");
+/// Indents every non-empty line (but the first unless `first_line`) by `indent`.
+fn indent_lines(text: &str, indent: &str, first_line: bool) -> String {
+    text.split('\n')
+        .enumerate()
+        .map(|(index, line)| {
+            if line.is_empty() || (index == 0 && !first_line) {
+                line.to_string()
+            } else {
+                format!("{}{}", indent, line)
             }
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Writes a fenced code block. The fence is longer than any run of backticks in the code.
+/// The caption is the `title` of the block in MDX (as Docusaurus reads it),
+/// and an italic line below it elsewhere.
+fn render_code(code: &CodeField, options: &MarkdownOptions) -> String {
+    let code_text: String = code
+        .rich_text
+        .iter()
+        .map(|rich_text| rich_text.to_plaintext())
+        .collect();
+    let caption_text = caption_to_plaintext(&code.caption);
+
+    let longest_run = code_text
+        .split(|c| c != '`')
+        .map(|run| run.len())
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat(longest_run.max(2) + 1);
 
-            Block::ToDo(todo_block) => {
-                markdown.push_str(&format!("- [] {}\n", todo_block.to_do.to_markdown()));
+    let mut info = code.language.to_fence_language().unwrap_or("").to_string();
+    if options.flavor.is_jsx() && !caption_text.is_empty() {
+        let title = caption_text.replace(['\n', '\r'], " ").replace('"', "'");
+        if info.is_empty() {
+            info.push_str("text");
+        }
+        info.push_str(&format!(" title=\"{}\"", title));
+    }
+
+    let mut markdown = format!(
+        "\n{}{}\n{}\n{}\n\n",
+        fence,
+        info,
+        code_text.trim_end_matches('\n'),
+        fence
+    );
+    if !options.flavor.is_jsx() && !caption_text.is_empty() {
+        markdown.push_str(&format!(
+            "*{}*\n\n",
+            escape_markdown(&caption_text, options.flavor)
+        ));
+    }
+    markdown
+}
+
+/// Prefixes every line with `>`, keeping `markdown` inside one blockquote.
+fn blockquote(markdown: &str) -> String {
+    markdown
+        .trim_matches('\n')
+        .split('\n')
+        .map(|line| {
+            if line.is_empty() {
+                String::from(">")
+            } else {
+                format!("> {}", line)
             }
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
 
-            Block::Toggle(_) => {
-                println!("Toggle is unsupported!");
+/// Toggles are `<details>` where HTML works, a folded callout in Obsidian,
+/// and Hugo's `details` shortcode. `summary_markdown` is the summary rendered as Markdown.
+///
+/// A toggleable heading of `heading_level` has an `<h1>` to `<h3>` as its `<summary>`.
+/// Callout titles and shortcode parameters cannot hold a heading, so in Obsidian and Hugo
+/// its text is the summary.
+fn render_toggle(
+    summary: &[RichTextElement],
+    summary_markdown: &str,
+    heading_level: Option<usize>,
+    children: &str,
+    options: &MarkdownOptions,
+) -> String {
+    let children = children.trim_matches('\n');
+    match options.flavor {
+        MarkdownFlavor::Obsidian => {
+            let mut markdown = format!("\n> [!note]- {}\n", summary_markdown);
+            if !children.is_empty() {
+                markdown.push_str(&format!("{}\n", blockquote(children)));
+            }
+            markdown.push('\n');
+            markdown
+        }
+        MarkdownFlavor::Hugo => format!(
+            "\n{{{{< details summary=\"{}\" >}}}}\n\n{}\n\n{{{{< /details >}}}}\n\n",
+            summary_markdown.replace('\\', "\\\\").replace('"', "\\\""),
+            children
+        ),
+        MarkdownFlavor::CommonMark | MarkdownFlavor::Gfm | MarkdownFlavor::Mdx => {
+            // Markdown is not rendered inside `<summary>`, so the summary is plain text.
+            let mut summary_html = escape_html(&caption_to_plaintext(summary));
+            if options.flavor.is_jsx() {
+                summary_html = summary_html.replace('{', "&#123;").replace('}', "&#125;");
             }
+            if let Some(level) = heading_level {
+                summary_html = format!("<h{}>{}</h{}>", level, summary_html, level);
+            }
+            format!(
+                "\n<details>\n<summary>{}</summary>\n\n{}\n\n</details>\n\n",
+                summary_html, children
+            )
+        }
+    }
+}
 
-            Block::Unsupported(_) => {
-                println!("This kind of Block is unsupported!");
+/// The kinds of admonition that all flavors with admonitions have in common.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Admonition {
+    Note,
+    Tip,
+    Important,
+    Warning,
+    Caution,
+}
+
+impl Admonition {
+    /// Picks the admonition that the callout's icon stands for, or failing that, its color.
+    /// Returns whether it was the icon, which then goes without saying.
+    fn of_callout(callout: &CalloutField) -> (Admonition, bool) {
+        let by_icon = match callout.icon.emoji.trim_end_matches('\u{FE0F}') {
+            "💡" => Some(Admonition::Tip),
+            "ℹ" | "📝" => Some(Admonition::Note),
+            "❗" | "‼" | "📌" => Some(Admonition::Important),
+            "⚠" => Some(Admonition::Warning),
+            "🚨" | "⛔" | "🛑" | "❌" => Some(Admonition::Caution),
+            _ => None,
+        };
+        match by_icon {
+            Some(admonition) => (admonition, true),
+            None => {
+                let admonition = match callout.color {
+                    Color::Red | Color::RedBackground => Admonition::Caution,
+                    Color::Orange
+                    | Color::OrangeBackground
+                    | Color::Yellow
+                    | Color::YellowBackground => Admonition::Warning,
+                    _ => Admonition::Note,
+                };
+                (admonition, false)
             }
+        }
+    }
+
+    /// The name in GitHub (and Hugo) alerts, e.g. `> [!TIP]`.
+    fn alert_name(self) -> &'static str {
+        match self {
+            Admonition::Note => "NOTE",
+            Admonition::Tip => "TIP",
+            Admonition::Important => "IMPORTANT",
+            Admonition::Warning => "WARNING",
+            Admonition::Caution => "CAUTION",
+        }
+    }
+
+    /// The name in Obsidian callouts, e.g. `> [!tip]`.
+    fn callout_name(self) -> &'static str {
+        match self {
+            Admonition::Note => "note",
+            Admonition::Tip => "tip",
+            Admonition::Important => "important",
+            Admonition::Warning => "warning",
+            Admonition::Caution => "danger",
+        }
+    }
+
+    /// The name in Docusaurus admonitions, e.g. `:::tip`.
+    fn docusaurus_name(self) -> &'static str {
+        match self {
+            Admonition::Note => "note",
+            Admonition::Tip => "tip",
+            Admonition::Important => "info",
+            Admonition::Warning => "warning",
+            Admonition::Caution => "danger",
+        }
+    }
+}
+
+/// Callouts become admonitions: GitHub alerts (which Hugo renders too), Obsidian callouts,
+/// or Docusaurus admonitions in MDX. CommonMark has none, so there it is a plain blockquote.
+/// `text` is the text of the callout rendered as Markdown.
+fn render_callout(
+    callout: &CalloutField,
+    text: &str,
+    children: &str,
+    options: &MarkdownOptions,
+) -> String {
+    let (admonition, icon_implied) = Admonition::of_callout(callout);
+    let text = color_span(text.to_string(), callout.color, options);
+    let icon = callout.icon.emoji.as_str();
+
+    let text = if icon.is_empty() || (icon_implied && options.flavor != MarkdownFlavor::CommonMark)
+    {
+        text
+    } else {
+        format!("{} {}", icon, text)
+    };
+    let body = [text.as_str(), children.trim_matches('\n')]
+        .iter()
+        .filter(|part| !part.is_empty())
+        .copied()
+        .collect::<Vec<&str>>()
+        .join("\n\n");
+
+    match options.flavor {
+        MarkdownFlavor::CommonMark => format!("\n{}\n\n", blockquote(&body)),
+        MarkdownFlavor::Gfm | MarkdownFlavor::Hugo => format!(
+            "\n> [!{}]\n{}\n\n",
+            admonition.alert_name(),
+            blockquote(&body)
+        ),
+        MarkdownFlavor::Obsidian => format!(
+            "\n> [!{}]\n{}\n\n",
+            admonition.callout_name(),
+            blockquote(&body)
+        ),
+        MarkdownFlavor::Mdx => format!(
+            "\n:::{}\n\n{}\n\n:::\n\n",
+            admonition.docusaurus_name(),
+            body
+        ),
+    }
+}
+
+/// The `type` of the `<ol>` that a list in `list_format` is written as, or `None` when it is
+/// numbered in Markdown: for lists in digits, and where Markdown inside HTML is not rendered.
+fn html_list_type(list_format: Option<ListFormat>, flavor: MarkdownFlavor) -> Option<&'static str> {
+    if !flavor.renders_markdown_in_html() {
+        return None;
+    }
+    match list_format? {
+        ListFormat::Numbers => None,
+        ListFormat::Letters => Some("a"),
+        ListFormat::Roman => Some("i"),
+    }
+}
+
+/// Writes the numbered list `items` as `<ol type='a' start='n'>` with an `<li>` per item.
+/// The text and children of the items are Markdown, set apart by blank lines so that it
+/// is rendered.
+async fn render_html_list(
+    items: &[Block],
+    list_type: &str,
+    options: &MarkdownOptions,
+    cx: &RenderContext<'_>,
+) -> Result<String, Error> {
+    let start = match items.first() {
+        Some(Block::NumberedListItem(block)) => {
+            block.numbered_list_item.list_start_index.unwrap_or(1)
+        }
+        _ => 1,
+    };
+    let start = if options.flavor.is_jsx() {
+        format!("{{{}}}", start)
+    } else {
+        format!("'{}'", start)
+    };
+
+    let mut markdown = format!("\n<ol type='{}' start={}>\n", list_type, start);
+    for item in items {
+        if let Block::NumberedListItem(block) = item {
+            let item_markdown = color_span(
+                cx.renderer
+                    .rich_text(&block.numbered_list_item.rich_text, cx),
+                block.numbered_list_item.color,
+                options,
+            );
+            let children = render_children(&block.base, cx).await?;
+            markdown.push_str(&format!(
+                "<li>\n\n{}\n\n{}\n\n</li>\n",
+                item_markdown,
+                children.trim_matches('\n')
+            ));
+        }
+    }
+    markdown.push_str("</ol>\n\n");
+    Ok(markdown)
+}
+
+/// Columns are a grid of `<div>`s where Markdown inside HTML is rendered. Elsewhere they
+/// are written one after another, with `column_separator` between them.
+fn render_columns(columns: &[String], options: &MarkdownOptions) -> String {
+    let columns: Vec<&str> = columns
+        .iter()
+        .map(|column| column.trim_matches('\n'))
+        .collect();
+
+    if !options.flavor.renders_markdown_in_html() {
+        let separator = match &options.column_separator {
+            Some(separator) => format!("\n\n{}\n\n", separator),
+            None => String::from("\n\n"),
+        };
+        return format!("\n{}\n\n", columns.join(&separator));
+    }
+
+    let class = class_attribute(options.flavor);
+    let style = if options.flavor.is_jsx() {
+        format!(
+            "{{{{ display: 'grid', gridTemplateColumns: 'repeat({}, minmax(0, 1fr))', gap: '1.5em' }}}}",
+            columns.len()
+        )
+    } else {
+        format!(
+            "'display: grid; grid-template-columns: repeat({}, minmax(0, 1fr)); gap: 1.5em'",
+            columns.len()
+        )
+    };
+
+    let mut markdown = format!("\n<div {}='notion-column-list' style={}>\n\n", class, style);
+    for column in columns {
+        markdown.push_str(&format!(
+            "<div {}='notion-column'>\n\n{}\n\n</div>\n\n",
+            class, column
+        ));
+    }
+    markdown.push_str("</div>\n\n");
+    markdown
+}
+
+/// Wraps colored text in a `notion-<color>` span when `color_spans` is set,
+/// matching the classes of the HTML output.
+fn color_span(markdown: String, color: Color, options: &MarkdownOptions) -> String {
+    if !options.color_spans || color == Color::Default || markdown.is_empty() {
+        return markdown;
+    }
+    format!(
+        "<span {}='notion-{}'>{}</span>",
+        class_attribute(options.flavor),
+        color.class_name(),
+        markdown
+    )
+}
+
+/// Writes a block equation in the math syntax of `flavor`. CommonMark has none,
+/// so the TeX is kept in a `math` code block, which some renderers pick up.
+fn render_equation(expression: &str, flavor: MarkdownFlavor) -> String {
+    let expression = expression.trim();
+    match flavor {
+        MarkdownFlavor::CommonMark => format!("\n```math\n{}\n```\n\n", expression),
+        MarkdownFlavor::Gfm
+        | MarkdownFlavor::Mdx
+        | MarkdownFlavor::Obsidian
+        | MarkdownFlavor::Hugo => format!("\n$$\n{}\n$$\n\n", expression),
+    }
+}
 
-            _ => {
-                println!("Not Define!!!");
+/// Bookmarks and link previews are links titled by the page they point to.
+/// The title and description come from that page, so they are escaped like any text.
+fn render_link_card(
+    url: &str,
+    caption: &[RichTextElement],
+    metadata: &LinkMetadata,
+    flavor: MarkdownFlavor,
+) -> String {
+    let title = inline_text(metadata.title.as_deref().unwrap_or(url), flavor);
+    let mut markdown = format!("\n{}", link(&title, url));
+    if let Some(description) = &metadata.description {
+        markdown.push_str(&format!(" - {}", inline_text(description, flavor)));
+    }
+    markdown.push_str("\n\n");
+    markdown.push_str(&render_caption(caption, flavor));
+    markdown
+}
+
+fn caption_to_plaintext(caption: &[RichTextElement]) -> String {
+    caption
+        .iter()
+        .map(|rich_text| rich_text.to_plaintext())
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Writes the plain caption as an italic line, or nothing for an empty caption.
+fn render_caption(caption: &[RichTextElement], flavor: MarkdownFlavor) -> String {
+    let caption_text = caption_to_plaintext(caption);
+    if caption_text.is_empty() {
+        return String::new();
+    }
+    format!("*{}*\n\n", inline_text(&caption_text, flavor))
+}
+
+/// Escapes `text` for a single line of Markdown, collapsing its whitespace.
+fn inline_text(text: &str, flavor: MarkdownFlavor) -> String {
+    escape_markdown(
+        &text.split_whitespace().collect::<Vec<&str>>().join(" "),
+        flavor,
+    )
+}
+
+/// A link with the already escaped `label`, or just the label when the scheme of `url`
+/// is not allowed.
+fn link(label: &str, url: &str) -> String {
+    match link_destination(url) {
+        Some(destination) => format!("[{}]({})", label, destination),
+        None => label.to_string(),
+    }
+}
+
+/// The plain caption doubles as alt text, and is repeated below the image
+/// because Markdown has no figure caption.
+fn render_image(image: &ImageField, flavor: MarkdownFlavor) -> String {
+    let alt = inline_text(&caption_to_plaintext(&image.caption), flavor);
+    let image_markdown = match link_destination(image.file.url()) {
+        Some(destination) => format!("![{}]({})", alt, destination),
+        None => alt,
+    };
+    format!(
+        "\n{}\n\n{}",
+        image_markdown,
+        render_caption(&image.caption, flavor)
+    )
+}
+
+/// Media that Markdown cannot embed is written as a plain link,
+/// labelled with its caption when there is one.
+fn render_link(url: &str, caption: &[RichTextElement], flavor: MarkdownFlavor) -> String {
+    let caption_text = caption_to_plaintext(caption);
+    let label = if caption_text.is_empty() {
+        url
+    } else {
+        &caption_text
+    };
+    format!("\n{}\n\n", link(&inline_text(label, flavor), url))
+}
+
+fn render_file(file: &FileField, size: Option<u64>, flavor: MarkdownFlavor) -> String {
+    let mut markdown = format!(
+        "\n{}",
+        link(&inline_text(&file.file_name(), flavor), file.file.url())
+    );
+
+    let details: Vec<String> = file
+        .extension()
+        .into_iter()
+        .chain(size.map(format_file_size))
+        .collect();
+    if !details.is_empty() {
+        markdown.push_str(&format!(" ({})", inline_text(&details.join(", "), flavor)));
+    }
+    markdown.push_str("\n\n");
+    markdown.push_str(&render_caption(&file.caption, flavor));
+    markdown
+}
+
+/// GFM tables always require a header row, so a table without a column header
+/// gets an empty one to keep every Notion row in the body. Cells are rendered with `render_cell`.
+fn render_table(
+    table: &TableField,
+    rows: &[Block],
+    options: &MarkdownOptions,
+    render_cell: impl Fn(&[RichTextElement]) -> String,
+) -> String {
+    let mut markdown = String::new();
+
+    let mut rows = rows.iter().filter_map(|block| match block {
+        Block::TableRow(table_row_block) => Some(&table_row_block.table_row),
+        _ => None,
+    });
+
+    let header_row = if table.has_column_header {
+        rows.next()
+    } else {
+        None
+    };
+
+    match header_row {
+        Some(row) => markdown.push_str(&render_table_row(row, options, &render_cell)),
+        None => {
+            markdown.push('|');
+            for _ in 0..table.table_width {
+                markdown.push_str("  |");
             }
+            markdown.push('\n');
         }
     }
 
-    Ok(normalize_newlines(&markdown))
+    markdown.push('|');
+    for _ in 0..table.table_width {
+        markdown.push_str(" --- |");
+    }
+    markdown.push('\n');
+
+    for row in rows {
+        markdown.push_str(&render_table_row(row, options, &render_cell));
+    }
+
+    markdown
+}
+
+fn render_table_row(
+    row: &TableRowField,
+    options: &MarkdownOptions,
+    render_cell: &impl Fn(&[RichTextElement]) -> String,
+) -> String {
+    let mut markdown = String::from("|");
+
+    for cell in &row.cells {
+        let cell_markdown: String = render_cell(cell)
+            .replace('|', "\\|")
+            .replace('\n', line_break(options.flavor));
+        markdown.push_str(&format!(" {} |", cell_markdown));
+    }
+
+    markdown.push('\n');
+    markdown
+}
+
+/// For flavors without pipe tables: an HTML table as `convert_page_to_html` writes it,
+/// with the Markdown of each cell set apart by blank lines so that it is rendered.
+fn render_table_in_html(
+    table: &TableField,
+    rows: &[Block],
+    options: &MarkdownOptions,
+    render_cell: impl Fn(&[RichTextElement]) -> String,
+) -> String {
+    let attributes = format!(" {}='notion-table'", class_attribute(options.flavor));
+    let html = render_html_table(table, rows, &attributes, |cell| {
+        let cell_markdown = render_cell(cell);
+        let cell_markdown = cell_markdown.trim();
+        if cell_markdown.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "\n\n{}\n\n",
+                cell_markdown.replace('\n', line_break(options.flavor))
+            )
+        }
+    });
+    format!("{}\n", html)
+}
+
+/// A line break within a table cell, where a newline would end the cell or paragraph.
+fn line_break(flavor: MarkdownFlavor) -> &'static str {
+    if flavor.is_jsx() {
+        "<br />"
+    } else {
+        "<br>"
+    }
 }
 
 fn normalize_newlines(input: &str) -> String {
@@ -236,7 +1163,7 @@ fn normalize_newlines(input: &str) -> String {
             if newline_count >= 3 {
                 result.push_str("\n\n");
             } else if newline_count > 0 {
-                result.extend(std::iter::repeat('\n').take(newline_count));
+                result.extend(std::iter::repeat_n('\n', newline_count));
             }
             newline_count = 0;
             result.push(c);
@@ -249,3 +1176,493 @@ fn normalize_newlines(input: &str) -> String {
 
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{block, context, rich_text, styled_text, table_row, text, text_row};
+    use pulldown_cmark::{Event, Parser};
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    fn callout(emoji: &str, content: &str) -> CalloutField {
+        serde_json::from_value(json!({
+            "rich_text": [text(content)],
+            "icon": { "type": "emoji", "emoji": emoji },
+            "color": "gray_background"
+        }))
+        .unwrap()
+    }
+
+    fn flavored(flavor: MarkdownFlavor) -> MarkdownOptions {
+        MarkdownOptions {
+            flavor,
+            ..MarkdownOptions::default()
+        }
+    }
+
+    #[test]
+    fn callouts_become_admonitions() {
+        let tip = callout("💡", "Use a cache.");
+        let children = "\nMore detail.\n\n";
+        let expected = [
+            (
+                MarkdownFlavor::CommonMark,
+                "\n> 💡 Use a cache.\n>\n> More detail.\n\n",
+            ),
+            (
+                MarkdownFlavor::Gfm,
+                "\n> [!TIP]\n> Use a cache.\n>\n> More detail.\n\n",
+            ),
+            (
+                MarkdownFlavor::Hugo,
+                "\n> [!TIP]\n> Use a cache.\n>\n> More detail.\n\n",
+            ),
+            (
+                MarkdownFlavor::Obsidian,
+                "\n> [!tip]\n> Use a cache.\n>\n> More detail.\n\n",
+            ),
+            (
+                MarkdownFlavor::Mdx,
+                "\n:::tip\n\nUse a cache.\n\nMore detail.\n\n:::\n\n",
+            ),
+        ];
+        for (flavor, markdown) in expected {
+            assert_eq!(
+                render_callout(&tip, "Use a cache.", children, &flavored(flavor)),
+                markdown,
+                "{:?}",
+                flavor
+            );
+        }
+
+        // An icon without a meaning of its own is kept.
+        assert_eq!(
+            render_callout(
+                &callout("🦀", "Rust"),
+                "Rust",
+                "",
+                &flavored(MarkdownFlavor::Gfm)
+            ),
+            "\n> [!NOTE]\n> 🦀 Rust\n\n"
+        );
+    }
+
+    #[test]
+    fn toggles_keep_their_children() {
+        let summary = rich_text("Details {x}");
+        let children = "- a\n- b\n";
+
+        assert_eq!(
+            render_toggle(
+                &summary,
+                "Details {x}",
+                None,
+                children,
+                &flavored(MarkdownFlavor::Gfm)
+            ),
+            "\n<details>\n<summary>Details {x}</summary>\n\n- a\n- b\n\n</details>\n\n"
+        );
+        assert_eq!(
+            render_toggle(
+                &summary,
+                "Details {x}",
+                None,
+                children,
+                &flavored(MarkdownFlavor::Mdx)
+            ),
+            "\n<details>\n<summary>Details &#123;x&#125;</summary>\n\n- a\n- b\n\n</details>\n\n"
+        );
+        assert_eq!(
+            render_toggle(
+                &summary,
+                "Details {x}",
+                None,
+                children,
+                &flavored(MarkdownFlavor::Obsidian)
+            ),
+            "\n> [!note]- Details {x}\n> - a\n> - b\n\n"
+        );
+        assert_eq!(
+            render_toggle(
+                &summary,
+                "Details {x}",
+                None,
+                children,
+                &flavored(MarkdownFlavor::Hugo)
+            ),
+            "\n{{< details summary=\"Details {x}\" >}}\n\n- a\n- b\n\n{{< /details >}}\n\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn toggleable_headings_are_toggles() {
+        let context = context().await;
+        let link_metadata = HashMap::new();
+        let heading = |is_toggleable| {
+            let block = block(
+                "heading_2",
+                json!({ "rich_text": [text("Setup")], "is_toggleable": is_toggleable,
+                    "color": "default" }),
+            );
+            match block {
+                Block::Heading2(heading) => heading,
+                _ => unreachable!(),
+            }
+        };
+
+        let expected = [
+            (
+                MarkdownFlavor::Gfm,
+                "\n<details>\n<summary><h2>Setup</h2></summary>\n\n\n\n</details>\n\n",
+            ),
+            (
+                MarkdownFlavor::Mdx,
+                "\n<details>\n<summary><h2>Setup</h2></summary>\n\n\n\n</details>\n\n",
+            ),
+            (MarkdownFlavor::Obsidian, "\n> [!note]- Setup\n\n"),
+            (
+                MarkdownFlavor::Hugo,
+                "\n{{< details summary=\"Setup\" >}}\n\n\n\n{{< /details >}}\n\n",
+            ),
+        ];
+        for (flavor, markdown) in expected {
+            let renderer = MarkdownRenderer::new(flavored(flavor));
+            let cx = RenderContext {
+                context: &context,
+                renderer: &renderer,
+                link_metadata: &link_metadata,
+            };
+            assert_eq!(
+                renderer.heading_2(&heading(true), &cx).await.unwrap(),
+                markdown,
+                "{:?}",
+                flavor
+            );
+            assert_eq!(
+                renderer.heading_2(&heading(false), &cx).await.unwrap(),
+                "\n## Setup\n\n"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn to_dos_are_task_list_items() {
+        let context = context().await;
+        let renderer = MarkdownRenderer::new(flavored(MarkdownFlavor::Gfm));
+        let link_metadata = HashMap::new();
+        let cx = RenderContext {
+            context: &context,
+            renderer: &renderer,
+            link_metadata: &link_metadata,
+        };
+        let to_do = |checked: bool| {
+            let block = block(
+                "to_do",
+                json!({ "rich_text": [text("Buy milk")], "checked": checked, "color": "default" }),
+            );
+            match block {
+                Block::ToDo(to_do) => to_do,
+                _ => unreachable!(),
+            }
+        };
+
+        assert_eq!(
+            renderer.to_do(&to_do(true), &cx).await.unwrap(),
+            "- [x] Buy milk\n"
+        );
+        assert_eq!(
+            renderer.to_do(&to_do(false), &cx).await.unwrap(),
+            "- [ ] Buy milk\n"
+        );
+    }
+
+    fn numbered(content: &str, list_start_index: Option<u32>, list_format: Option<&str>) -> Block {
+        block(
+            "numbered_list_item",
+            json!({ "rich_text": [text(content)], "color": "default",
+                "list_start_index": list_start_index, "list_format": list_format }),
+        )
+    }
+
+    fn paragraph(content: &str) -> Block {
+        block(
+            "paragraph",
+            json!({ "rich_text": [text(content)], "color": "default" }),
+        )
+    }
+
+    async fn render_blocks(blocks: &[Block], flavor: MarkdownFlavor) -> String {
+        let context = context().await;
+        let renderer = MarkdownRenderer::new(flavored(flavor));
+        let link_metadata = HashMap::new();
+        let cx = RenderContext {
+            context: &context,
+            renderer: &renderer,
+            link_metadata: &link_metadata,
+        };
+        renderer.blocks(blocks, &cx).await.unwrap()
+    }
+
+    /// The start numbers of the ordered lists in `markdown`.
+    fn list_starts(markdown: &str) -> Vec<u64> {
+        Parser::new(markdown)
+            .filter_map(|event| match event {
+                Event::Start(pulldown_cmark::Tag::List(Some(start))) => Some(start),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn numbering_restarts_after_an_interruption() {
+        let blocks = [
+            numbered("one", None, None),
+            numbered("two", None, None),
+            paragraph("Then"),
+            numbered("again", None, None),
+            numbered("and again", None, None),
+        ];
+        let markdown = render_blocks(&blocks, MarkdownFlavor::Gfm).await;
+        assert_eq!(
+            markdown,
+            "1. one\n2. two\n\nThen\n\n1. again\n2. and again\n"
+        );
+        assert_eq!(list_starts(&markdown), [1, 1]);
+    }
+
+    #[tokio::test]
+    async fn lists_start_at_their_start_index() {
+        let blocks = [
+            paragraph("Steps"),
+            numbered("three", Some(3), None),
+            numbered("four", None, None),
+        ];
+        let markdown = render_blocks(&blocks, MarkdownFlavor::CommonMark).await;
+        assert_eq!(markdown, "Steps\n\n3. three\n4. four\n");
+        assert_eq!(list_starts(&markdown), [3]);
+    }
+
+    #[tokio::test]
+    async fn lettered_lists_fall_back_to_html_where_it_renders() {
+        let blocks = [
+            numbered("first", Some(2), Some("letters")),
+            numbered("*second*", None, None),
+            paragraph("After"),
+            numbered("i", None, Some("roman")),
+        ];
+
+        let gfm = render_blocks(&blocks, MarkdownFlavor::Gfm).await;
+        assert_eq!(
+            gfm,
+            "<ol type='a' start='2'>\n<li>\n\nfirst\n\n</li>\n<li>\n\n\\*second\\*\n\n</li>\n</ol>\n\n\
+             After\n\n<ol type='i' start='1'>\n<li>\n\ni\n\n</li>\n</ol>\n"
+        );
+        let html: String = Parser::new(&gfm)
+            .filter_map(|event| match event {
+                Event::Html(html) => Some(html.to_string()),
+                _ => None,
+            })
+            .collect();
+        assert!(html.starts_with("<ol type='a' start='2'>\n<li>\n"));
+
+        let mdx = render_blocks(&blocks, MarkdownFlavor::Mdx).await;
+        assert!(mdx.starts_with("<ol type='a' start={2}>\n"));
+
+        // Obsidian shows HTML as it is, so the list is numbered instead.
+        let obsidian = render_blocks(&blocks, MarkdownFlavor::Obsidian).await;
+        assert!(obsidian.starts_with("2. first\n3. \\*second\\*\n"));
+    }
+
+    #[test]
+    fn list_children_are_indented_by_the_marker_width() {
+        assert_eq!(
+            render_list_item("10. ", "item\nmore", "- child\n  - grandchild\n"),
+            "10. item\n    more\n    - child\n      - grandchild\n"
+        );
+        assert_eq!(
+            render_list_item("- ", "item", "\n1. child\n\n   text\n"),
+            "- item\n  1. child\n\n     text\n"
+        );
+    }
+
+    #[test]
+    fn columns_are_a_grid_or_flattened() {
+        let columns = [String::from("Left\n"), String::from("Right\n")];
+
+        let grid = render_columns(&columns, &flavored(MarkdownFlavor::Gfm));
+        assert!(grid.starts_with("\n<div class='notion-column-list' style='display: grid; "));
+        assert!(grid.contains("<div class='notion-column'>\n\nLeft\n\n</div>"));
+
+        let jsx = render_columns(&columns, &flavored(MarkdownFlavor::Mdx));
+        assert!(jsx.contains("className='notion-column-list' style={{ display: 'grid'"));
+
+        let options = MarkdownOptions {
+            column_separator: Some(String::from("---")),
+            ..flavored(MarkdownFlavor::Hugo)
+        };
+        assert_eq!(
+            render_columns(&columns, &options),
+            "\nLeft\n\n---\n\nRight\n\n"
+        );
+    }
+
+    #[test]
+    fn code_fences_outlast_the_code() {
+        let code: CodeField = serde_json::from_value(json!({
+            "rich_text": [text("Use ```rust fences.\n")],
+            "caption": [text("README.md")],
+            "language": "markdown"
+        }))
+        .unwrap();
+
+        assert_eq!(
+            render_code(&code, &flavored(MarkdownFlavor::Gfm)),
+            "\n````markdown\nUse ```rust fences.\n````\n\n*README.md*\n\n"
+        );
+        assert_eq!(
+            render_code(&code, &flavored(MarkdownFlavor::Mdx)),
+            "\n````markdown title=\"README.md\"\nUse ```rust fences.\n````\n\n"
+        );
+
+        let markdown = render_code(&code, &flavored(MarkdownFlavor::Gfm));
+        let events: Vec<Event> = Parser::new(&markdown).collect();
+        assert!(events.contains(&Event::Text("Use ```rust fences.\n".into())));
+    }
+
+    #[test]
+    fn tables_always_have_a_header_row() {
+        let rows = [text_row(&["", "Q1"]), text_row(&["Sales", "a|b\nc"])];
+        let plain_cell = |cell: &[RichTextElement]| -> String {
+            cell.iter().map(|element| element.to_plaintext()).collect()
+        };
+        let table = |has_column_header, has_row_header| TableField {
+            table_width: 2,
+            has_column_header,
+            has_row_header,
+        };
+        let options = flavored(MarkdownFlavor::Gfm);
+
+        assert_eq!(
+            render_table(&table(true, true), &rows, &options, plain_cell),
+            "|  | Q1 |\n| --- | --- |\n| Sales | a\\|b<br>c |\n"
+        );
+        assert_eq!(
+            render_table(&table(false, false), &rows, &options, plain_cell),
+            "|  |  |\n| --- | --- |\n|  | Q1 |\n| Sales | a\\|b<br>c |\n"
+        );
+    }
+
+    #[test]
+    fn html_tables_keep_the_markdown_of_their_cells() {
+        let row = |cells: &[&str]| {
+            table_row(
+                cells
+                    .iter()
+                    .map(|cell| json!([styled_text(cell, &["bold"], None)]))
+                    .collect(),
+            )
+        };
+        let rows = [row(&["Region", ""]), row(&["Sales", "a\nb"])];
+        let table = TableField {
+            table_width: 2,
+            has_column_header: true,
+            has_row_header: true,
+        };
+        let options = flavored(MarkdownFlavor::CommonMark);
+        // Stands in for the renderer, whose Markdown is what ends up in the cells.
+        let bold_cell = |cell: &[RichTextElement]| -> String {
+            cell.iter()
+                .map(|element| element.to_plaintext())
+                .filter(|text| !text.is_empty())
+                .map(|text| format!("**{}**", text))
+                .collect()
+        };
+
+        let markdown = render_table_in_html(&table, &rows, &options, bold_cell);
+        assert!(
+            markdown.starts_with("<table class='notion-table'><colgroup>"),
+            "{}",
+            markdown
+        );
+        let html = {
+            let mut html = String::new();
+            pulldown_cmark::html::push_html(&mut html, Parser::new(&markdown));
+            html
+        };
+        let fragment = scraper::Html::parse_fragment(&html);
+        let select = |selector: &str| -> Vec<String> {
+            fragment
+                .select(&scraper::Selector::parse(selector).unwrap())
+                .map(|element| element.inner_html().trim().to_string())
+                .collect()
+        };
+        assert_eq!(select("thead th > p > strong"), vec!["Region"], "{}", html);
+        assert_eq!(select("tbody th[scope=row] > p > strong"), vec!["Sales"]);
+        assert_eq!(select("tbody td > p > strong"), vec!["a<br>b"]);
+        assert_eq!(select("td, th").len(), 4);
+    }
+
+    #[test]
+    fn images_use_their_caption_as_alt_text() {
+        let image: ImageField = serde_json::from_value(json!({
+            "caption": [text("The [old] logo")],
+            "type": "external",
+            "external": { "url": "https://example.com/logo.png" }
+        }))
+        .unwrap();
+        let markdown = render_image(&image, MarkdownFlavor::Gfm);
+        assert_eq!(
+            markdown,
+            "\n![The \\[old\\] logo](https://example.com/logo.png)\n\n*The \\[old\\] logo*\n\n"
+        );
+
+        let events: Vec<Event> = Parser::new(&markdown).collect();
+        assert!(events.iter().any(|event| matches!(
+            event,
+            Event::Start(pulldown_cmark::Tag::Image { dest_url, .. })
+                if dest_url.as_ref() == "https://example.com/logo.png"
+        )));
+    }
+
+    #[test]
+    fn link_cards_escape_what_the_linked_page_says() {
+        let metadata = LinkMetadata {
+            title: Some(String::from("<img src=x onerror=alert(1)> {props}")),
+            description: Some(String::from("Line one\n# not a heading *really*")),
+            ..LinkMetadata::default()
+        };
+        let caption = rich_text("[see](x)");
+
+        assert_eq!(
+            render_link_card(
+                "https://example.com/a b",
+                &caption,
+                &metadata,
+                MarkdownFlavor::Mdx
+            ),
+            "\n[\\<img src=x onerror=alert(1)\\> \\{props\\}](https://example.com/a%20b) \
+             - Line one # not a heading \\*really\\*\n\n*\\[see\\](x)*\n\n"
+        );
+
+        let markdown =
+            render_link_card("https://example.com/", &[], &metadata, MarkdownFlavor::Gfm);
+        assert!(!Parser::new(&markdown)
+            .any(|event| matches!(event, Event::Html(_) | Event::InlineHtml(_))));
+
+        // Links with a refused scheme are left as text.
+        assert_eq!(
+            render_link("javascript:alert(1)", &[], MarkdownFlavor::Gfm),
+            "\njavascript:alert(1)\n\n"
+        );
+        assert_eq!(
+            render_link_card(
+                "javascript:alert(1)",
+                &[],
+                &LinkMetadata::default(),
+                MarkdownFlavor::Gfm
+            ),
+            "\njavascript:alert(1)\n\n"
+        );
+    }
+}
@@ -0,0 +1,205 @@
+use lambda_runtime::Error;
+use serde_json::Value;
+
+use crate::context::option_from_event;
+use crate::helpers::escape::{is_css_class_prefix, is_css_identifier};
+use crate::models::common::Color;
+use crate::options::{DarkMode, StylesheetOptions};
+
+/// Generates the stylesheet for the color classes of `convert_page_to_html`:
+/// block colors (`notion-blue`, `notion-blue-background`, ...) and inline colors
+/// (`notion-rich-text notion-blue`, ...), in light and dark palettes that follow Notion.
+pub fn generate_stylesheet_command(event: Value) -> Result<String, Error> {
+    let options: StylesheetOptions = option_from_event(&event, "stylesheet_options")?;
+    if !is_css_class_prefix(&options.class_prefix) {
+        return Err(Error::from(format!(
+            "The class_prefix {:?} may only contain letters, digits, '-' and '_', and not start with a digit",
+            options.class_prefix
+        )));
+    }
+    if !is_css_identifier(&options.dark_class) {
+        return Err(Error::from(format!(
+            "The dark_class {:?} must be a CSS identifier: letters, digits, '-' and '_', not starting with a digit",
+            options.dark_class
+        )));
+    }
+    Ok(generate_stylesheet(&options))
+}
+
+/// `class_prefix` and `dark_class` are written into selectors as they are,
+/// so they must pass [`is_css_class_prefix`] and [`is_css_identifier`].
+pub fn generate_stylesheet(options: &StylesheetOptions) -> String {
+    let mut css = String::from("/* Notion colors, light palette */\n");
+    css.push_str(&color_rules("", &options.class_prefix, light_palette));
+
+    match options.dark_mode {
+        DarkMode::Media => {
+            css.push_str("\n/* Notion colors, dark palette */\n");
+            css.push_str("@media (prefers-color-scheme: dark) {\n");
+            for line in color_rules("", &options.class_prefix, dark_palette).lines() {
+                css.push_str(&format!("  {}\n", line));
+            }
+            css.push_str("}\n");
+        }
+        DarkMode::Class => {
+            css.push_str("\n/* Notion colors, dark palette */\n");
+            css.push_str(&color_rules(
+                &format!(".{} ", options.dark_class),
+                &options.class_prefix,
+                dark_palette,
+            ));
+        }
+        DarkMode::None => {}
+    }
+
+    css
+}
+
+fn color_rules(scope: &str, prefix: &str, palette: fn(Color) -> Option<&'static str>) -> String {
+    let mut css = String::new();
+    for color in Color::ALL {
+        if let Some(value) = palette(color) {
+            let property = if color.is_background() {
+                "background-color"
+            } else {
+                "color"
+            };
+            css.push_str(&format!(
+                "{scope}.{prefix}{class}, {scope}.{prefix}rich-text.{prefix}{class} {{ {property}: {value}; }}\n",
+                scope = scope,
+                prefix = prefix,
+                class = color.class_name(),
+                property = property,
+                value = value
+            ));
+        }
+    }
+    css
+}
+
+fn light_palette(color: Color) -> Option<&'static str> {
+    let value = match color {
+        Color::Default => return None,
+        Color::Gray => "#787774",
+        Color::Brown => "#9f6b53",
+        Color::Orange => "#d9730d",
+        Color::Yellow => "#cb912f",
+        Color::Green => "#448361",
+        Color::Blue => "#337ea9",
+        Color::Purple => "#9065b0",
+        Color::Pink => "#c14c8a",
+        Color::Red => "#d44c47",
+        Color::GrayBackground => "#f1f1ef",
+        Color::BrownBackground => "#f4eeee",
+        Color::OrangeBackground => "#fbecdd",
+        Color::YellowBackground => "#fbf3db",
+        Color::GreenBackground => "#edf3ec",
+        Color::BlueBackground => "#e7f3f8",
+        Color::PurpleBackground => "#f6f3f9",
+        Color::PinkBackground => "#faf1f5",
+        Color::RedBackground => "#fdebec",
+    };
+    Some(value)
+}
+
+fn dark_palette(color: Color) -> Option<&'static str> {
+    let value = match color {
+        Color::Default => return None,
+        Color::Gray => "#9b9b9b",
+        Color::Brown => "#ba856f",
+        Color::Orange => "#c77d48",
+        Color::Yellow => "#ca9849",
+        Color::Green => "#529e72",
+        Color::Blue => "#5e87c9",
+        Color::Purple => "#9d68d3",
+        Color::Pink => "#d15796",
+        Color::Red => "#df5452",
+        Color::GrayBackground => "#2f2f2f",
+        Color::BrownBackground => "#4a3228",
+        Color::OrangeBackground => "#5c3b23",
+        Color::YellowBackground => "#564328",
+        Color::GreenBackground => "#243d30",
+        Color::BlueBackground => "#143a4e",
+        Color::PurpleBackground => "#3c2d49",
+        Color::PinkBackground => "#4e2c3c",
+        Color::RedBackground => "#522e2a",
+    };
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rules(css: &str, selector: &str) -> usize {
+        css.lines()
+            .filter(|line| line.trim_start().starts_with(selector))
+            .count()
+    }
+
+    #[test]
+    fn every_color_has_a_light_and_a_dark_rule() {
+        let css = generate_stylesheet(&StylesheetOptions {
+            dark_mode: DarkMode::Class,
+            ..StylesheetOptions::default()
+        });
+
+        for color in Color::ALL {
+            let light = format!(".notion-{}, ", color.class_name());
+            let dark = format!(".notion-dark .notion-{}, ", color.class_name());
+            let expected = usize::from(color != Color::Default);
+            assert_eq!(rules(&css, &light), expected, "{:?}", color);
+            assert_eq!(rules(&css, &dark), expected, "{:?}", color);
+        }
+        assert!(css.contains(
+            ".notion-blue-background, .notion-rich-text.notion-blue-background { background-color: #e7f3f8; }"
+        ));
+        assert!(css.contains(
+            ".notion-dark .notion-red, .notion-dark .notion-rich-text.notion-red { color: #df5452; }"
+        ));
+    }
+
+    #[test]
+    fn the_dark_palette_follows_the_dark_mode() {
+        let css = |dark_mode| {
+            generate_stylesheet(&StylesheetOptions {
+                dark_mode,
+                class_prefix: String::from("n-"),
+                ..StylesheetOptions::default()
+            })
+        };
+
+        let media = css(DarkMode::Media);
+        assert!(media.contains("@media (prefers-color-scheme: dark) {\n  .n-blue, .n-rich-text.n-blue { color: #5e87c9; }\n"));
+        assert_eq!(rules(&media, ".n-gray,"), 2);
+
+        let none = css(DarkMode::None);
+        assert!(!none.contains("dark palette"));
+        assert_eq!(rules(&none, ".n-gray,"), 1);
+    }
+
+    #[test]
+    fn refuses_class_names_that_are_not_identifiers() {
+        let command = |options| {
+            generate_stylesheet_command(json!({ "stylesheet_options": options }))
+                .map_err(|e| e.to_string())
+        };
+
+        assert!(command(json!({ "class_prefix": "", "dark_class": "dark_mode-2" })).is_ok());
+        for options in [
+            json!({ "class_prefix": "x{} body{" }),
+            json!({ "class_prefix": "a b" }),
+            json!({ "class_prefix": "1" }),
+            json!({ "class_prefix": "-1" }),
+            json!({ "dark_class": "" }),
+            json!({ "dark_class": "</style>" }),
+            json!({ "dark_class": "a.b" }),
+            json!({ "dark_class": "1a" }),
+            json!({ "dark_class": "-1" }),
+            json!({ "dark_class": "-" }),
+        ] {
+            assert!(command(options.clone()).is_err(), "{}", options);
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use lambda_runtime::Error;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::helpers::asset_localizer::AssetLocalizer;
+use crate::helpers::asset_store::build_asset_store;
+use crate::helpers::fetch_link_metadata::LinkMetadata;
+use crate::helpers::link_metadata_cache::LinkMetadataCache;
+use crate::helpers::link_preview::{collect_link_urls, resolve_link_preview_mentions};
+use crate::helpers::link_rewriter::LinkRewriter;
+use crate::helpers::mention_resolver::MentionResolver;
+use crate::helpers::responsive_image::ResponsiveImage;
+use crate::models::block::Block;
+use crate::models::objects::{FileObject, IconObject};
+use crate::models::page::Page;
+use crate::options::{
+    AssetOptions, FetchOptions, LinkOptions, MentionOptions, MetadataCacheOptions,
+};
+
+/// ## ConvertContext - Struct
+///
+/// Everything a conversion needs besides the blocks themselves.
+/// One context is created per invocation and shared by the recursive calls for child blocks.
+pub struct ConvertContext {
+    pub notion_api_key: String,
+    pub fetch_options: FetchOptions,
+    /// The fetch options of the assets, for looking up the sizes of Notion-hosted files.
+    pub file_fetch_options: FetchOptions,
+    pub metadata_cache: LinkMetadataCache,
+    pub mention_resolver: MentionResolver,
+    pub link_rewriter: LinkRewriter,
+    /// `None` when no asset store is configured.
+    pub asset_localizer: Option<AssetLocalizer>,
+    /// The sizes of files that were not localized, by URL.
+    file_sizes: Mutex<HashMap<String, u64>>,
+}
+
+impl ConvertContext {
+    pub async fn from_event(event: &Value) -> Result<Self, Error> {
+        let notion_api_key = event["NOTION_API_KEY"]
+            .as_str()
+            .ok_or_else(|| Error::from("The NOTION_API_KEY field is missing or not a string"))?;
+
+        let fetch_options: FetchOptions = option_from_event(event, "fetch_options")?;
+        let metadata_cache_options: MetadataCacheOptions =
+            option_from_event(event, "metadata_cache_options")?;
+        let mention_options: MentionOptions = option_from_event(event, "mention_options")?;
+        let link_options: LinkOptions = option_from_event(event, "link_options")?;
+        let asset_options: AssetOptions = option_from_event(event, "asset_options")?;
+
+        let asset_localizer = match &asset_options.store {
+            Some(store_options) => Some(AssetLocalizer::new(
+                build_asset_store(store_options).await,
+                asset_options.max_bytes,
+                asset_options.images.clone(),
+                asset_options.fetch_options(&fetch_options),
+            )),
+            None => None,
+        };
+
+        Ok(ConvertContext {
+            notion_api_key: notion_api_key.to_string(),
+            file_fetch_options: asset_options.fetch_options(&fetch_options),
+            fetch_options,
+            metadata_cache: LinkMetadataCache::new(metadata_cache_options).await,
+            link_rewriter: LinkRewriter::new(
+                link_options,
+                mention_options.page_url_template.clone(),
+            ),
+            mention_resolver: MentionResolver::new(notion_api_key, mention_options),
+            asset_localizer,
+            file_sizes: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Looks up everything `blocks` link to before they are rendered: Notion-hosted files
+    /// (copied to the asset store), the sizes of files, the targets of mentions (filled into
+    /// the rich text in place), links to Notion pages (rewritten in place), and the metadata
+    /// of bookmarks and link previews (returned by URL).
+    pub async fn resolve_blocks(&self, blocks: &mut [Block]) -> HashMap<String, LinkMetadata> {
+        if let Some(asset_localizer) = &self.asset_localizer {
+            asset_localizer.localize(blocks).await;
+        }
+        self.resolve_file_sizes(blocks).await;
+        self.mention_resolver.resolve(blocks).await;
+        self.link_rewriter.rewrite(blocks);
+        let link_metadata = self
+            .metadata_cache
+            .get_all(collect_link_urls(blocks), &self.fetch_options)
+            .await;
+        resolve_link_preview_mentions(blocks, &link_metadata);
+        link_metadata
+    }
+
+    /// Points the cover and icon of `page` at localized copies, like the files in blocks.
+    pub async fn resolve_page(&self, page: &mut Page) {
+        let Some(asset_localizer) = &self.asset_localizer else {
+            return;
+        };
+        if let Some(FileObject::File { file }) = &mut page.cover {
+            asset_localizer.localize_file(file, true).await;
+        }
+        if let Some(IconObject::File { file }) = &mut page.icon {
+            asset_localizer.localize_file(file, true).await;
+        }
+    }
+
+    /// Returns the resized copies of an image that was localized during [`Self::resolve_blocks`].
+    pub fn responsive_image(&self, url: &str) -> Option<ResponsiveImage> {
+        self.asset_localizer
+            .as_ref()
+            .and_then(|asset_localizer| asset_localizer.responsive_image(url))
+    }
+
+    /// Looks up the sizes of file blocks that were not localized, through the metadata cache:
+    /// Notion-hosted files with the fetch options of the assets, and others with those of links.
+    async fn resolve_file_sizes(&self, blocks: &[Block]) {
+        let urls = blocks.iter().filter_map(|block| match block {
+            Block::File(block) => {
+                let file = &block.file.file;
+                if self.localized_file_size(file.url()).is_some() {
+                    return None;
+                }
+                match file {
+                    FileObject::File { file } => {
+                        Some((file.url.as_str(), &self.file_fetch_options))
+                    }
+                    FileObject::External { external } => {
+                        Some((external.url.as_str(), &self.fetch_options))
+                    }
+                }
+            }
+            _ => None,
+        });
+        let file_sizes = self.metadata_cache.get_file_sizes(urls).await;
+        self.file_sizes.lock().unwrap().extend(file_sizes);
+    }
+
+    fn localized_file_size(&self, url: &str) -> Option<u64> {
+        self.asset_localizer
+            .as_ref()
+            .and_then(|asset_localizer| asset_localizer.file_size(url))
+    }
+
+    /// Returns the size of a file in bytes: as recorded when it was localized,
+    /// or else as looked up during [`Self::resolve_blocks`].
+    pub fn file_size(&self, url: &str) -> Option<u64> {
+        self.localized_file_size(url)
+            .or_else(|| self.file_sizes.lock().unwrap().get(url).copied())
+    }
+
+    /// Logs the links to Notion pages that could not be rewritten to our site.
+    pub fn report_unresolved_links(&self) {
+        for url in self.link_rewriter.unresolved() {
+            println!("Unresolved Notion link: {}", url);
+        }
+    }
+}
+
+/// Reads an optional options object from the event, falling back to its default.
+pub fn option_from_event<T: DeserializeOwned + Default>(
+    event: &Value,
+    key: &str,
+) -> Result<T, Error> {
+    match event.get(key) {
+        Some(value) => Ok(serde_json::from_value(value.clone())?),
+        None => Ok(T::default()),
+    }
+}
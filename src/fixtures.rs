@@ -0,0 +1,96 @@
+//! Notion API objects for tests, shaped as the API returns them.
+
+use serde_json::{json, Value};
+
+use crate::context::ConvertContext;
+use crate::models::block::Block;
+use crate::models::rich_text::RichTextElement;
+
+/// The annotations of text with all of `styles` set, e.g. `&["bold", "code"]`.
+pub fn annotations(styles: &[&str]) -> Value {
+    let mut annotations = json!({
+        "bold": false,
+        "italic": false,
+        "strikethrough": false,
+        "underline": false,
+        "code": false,
+        "color": "default"
+    });
+    for style in styles {
+        annotations[*style] = json!(true);
+    }
+    annotations
+}
+
+/// A text element with all of `styles` and, if given, a link.
+pub fn styled_text(content: &str, styles: &[&str], href: Option<&str>) -> Value {
+    json!({
+        "type": "text",
+        "text": { "content": content, "link": href.map(|url| json!({ "url": url })) },
+        "annotations": annotations(styles),
+        "plain_text": content,
+        "href": href
+    })
+}
+
+/// A text element without annotations.
+pub fn text(content: &str) -> Value {
+    styled_text(content, &[], None)
+}
+
+/// A run of rich text of one text element without annotations.
+pub fn rich_text(content: &str) -> Vec<RichTextElement> {
+    serde_json::from_value(json!([text(content)])).unwrap()
+}
+
+/// A mention element of `mention`, e.g. `{ "type": "page", "page": { "id": "p1" } }`.
+pub fn mention(mention: Value, plain_text: &str, href: Option<&str>) -> Value {
+    json!({
+        "type": "mention",
+        "mention": mention,
+        "annotations": annotations(&[]),
+        "plain_text": plain_text,
+        "href": href
+    })
+}
+
+/// The JSON of a block without children of `type`, whose field of that name is `content`.
+pub fn block_json(r#type: &str, content: Value) -> Value {
+    let mut block = json!({
+        "object": "block",
+        "id": "b",
+        "parent": { "type": "page_id", "database_id": null, "page_id": "p",
+            "workspace": null, "block_id": null },
+        "created_time": "2024-01-01T00:00:00.000Z",
+        "last_edited_time": "2024-01-01T00:00:00.000Z",
+        "created_by": { "object": "user", "id": "u" },
+        "last_edited_by": { "object": "user", "id": "u" },
+        "has_children": false,
+        "archived": false,
+        "type": r#type,
+    });
+    block[r#type] = content;
+    block
+}
+
+/// [`block_json`] as a block.
+pub fn block(r#type: &str, content: Value) -> Block {
+    serde_json::from_value(block_json(r#type, content)).unwrap()
+}
+
+/// A table row whose cells are arrays of rich text.
+pub fn table_row(cells: Vec<Value>) -> Block {
+    block("table_row", json!({ "cells": cells }))
+}
+
+/// A table row of one text element per cell.
+pub fn text_row(cells: &[&str]) -> Block {
+    table_row(cells.iter().map(|cell| json!([text(cell)])).collect())
+}
+
+/// A context that makes no requests unless a test renders blocks with children.
+pub async fn context() -> ConvertContext {
+    ConvertContext::from_event(&json!({ "NOTION_API_KEY": "key" }))
+        .await
+        .unwrap()
+}
@@ -1,2 +1,20 @@
-pub mod fetch_title;
+pub mod asset_localizer;
+pub mod asset_store;
+pub mod embed;
+pub mod escape;
+pub mod fetch_file_size;
+pub mod fetch_link_metadata;
+pub mod fetcher;
+pub mod front_matter;
 pub mod get_all_blocks;
+pub mod get_database;
+pub mod get_page;
+pub mod get_user;
+pub mod html_document;
+pub mod inline_assets;
+pub mod inline_markdown;
+pub mod link_metadata_cache;
+pub mod link_preview;
+pub mod link_rewriter;
+pub mod mention_resolver;
+pub mod responsive_image;
@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use futures::future::join_all;
+use lambda_runtime::Error;
+use reqwest::{header, Url};
+use sha2::{Digest, Sha256};
+
+use crate::helpers::asset_store::AssetStore;
+use crate::helpers::fetcher::build_client;
+use crate::helpers::responsive_image::{
+    process_image, ImageSource, ProcessedImage, ResponsiveImage,
+};
+use crate::models::block::Block;
+use crate::models::objects::{File, FileObject};
+use crate::options::{FetchOptions, ImageOptions};
+
+/// ## AssetLocalizer - Struct
+///
+/// Copies Notion-hosted files (whose URLs expire after an hour) into an [`AssetStore`]
+/// and points the blocks at the copies. Files that cannot be copied keep their Notion URL.
+/// With `image_options`, images also get resized copies and a placeholder.
+pub struct AssetLocalizer {
+    store: Box<dyn AssetStore>,
+    max_bytes: usize,
+    image_options: Option<ImageOptions>,
+    fetch_options: FetchOptions,
+    /// Localized URLs by Notion URL without its signature, which changes on every request.
+    localized: Mutex<HashMap<String, String>>,
+    /// The sizes of the copies in bytes, by localized URL.
+    file_sizes: Mutex<HashMap<String, u64>>,
+    /// Processed images by localized URL.
+    responsive_images: Mutex<HashMap<String, ResponsiveImage>>,
+}
+
+impl AssetLocalizer {
+    pub fn new(
+        store: Box<dyn AssetStore>,
+        max_bytes: usize,
+        image_options: Option<ImageOptions>,
+        fetch_options: FetchOptions,
+    ) -> Self {
+        AssetLocalizer {
+            store,
+            max_bytes,
+            image_options,
+            fetch_options,
+            localized: Mutex::new(HashMap::new()),
+            file_sizes: Mutex::new(HashMap::new()),
+            responsive_images: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the resized copies of a localized image, if it was processed.
+    pub fn responsive_image(&self, url: &str) -> Option<ResponsiveImage> {
+        self.responsive_images.lock().unwrap().get(url).cloned()
+    }
+
+    /// Returns the size in bytes of a localized file.
+    pub fn file_size(&self, url: &str) -> Option<u64> {
+        self.file_sizes.lock().unwrap().get(url).copied()
+    }
+
+    /// Localizes the files of image, file, video and PDF blocks concurrently.
+    pub async fn localize(&self, blocks: &mut [Block]) {
+        let mut urls: Vec<(String, bool)> = blocks
+            .iter_mut()
+            .filter_map(|block| {
+                let is_image = matches!(block, Block::Image(_));
+                match block.file_mut()? {
+                    FileObject::File { file } => Some((file.url.clone(), is_image)),
+                    FileObject::External { .. } => None,
+                }
+            })
+            .collect();
+        urls.sort_unstable();
+        urls.dedup();
+
+        join_all(
+            urls.iter()
+                .map(|(url, is_image)| self.localize_url(url, *is_image)),
+        )
+        .await;
+
+        let localized = self.localized.lock().unwrap();
+        for block in blocks.iter_mut() {
+            if let Some(FileObject::File { file }) = block.file_mut() {
+                if let Some(url) = localized.get(&unsigned_url(&file.url)) {
+                    file.url = url.clone();
+                }
+            }
+        }
+    }
+
+    /// Localizes a single file outside of a block, such as a page cover.
+    pub async fn localize_file(&self, file: &mut File, is_image: bool) {
+        self.localize_url(&file.url, is_image).await;
+        if let Some(url) = self.localized.lock().unwrap().get(&unsigned_url(&file.url)) {
+            file.url = url.clone();
+        }
+    }
+
+    async fn localize_url(&self, url: &str, is_image: bool) {
+        let unsigned = unsigned_url(url);
+        if self.localized.lock().unwrap().contains_key(&unsigned) {
+            return;
+        }
+
+        match self.copy(url, is_image).await {
+            Ok(localized_url) => {
+                self.localized
+                    .lock()
+                    .unwrap()
+                    .insert(unsigned, localized_url);
+            }
+            Err(e) => println!("Failed to localize {}: {}", unsigned, e),
+        }
+    }
+
+    async fn copy(&self, url: &str, is_image: bool) -> Result<String, Error> {
+        let url = Url::parse(url)?;
+        let client = build_client(&url, &self.fetch_options).await?;
+        let mut response = client.get(url.clone()).send().await?;
+
+        if !response.status().is_success() {
+            return Err(Error::from(format!(
+                "Unexpected status {}",
+                response.status()
+            )));
+        }
+
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string());
+
+        let mut bytes: Vec<u8> = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            bytes.extend_from_slice(&chunk);
+            if bytes.len() > self.max_bytes {
+                return Err(Error::from(format!("Larger than {} bytes", self.max_bytes)));
+            }
+        }
+
+        let hash = content_hash(&bytes);
+        let file_size = bytes.len() as u64;
+
+        let processed = match &self.image_options {
+            Some(image_options) if is_image => {
+                // Decoding and resizing is CPU-bound, so it must not hold up the other downloads.
+                let image_options = image_options.clone();
+                let (returned_bytes, processed) = tokio::task::spawn_blocking(move || {
+                    let processed = process_image(&bytes, &image_options);
+                    (bytes, processed)
+                })
+                .await?;
+                bytes = returned_bytes;
+                processed
+            }
+            _ => None,
+        };
+
+        let key = match extension(&url, content_type.as_deref()) {
+            Some(extension) => format!("{}.{}", hash, extension),
+            None => hash.clone(),
+        };
+        let localized_url = self.store.put(&key, bytes, content_type.as_deref()).await?;
+        self.file_sizes
+            .lock()
+            .unwrap()
+            .insert(localized_url.clone(), file_size);
+
+        if let (Some(processed), Some(image_options)) = (processed, &self.image_options) {
+            let responsive_image = self
+                .store_variants(&hash, &localized_url, processed, &image_options.sizes)
+                .await?;
+            self.responsive_images
+                .lock()
+                .unwrap()
+                .insert(localized_url.clone(), responsive_image);
+        }
+
+        Ok(localized_url)
+    }
+
+    /// Stores the resized copies of an image as `<hash>-<width>w.<extension>`.
+    async fn store_variants(
+        &self,
+        hash: &str,
+        localized_url: &str,
+        processed: ProcessedImage,
+        sizes: &str,
+    ) -> Result<ResponsiveImage, Error> {
+        let mut sources = Vec::new();
+        let mut webp_sources = Vec::new();
+
+        for variant in processed.variants {
+            let key = format!("{}-{}w.{}", hash, variant.width, variant.extension);
+            let is_webp = variant.is_webp();
+            let source = ImageSource {
+                url: self
+                    .store
+                    .put(&key, variant.bytes, Some(variant.content_type))
+                    .await?,
+                width: variant.width,
+            };
+            if is_webp {
+                webp_sources.push(source);
+            } else {
+                sources.push(source);
+            }
+        }
+
+        // Without resized copies the original is all there is, and `srcset` would say nothing.
+        if !sources.is_empty() {
+            sources.push(ImageSource {
+                url: localized_url.to_string(),
+                width: processed.width,
+            });
+        }
+
+        Ok(ResponsiveImage {
+            width: processed.width,
+            height: processed.height,
+            sources,
+            webp_sources,
+            sizes: sizes.to_string(),
+            placeholder: processed.placeholder,
+        })
+    }
+}
+
+/// Assets are named after the SHA-256 of their content and keep their file extension,
+/// e.g. `3a7bd3e2360a3d29eea436fcfb7e44c735d117c42d1c1835420b6b9942dd4f1b.png`.
+fn content_hash(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+fn extension(url: &Url, content_type: Option<&str>) -> Option<String> {
+    let from_path = url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .and_then(|file_name| file_name.rsplit_once('.'))
+        .map(|(_, extension)| extension.to_ascii_lowercase())
+        .filter(|extension| {
+            (1..=5).contains(&extension.len())
+                && extension.chars().all(|c| c.is_ascii_alphanumeric())
+        });
+
+    from_path.or_else(|| {
+        let mime_type = content_type?.split(';').next()?.trim().to_ascii_lowercase();
+        let extension = match mime_type.as_str() {
+            "image/png" => "png",
+            "image/jpeg" => "jpg",
+            "image/gif" => "gif",
+            "image/webp" => "webp",
+            "image/svg+xml" => "svg",
+            "video/mp4" => "mp4",
+            "video/webm" => "webm",
+            "video/quicktime" => "mov",
+            "application/pdf" => "pdf",
+            "application/zip" => "zip",
+            "text/plain" => "txt",
+            _ => return None,
+        };
+        Some(extension.to_string())
+    })
+}
+
+/// Notion file URLs are pre-signed S3 URLs. The path identifies the file,
+/// the query string only carries the signature.
+fn unsigned_url(url: &str) -> String {
+    url.split('?').next().unwrap_or(url).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::AssetOptions;
+
+    #[test]
+    fn notion_urls_are_compared_without_their_signature() {
+        assert_eq!(
+            unsigned_url(
+                "https://prod-files-secure.s3.us-west-2.amazonaws.com/w/f/cat.png?X-Amz-Signature=1"
+            ),
+            "https://prod-files-secure.s3.us-west-2.amazonaws.com/w/f/cat.png"
+        );
+        assert_eq!(
+            unsigned_url("https://example.com/cat.png"),
+            "https://example.com/cat.png"
+        );
+    }
+
+    #[test]
+    fn keys_are_the_content_hash_and_extension() {
+        assert_eq!(
+            content_hash(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        let url = |url: &str| Url::parse(url).unwrap();
+        let cases = [
+            ("https://example.com/a/Cat.PNG?sig=1", None, Some("png")),
+            (
+                "https://example.com/report.pdf",
+                Some("text/html"),
+                Some("pdf"),
+            ),
+            (
+                "https://example.com/photo",
+                Some("image/jpeg; q=1"),
+                Some("jpg"),
+            ),
+            ("https://example.com/archive.tar.gz", None, Some("gz")),
+            (
+                "https://example.com/notes.markdown",
+                Some("text/plain"),
+                Some("txt"),
+            ),
+            ("https://example.com/x.p-g", None, None),
+            (
+                "https://example.com/file",
+                Some("application/octet-stream"),
+                None,
+            ),
+            ("https://example.com/file", None, None),
+        ];
+        for (address, content_type, expected) in cases {
+            assert_eq!(
+                extension(&url(address), content_type).as_deref(),
+                expected,
+                "{}",
+                address
+            );
+        }
+    }
+
+    #[test]
+    fn downloads_use_the_asset_limits_and_page_denials() {
+        let page_options = FetchOptions {
+            timeout_ms: 1_000,
+            allowed_hosts: vec![String::from("example.com")],
+            denied_hosts: vec![String::from("evil.example")],
+            ..FetchOptions::default()
+        };
+        let asset_options = AssetOptions::default();
+        let fetch_options = asset_options.fetch_options(&page_options);
+
+        assert_eq!(fetch_options.timeout_ms, asset_options.timeout_ms);
+        assert_eq!(fetch_options.max_body_bytes, asset_options.max_bytes);
+        assert!(fetch_options
+            .allowed_hosts
+            .contains(&String::from("amazonaws.com")));
+        assert_eq!(fetch_options.denied_hosts, page_options.denied_hosts);
+        assert_eq!(fetch_options.user_agent, page_options.user_agent);
+    }
+}
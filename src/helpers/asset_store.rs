@@ -0,0 +1,187 @@
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use aws_config::{BehaviorVersion, Region};
+use aws_sdk_s3::primitives::ByteStream;
+use lambda_runtime::Error;
+
+use crate::options::AssetStoreOptions;
+
+/// Where localized assets are kept. Keys are content-hash file names,
+/// so an asset that already exists under its key never has to be written again.
+#[async_trait]
+pub trait AssetStore: Send + Sync {
+    /// Stores `bytes` under `key` unless it is already stored, and returns its public URL.
+    async fn put(
+        &self,
+        key: &str,
+        bytes: Vec<u8>,
+        content_type: Option<&str>,
+    ) -> Result<String, Error>;
+}
+
+/// Creates the store described by `options`.
+pub async fn build_asset_store(options: &AssetStoreOptions) -> Box<dyn AssetStore> {
+    match options {
+        AssetStoreOptions::Local {
+            directory,
+            base_url,
+        } => Box::new(LocalAssetStore::new(directory, base_url)),
+        AssetStoreOptions::S3 {
+            bucket,
+            prefix,
+            region,
+            endpoint_url,
+            public_base_url,
+            force_path_style,
+        } => {
+            let mut loader = aws_config::defaults(BehaviorVersion::latest());
+            if let Some(region) = region {
+                loader = loader.region(Region::new(region.clone()));
+            }
+            let sdk_config = loader.load().await;
+
+            let mut config =
+                aws_sdk_s3::config::Builder::from(&sdk_config).force_path_style(*force_path_style);
+            if let Some(endpoint_url) = endpoint_url {
+                config = config.endpoint_url(endpoint_url);
+            }
+
+            let public_base_url = match (public_base_url, endpoint_url) {
+                (Some(public_base_url), _) => public_base_url.clone(),
+                (None, Some(endpoint_url)) => {
+                    format!("{}/{}", endpoint_url.trim_end_matches('/'), bucket)
+                }
+                (None, None) => format!(
+                    "https://{}.s3.{}.amazonaws.com",
+                    bucket,
+                    sdk_config
+                        .region()
+                        .map(|region| region.as_ref())
+                        .unwrap_or("us-east-1")
+                ),
+            };
+
+            Box::new(S3AssetStore {
+                client: aws_sdk_s3::Client::from_conf(config.build()),
+                bucket: bucket.clone(),
+                prefix: prefix.clone(),
+                public_base_url,
+            })
+        }
+    }
+}
+
+/// ## LocalAssetStore - Struct
+///
+/// Writes assets into a directory, e.g. the output directory of a static site.
+pub struct LocalAssetStore {
+    directory: PathBuf,
+    base_url: String,
+}
+
+impl LocalAssetStore {
+    pub fn new(directory: impl Into<PathBuf>, base_url: &str) -> Self {
+        LocalAssetStore {
+            directory: directory.into(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl AssetStore for LocalAssetStore {
+    async fn put(
+        &self,
+        key: &str,
+        bytes: Vec<u8>,
+        _content_type: Option<&str>,
+    ) -> Result<String, Error> {
+        let path = self.directory.join(key);
+        if !path.exists() {
+            tokio::fs::create_dir_all(&self.directory).await?;
+            tokio::fs::write(&path, bytes).await?;
+        }
+        Ok(format!("{}/{}", self.base_url, key))
+    }
+}
+
+/// ## S3AssetStore - Struct
+///
+/// Uploads assets to an S3 bucket (or an S3-compatible stand-in).
+/// Objects are immutable, so they are served with a long `Cache-Control`.
+pub struct S3AssetStore {
+    client: aws_sdk_s3::Client,
+    bucket: String,
+    prefix: String,
+    public_base_url: String,
+}
+
+#[async_trait]
+impl AssetStore for S3AssetStore {
+    async fn put(
+        &self,
+        key: &str,
+        bytes: Vec<u8>,
+        content_type: Option<&str>,
+    ) -> Result<String, Error> {
+        let key = format!("{}{}", self.prefix, key);
+
+        let exists = self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(&key)
+            .send()
+            .await
+            .is_ok();
+
+        if !exists {
+            self.client
+                .put_object()
+                .bucket(&self.bucket)
+                .key(&key)
+                .body(ByteStream::from(bytes))
+                .set_content_type(content_type.map(|content_type| content_type.to_string()))
+                .cache_control("public, max-age=31536000, immutable")
+                .send()
+                .await?;
+        }
+
+        Ok(format!(
+            "{}/{}",
+            self.public_base_url.trim_end_matches('/'),
+            key
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn local_store_writes_each_key_once() {
+        let directory = std::env::temp_dir().join(format!("asset-store-{}", std::process::id()));
+        let store = LocalAssetStore::new(&directory, "https://example.com/assets/");
+
+        let url = store
+            .put("abc.png", b"first".to_vec(), Some("image/png"))
+            .await
+            .unwrap();
+        assert_eq!(url, "https://example.com/assets/abc.png");
+
+        // Keys are content hashes, so an existing file is never rewritten.
+        let url = store
+            .put("abc.png", b"second".to_vec(), None)
+            .await
+            .unwrap();
+        assert_eq!(url, "https://example.com/assets/abc.png");
+        assert_eq!(
+            tokio::fs::read(directory.join("abc.png")).await.unwrap(),
+            b"first"
+        );
+
+        tokio::fs::remove_dir_all(&directory).await.unwrap();
+    }
+}
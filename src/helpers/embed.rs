@@ -0,0 +1,192 @@
+use reqwest::Url;
+
+/// Hosts that embeds are allowed from unless the caller configures otherwise.
+pub const DEFAULT_EMBED_ALLOWED_HOSTS: [&str; 5] = [
+    "youtube.com",
+    "youtu.be",
+    "youtube-nocookie.com",
+    "vimeo.com",
+    "loom.com",
+];
+
+/// How an embedded URL should be presented.
+#[derive(Debug, PartialEq, Eq)]
+pub enum EmbedKind {
+    /// A hosted player that should be shown in an iframe pointing at `src`.
+    Player { provider: &'static str, src: String },
+    /// A direct link to a video file that a `<video>` element can play.
+    VideoFile,
+    /// Anything else.
+    Unknown,
+}
+
+/// Detects YouTube, Vimeo and Loom URLs and maps them to their
+/// privacy-enhanced player URLs, and recognizes direct video files.
+pub fn detect_embed(url: &str) -> EmbedKind {
+    let url = match Url::parse(url) {
+        Ok(url) => url,
+        Err(_) => return EmbedKind::Unknown,
+    };
+
+    let host = url.host_str().unwrap_or_default();
+    let segments: Vec<&str> = url
+        .path_segments()
+        .map(|segments| segments.filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+
+    if host_matches(host, "youtu.be") {
+        if let Some(id) = segments.first() {
+            return youtube_player(id);
+        }
+    }
+
+    if host_matches(host, "youtube.com") || host_matches(host, "youtube-nocookie.com") {
+        let id = match segments.as_slice() {
+            ["watch"] => url
+                .query_pairs()
+                .find(|(key, _)| key == "v")
+                .map(|(_, value)| value.into_owned()),
+            ["embed" | "shorts" | "live" | "v", id, ..] => Some(id.to_string()),
+            _ => None,
+        };
+        if let Some(id) = id {
+            return youtube_player(&id);
+        }
+    }
+
+    if host_matches(host, "vimeo.com") {
+        let id = match segments.as_slice() {
+            ["video", id, ..] => Some(*id),
+            [.., id] if id.chars().all(|c| c.is_ascii_digit()) => Some(*id),
+            _ => None,
+        };
+        if let Some(id) = id {
+            return EmbedKind::Player {
+                provider: "vimeo",
+                src: format!("https://player.vimeo.com/video/{}?dnt=1", id),
+            };
+        }
+    }
+
+    if host_matches(host, "loom.com") {
+        if let ["share" | "embed", id, ..] = segments.as_slice() {
+            return EmbedKind::Player {
+                provider: "loom",
+                src: format!("https://www.loom.com/embed/{}", id),
+            };
+        }
+    }
+
+    if is_video_file(url.path()) {
+        return EmbedKind::VideoFile;
+    }
+
+    EmbedKind::Unknown
+}
+
+/// Returns `true` when the host of `url` is one of `allowed_hosts` or a subdomain of one.
+pub fn is_allowed_host(url: &str, allowed_hosts: &[String]) -> bool {
+    let url = match Url::parse(url) {
+        Ok(url) => url,
+        Err(_) => return false,
+    };
+    let host = url.host_str().unwrap_or_default();
+    allowed_hosts
+        .iter()
+        .any(|allowed_host| host_matches(host, allowed_host))
+}
+
+fn youtube_player(id: &str) -> EmbedKind {
+    EmbedKind::Player {
+        provider: "youtube",
+        src: format!("https://www.youtube-nocookie.com/embed/{}", id),
+    }
+}
+
+fn host_matches(host: &str, domain: &str) -> bool {
+    let host = host.to_ascii_lowercase();
+    let domain = domain.to_ascii_lowercase();
+    host == domain || host.ends_with(&format!(".{}", domain))
+}
+
+fn is_video_file(path: &str) -> bool {
+    let path = path.to_ascii_lowercase();
+    [".mp4", ".webm", ".ogv", ".ogg", ".mov", ".m4v"]
+        .iter()
+        .any(|extension| path.ends_with(extension))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player(provider: &'static str, src: &str) -> EmbedKind {
+        EmbedKind::Player {
+            provider,
+            src: src.to_string(),
+        }
+    }
+
+    #[test]
+    fn maps_players_to_their_privacy_enhanced_urls() {
+        let youtube = player("youtube", "https://www.youtube-nocookie.com/embed/abc");
+        for url in [
+            "https://www.youtube.com/watch?v=abc&t=10",
+            "https://youtu.be/abc",
+            "https://m.youtube.com/shorts/abc",
+            "https://www.youtube.com/embed/abc",
+            "https://www.youtube-nocookie.com/embed/abc",
+        ] {
+            assert_eq!(detect_embed(url), youtube, "{}", url);
+        }
+
+        let vimeo = player("vimeo", "https://player.vimeo.com/video/123?dnt=1");
+        assert_eq!(detect_embed("https://vimeo.com/123"), vimeo);
+        assert_eq!(detect_embed("https://vimeo.com/channels/staff/123"), vimeo);
+        assert_eq!(detect_embed("https://player.vimeo.com/video/123"), vimeo);
+
+        assert_eq!(
+            detect_embed("https://www.loom.com/share/xyz?sid=1"),
+            player("loom", "https://www.loom.com/embed/xyz")
+        );
+    }
+
+    #[test]
+    fn recognizes_video_files_and_nothing_else() {
+        assert_eq!(
+            detect_embed("https://cdn.example.com/clip.MP4?token=1"),
+            EmbedKind::VideoFile
+        );
+        for url in [
+            "https://www.youtube.com/feed/trending",
+            "https://vimeo.com/about",
+            "https://example.com/clip.mp4.html",
+            "not a url",
+        ] {
+            assert_eq!(detect_embed(url), EmbedKind::Unknown, "{}", url);
+        }
+    }
+
+    #[test]
+    fn allows_hosts_and_their_subdomains_only() {
+        let allowed_hosts = [String::from("youtube.com"), String::from("Vimeo.com")];
+        assert!(is_allowed_host(
+            "https://youtube.com/watch?v=a",
+            &allowed_hosts
+        ));
+        assert!(is_allowed_host(
+            "https://www.YouTube.com/watch?v=a",
+            &allowed_hosts
+        ));
+        assert!(is_allowed_host(
+            "https://player.vimeo.com/video/1",
+            &allowed_hosts
+        ));
+        assert!(!is_allowed_host("https://notyoutube.com/", &allowed_hosts));
+        assert!(!is_allowed_host(
+            "https://youtube.com.evil.example/",
+            &allowed_hosts
+        ));
+        assert!(!is_allowed_host("javascript:alert(1)", &allowed_hosts));
+    }
+}
@@ -0,0 +1,249 @@
+/// Schemes that rendered links and sources may use. Relative URLs are always allowed.
+const ALLOWED_SCHEMES: [&str; 3] = ["http", "https", "mailto"];
+
+/// Escapes text so that it can be placed in HTML content or a quoted attribute value.
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Reverses [`escape_html`].
+pub fn unescape_html(text: &str) -> String {
+    text.replace("&#39;", "'")
+        .replace("&quot;", "\"")
+        .replace("&gt;", ">")
+        .replace("&lt;", "<")
+        .replace("&amp;", "&")
+}
+
+/// Returns `url` escaped for an attribute value, or `None` when its scheme is not allowed
+/// (e.g. `javascript:` or `data:`).
+///
+/// The scheme is read the way browsers read it: surrounding control characters and spaces
+/// are ignored and tabs and newlines are dropped, so `" java\tscript:"` is still `javascript:`.
+pub fn sanitize_url(url: &str) -> Option<String> {
+    let url: String = url
+        .trim_matches(|c: char| c.is_ascii_control() || c == ' ')
+        .chars()
+        .filter(|c| !matches!(c, '\t' | '\n' | '\r'))
+        .collect();
+
+    match url_scheme(&url) {
+        Some(scheme) if !ALLOWED_SCHEMES.contains(&scheme.to_ascii_lowercase().as_str()) => None,
+        _ => Some(escape_html(&url)),
+    }
+}
+
+/// Whether `url` leaves the site: an absolute or protocol-relative http(s) URL.
+pub fn is_external_url(url: &str) -> bool {
+    url.starts_with("//")
+        || url_scheme(url)
+            .map(|scheme| {
+                scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https")
+            })
+            .unwrap_or(false)
+}
+
+/// Renders the `href` of a link, with `rel='noopener noreferrer'` and `target` for external links.
+/// Returns `None` when the URL is not allowed, in which case no link should be rendered.
+pub fn link_attributes(url: &str, target: Option<&str>) -> Option<String> {
+    let href = sanitize_url(url)?;
+    let mut attributes = format!(" href='{}'", href);
+    if is_external_url(url.trim()) {
+        attributes.push_str(" rel='noopener noreferrer'");
+        if let Some(target) = target {
+            attributes.push_str(&format!(" target='{}'", escape_html(target)));
+        }
+    }
+    Some(attributes)
+}
+
+/// Whether `name` can be written as an attribute name as it is. Event handlers (`on*`)
+/// and attributes that take URLs are refused, since their values would bypass [`sanitize_url`],
+/// and so are `srcdoc` (a whole document) and `style` (which can load URLs and cover the page).
+pub fn is_safe_attribute_name(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':' | '.'))
+        && !name.starts_with("on")
+        && ![
+            "href",
+            "src",
+            "srcdoc",
+            "srcset",
+            "action",
+            "formaction",
+            "data",
+            "poster",
+            "xlink:href",
+            "style",
+        ]
+        .contains(&name.as_str())
+}
+
+/// Whether `name` can be written into a stylesheet as a class selector as it is:
+/// letters, digits, `-`, `_` and non-ASCII characters only,
+/// not empty and not starting with a digit, `-` and a digit, or a lone `-`.
+pub fn is_css_identifier(name: &str) -> bool {
+    let is_name_start = |c: char| c.is_ascii_alphabetic() || c == '_' || !c.is_ascii();
+    let mut chars = name.chars();
+    let starts_well = match chars.next() {
+        Some('-') => matches!(chars.next(), Some(c) if is_name_start(c) || c == '-'),
+        Some(c) => is_name_start(c),
+        None => false,
+    };
+    starts_well
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || !c.is_ascii())
+}
+
+/// Whether `prefix` can be put before class names in a stylesheet,
+/// i.e. whether it makes an identifier of them. It may be empty.
+pub fn is_css_class_prefix(prefix: &str) -> bool {
+    is_css_identifier(&format!("{}x", prefix))
+}
+
+/// Returns the scheme of an absolute URL, or `None` for a relative one.
+fn url_scheme(url: &str) -> Option<&str> {
+    let (scheme, _) = url.split_once(':')?;
+    let mut chars = scheme.chars();
+    let is_scheme = chars.next()?.is_ascii_alphabetic()
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    if is_scheme {
+        Some(scheme)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use scraper::{Html, Selector};
+
+    #[test]
+    fn allows_web_mail_and_relative_urls() {
+        assert_eq!(
+            sanitize_url("https://example.com/?a=1&b=2").as_deref(),
+            Some("https://example.com/?a=1&amp;b=2")
+        );
+        assert!(sanitize_url("HTTP://example.com").is_some());
+        assert!(sanitize_url("mailto:someone@example.com").is_some());
+        assert!(sanitize_url("/posts/1#heading").is_some());
+        assert!(sanitize_url("./a:b").is_some());
+        assert!(sanitize_url("#top").is_some());
+    }
+
+    #[test]
+    fn refuses_other_schemes() {
+        for url in [
+            "javascript:alert(1)",
+            "JavaScript:alert(1)",
+            " \u{1}javascript:alert(1)",
+            "java\tscript:alert(1)",
+            "java\nscript:alert(1)",
+            "data:text/html,<script>alert(1)</script>",
+            "vbscript:msgbox(1)",
+            "file:///etc/passwd",
+        ] {
+            assert_eq!(sanitize_url(url), None, "{:?}", url);
+        }
+    }
+
+    #[test]
+    fn adds_rel_and_target_to_external_links_only() {
+        assert_eq!(
+            link_attributes("https://example.com", Some("_blank")).as_deref(),
+            Some(" href='https://example.com' rel='noopener noreferrer' target='_blank'")
+        );
+        assert_eq!(
+            link_attributes("/about", Some("_blank")).as_deref(),
+            Some(" href='/about'")
+        );
+        assert_eq!(link_attributes("javascript:alert(1)", None), None);
+    }
+
+    #[test]
+    fn refuses_handlers_and_url_attributes() {
+        for name in ["role", "data-id", "aria-label", "x:y", "title"] {
+            assert!(is_safe_attribute_name(name), "{:?}", name);
+        }
+        for name in [
+            "onclick", "ONLOAD", "href", "SRC", "srcdoc", "style", "Style", "", "1a", "a b", "a'b",
+            "a=b",
+        ] {
+            assert!(!is_safe_attribute_name(name), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn accepts_css_identifiers_only() {
+        for name in ["a", "notion-", "_x", "-a", "--a", "a1-2_b", "é1"] {
+            assert!(is_css_identifier(name), "{:?}", name);
+        }
+        for name in ["", "-", "1a", "-1", "-1a", "a b", "a.b", "a{", "</style>"] {
+            assert!(!is_css_identifier(name), "{:?}", name);
+        }
+
+        for prefix in ["", "notion-", "-", "n_"] {
+            assert!(is_css_class_prefix(prefix), "{:?}", prefix);
+        }
+        for prefix in ["1", "-1", "a b"] {
+            assert!(!is_css_class_prefix(prefix), "{:?}", prefix);
+        }
+    }
+
+    proptest! {
+        /// Whatever the value, an escaped attribute ends where it was meant to:
+        /// the element keeps exactly its own attributes and the value reads back unchanged,
+        /// in a browser as well as with `unescape_html`.
+        #[test]
+        fn escaped_values_stay_inside_their_attribute(value in "[^\u{0}\r]*") {
+            let html = format!(
+                "<div><a title='{}' data-x=\"{}\">{}</a></div>",
+                escape_html(&value),
+                escape_html(&value),
+                escape_html(&value)
+            );
+            let fragment = Html::parse_fragment(&html);
+            let links: Vec<_> = fragment.select(&Selector::parse("a").unwrap()).collect();
+
+            prop_assert_eq!(links.len(), 1);
+            prop_assert_eq!(links[0].value().attrs().count(), 2);
+            prop_assert_eq!(links[0].value().attr("title"), Some(value.as_str()));
+            prop_assert_eq!(links[0].value().attr("data-x"), Some(value.as_str()));
+            prop_assert_eq!(links[0].text().collect::<String>(), value.clone());
+            prop_assert_eq!(fragment.select(&Selector::parse("*").unwrap()).count(), 3);
+            prop_assert_eq!(unescape_html(&escape_html(&value)), value.clone());
+        }
+
+        /// Whatever the URL, a link either has an allowed scheme or is not rendered.
+        #[test]
+        fn links_only_use_allowed_schemes(
+            scheme in "(javascript|data|vbscript|https|mailto|[a-z]{1,8})",
+            separator in "[ \t\n\u{1}]{0,2}",
+            rest in "[^\u{0}\r]*",
+        ) {
+            let url = format!("{}{}:{}", separator, scheme, rest);
+            if let Some(attributes) = link_attributes(&url, Some("_blank")) {
+                let html = format!("<a{}>link</a>", attributes);
+                let fragment = Html::parse_fragment(&html);
+                let link = fragment.select(&Selector::parse("a").unwrap()).next().unwrap();
+                let href = link.value().attr("href").unwrap();
+
+                let href_scheme = href.split(':').next().unwrap().to_ascii_lowercase();
+                prop_assert!(ALLOWED_SCHEMES.contains(&href_scheme.as_str()));
+                prop_assert!(link
+                    .value()
+                    .attrs()
+                    .all(|(name, _)| ["href", "rel", "target"].contains(&name)));
+            }
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use lambda_runtime::Error;
+use reqwest::{header, Url};
+
+use crate::helpers::fetcher::build_client;
+use crate::options::FetchOptions;
+
+/// Returns the size of the file at `url` in bytes, if the server reports it.
+///
+/// A HEAD request is tried first. Presigned S3 URLs (Notion-hosted files) are only
+/// signed for GET, so a ranged GET for the first byte is used as a fallback.
+pub async fn fetch_file_size(url: &str, options: &FetchOptions) -> Result<Option<u64>, Error> {
+    let url = Url::parse(url)?;
+    let client = build_client(&url, options).await?;
+
+    let response = client.head(url.clone()).send().await?;
+    if response.status().is_success() {
+        let content_length = response
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok());
+        if content_length.is_some() {
+            return Ok(content_length);
+        }
+    }
+
+    let response = client
+        .get(url)
+        .header(header::RANGE, "bytes=0-0")
+        .send()
+        .await?;
+
+    // e.g. `Content-Range: bytes 0-0/12345`
+    let total_size = response
+        .headers()
+        .get(header::CONTENT_RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.rsplit_once('/'))
+        .and_then(|(_, total)| total.parse::<u64>().ok());
+
+    Ok(total_size)
+}
+
+/// Formats a byte count for humans, e.g. `1.2 MB`.
+pub fn format_file_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[unit])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_sizes_in_binary_units() {
+        assert_eq!(format_file_size(0), "0 B");
+        assert_eq!(format_file_size(1023), "1023 B");
+        assert_eq!(format_file_size(1024), "1.0 KB");
+        assert_eq!(format_file_size(1536), "1.5 KB");
+        assert_eq!(format_file_size(5 * 1024 * 1024), "5.0 MB");
+        assert_eq!(format_file_size(3 * 1024u64.pow(5)), "3072.0 TB");
+    }
+}
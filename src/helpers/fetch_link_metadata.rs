@@ -0,0 +1,111 @@
+use lambda_runtime::Error;
+use reqwest::Url;
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+
+use crate::helpers::fetcher::fetch_document_head;
+use crate::options::FetchOptions;
+
+/// ## LinkMetadata - Struct
+///
+/// The Open Graph (or plain HTML) metadata of a linked page.
+/// URLs are already resolved against the page URL.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+pub struct LinkMetadata {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub image: Option<String>,
+    pub site_name: Option<String>,
+    pub favicon: Option<String>,
+}
+
+pub async fn fetch_link_metadata(url: &str, options: &FetchOptions) -> Result<LinkMetadata, Error> {
+    let document = fetch_document_head(url, options).await?;
+    Ok(parse_link_metadata(&document.html, &document.url))
+}
+
+pub fn parse_link_metadata(html: &str, base_url: &Url) -> LinkMetadata {
+    let document = Html::parse_document(html);
+
+    // A `<base href>` changes what relative URLs on the page are relative to.
+    let base_url = select_attr(&document, "base[href]", "href")
+        .and_then(|href| base_url.join(&href).ok())
+        .unwrap_or_else(|| base_url.clone());
+
+    let title = select_meta(&document, &["og:title", "twitter:title"]).or_else(|| {
+        let selector = Selector::parse("title").unwrap();
+        document
+            .select(&selector)
+            .next()
+            .map(|e| e.text().collect::<String>())
+            .and_then(non_empty)
+    });
+
+    let description = select_meta(
+        &document,
+        &["og:description", "twitter:description", "description"],
+    );
+
+    let image = select_meta(
+        &document,
+        &[
+            "og:image",
+            "og:image:url",
+            "twitter:image",
+            "twitter:image:src",
+        ],
+    )
+    .and_then(|image| resolve_url(&base_url, &image));
+
+    let site_name = select_meta(&document, &["og:site_name", "application-name"]);
+
+    let favicon = [
+        "link[rel~='icon']",
+        "link[rel='shortcut icon']",
+        "link[rel='apple-touch-icon']",
+    ]
+    .iter()
+    .find_map(|selector| select_attr(&document, selector, "href"))
+    .unwrap_or_else(|| String::from("/favicon.ico"));
+    let favicon = resolve_url(&base_url, &favicon);
+
+    LinkMetadata {
+        title,
+        description,
+        image,
+        site_name,
+        favicon,
+    }
+}
+
+/// Looks up `<meta property='...'>` and `<meta name='...'>` tags in order of preference.
+fn select_meta(document: &Html, keys: &[&str]) -> Option<String> {
+    keys.iter().find_map(|key| {
+        select_attr(
+            document,
+            &format!("meta[property='{}'], meta[name='{}']", key, key),
+            "content",
+        )
+    })
+}
+
+fn select_attr(document: &Html, selector: &str, attr: &str) -> Option<String> {
+    let selector = Selector::parse(selector).unwrap();
+    document
+        .select(&selector)
+        .find_map(|e| e.value().attr(attr).map(|value| value.to_string()))
+        .and_then(non_empty)
+}
+
+fn resolve_url(base_url: &Url, url: &str) -> Option<String> {
+    base_url.join(url).ok().map(|url| url.to_string())
+}
+
+fn non_empty(value: String) -> Option<String> {
+    let value = value.trim();
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
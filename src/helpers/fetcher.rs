@@ -0,0 +1,389 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use encoding_rs::{Encoding, UTF_8};
+use lambda_runtime::Error;
+use reqwest::{header, redirect::Policy, Client, Response, Url};
+
+use crate::options::FetchOptions;
+
+/// ## FetchedDocument - Struct
+///
+/// The decoded beginning of an HTML document, up to and including `</head>`.
+pub struct FetchedDocument {
+    /// The URL the document was finally served from, after redirects.
+    pub url: Url,
+    pub html: String,
+}
+
+/// Fetches the `<head>` of an HTML document for link previews.
+///
+/// Every hop (including redirects) is checked against the host allow/deny lists,
+/// and the host is resolved up front so that private, loopback and link-local
+/// addresses can be refused. The connection is then pinned to the checked
+/// addresses, so a second DNS answer cannot point it somewhere else.
+pub async fn fetch_document_head(
+    url: &str,
+    options: &FetchOptions,
+) -> Result<FetchedDocument, Error> {
+    let mut url = Url::parse(url)?;
+
+    for _ in 0..=options.max_redirects {
+        let client = build_client(&url, options).await?;
+
+        let mut response = client
+            .get(url.clone())
+            .header(
+                header::ACCEPT,
+                "text/html,application/xhtml+xml;q=0.9,*/*;q=0.1",
+            )
+            .send()
+            .await?;
+
+        if response.status().is_redirection() {
+            let location = response
+                .headers()
+                .get(header::LOCATION)
+                .and_then(|value| value.to_str().ok())
+                .ok_or_else(|| Error::from(format!("Redirect without a location: {}", url)))?;
+            url = url.join(location)?;
+            continue;
+        }
+
+        if !response.status().is_success() {
+            return Err(Error::from(format!(
+                "Unexpected status {} from {}",
+                response.status(),
+                url
+            )));
+        }
+
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_ascii_lowercase());
+
+        if let Some(content_type) = &content_type {
+            if !content_type.contains("html") {
+                return Err(Error::from(format!(
+                    "Not an HTML document ({}): {}",
+                    content_type, url
+                )));
+            }
+        }
+
+        let body = read_head(&mut response, options.max_body_bytes).await?;
+        let html = decode(&body, content_type.as_deref());
+
+        return Ok(FetchedDocument { url, html });
+    }
+
+    Err(Error::from(format!("Too many redirects: {}", url)))
+}
+
+/// Builds a client whose connections to the host of `url` are pinned to
+/// addresses that passed [`check_url`].
+pub async fn build_client(url: &Url, options: &FetchOptions) -> Result<Client, Error> {
+    let addrs = check_url(url, options).await?;
+    let host = url.host_str().unwrap_or_default();
+
+    let client = Client::builder()
+        .connect_timeout(Duration::from_millis(options.connect_timeout_ms))
+        .timeout(Duration::from_millis(options.timeout_ms))
+        .user_agent(&options.user_agent)
+        .redirect(Policy::none())
+        .no_proxy()
+        .resolve_to_addrs(host, &addrs)
+        .build()?;
+
+    Ok(client)
+}
+
+/// Validates the scheme and host of `url` and resolves it,
+/// refusing to return any address that is not publicly routable.
+pub async fn check_url(url: &Url, options: &FetchOptions) -> Result<Vec<SocketAddr>, Error> {
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(Error::from(format!("Unsupported scheme: {}", url)));
+    }
+
+    let host = url
+        .host_str()
+        .ok_or_else(|| Error::from(format!("URL has no host: {}", url)))?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_ascii_lowercase();
+
+    if options
+        .denied_hosts
+        .iter()
+        .any(|denied_host| host_matches(&host, denied_host))
+    {
+        return Err(Error::from(format!("Host is denied: {}", host)));
+    }
+
+    if !options.allowed_hosts.is_empty()
+        && !options
+            .allowed_hosts
+            .iter()
+            .any(|allowed_host| host_matches(&host, allowed_host))
+    {
+        return Err(Error::from(format!("Host is not allowed: {}", host)));
+    }
+
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), port))
+        .await?
+        .collect();
+
+    if addrs.is_empty() {
+        return Err(Error::from(format!("Host did not resolve: {}", host)));
+    }
+
+    if let Some(addr) = addrs.iter().find(|addr| !is_public_ip(addr.ip())) {
+        return Err(Error::from(format!(
+            "Host {} resolves to a non-public address: {}",
+            host,
+            addr.ip()
+        )));
+    }
+
+    Ok(addrs)
+}
+
+/// Reads the body until `</head>` has been seen or `max_bytes` have been read.
+async fn read_head(response: &mut Response, max_bytes: usize) -> Result<Vec<u8>, Error> {
+    let mut body: Vec<u8> = Vec::new();
+
+    while let Some(chunk) = response.chunk().await? {
+        // Search from slightly before the new chunk so a tag split across chunks is found.
+        let search_from = body.len().saturating_sub(6);
+        body.extend_from_slice(&chunk);
+
+        if body.len() >= max_bytes {
+            body.truncate(max_bytes);
+            break;
+        }
+
+        if let Some(end) = find_head_end(&body[search_from..]) {
+            body.truncate(search_from + end);
+            break;
+        }
+    }
+
+    Ok(body)
+}
+
+fn find_head_end(bytes: &[u8]) -> Option<usize> {
+    const HEAD_END: &[u8] = b"</head>";
+    bytes
+        .windows(HEAD_END.len())
+        .position(|window| window.eq_ignore_ascii_case(HEAD_END))
+        .map(|position| position + HEAD_END.len())
+}
+
+/// Decodes the body using, in order: a byte order mark, the charset of the
+/// `Content-Type` header, a `<meta>` charset declaration, and finally UTF-8.
+fn decode(body: &[u8], content_type: Option<&str>) -> String {
+    let encoding = Encoding::for_bom(body)
+        .map(|(encoding, _)| encoding)
+        .or_else(|| content_type.and_then(charset_from_content_type))
+        .or_else(|| charset_from_meta(body))
+        .unwrap_or(UTF_8);
+
+    let (html, _, _) = encoding.decode(body);
+    html.into_owned()
+}
+
+fn charset_from_content_type(content_type: &str) -> Option<&'static Encoding> {
+    content_type
+        .split(';')
+        .filter_map(|parameter| parameter.trim().strip_prefix("charset="))
+        .find_map(|charset| Encoding::for_label(charset.trim_matches('"').as_bytes()))
+}
+
+/// Looks for `<meta charset=...>` or `<meta http-equiv=... content="...; charset=...">`
+/// in the first 1024 bytes, as the HTML prescan algorithm does.
+fn charset_from_meta(body: &[u8]) -> Option<&'static Encoding> {
+    let prefix = &body[..body.len().min(1024)];
+    let prefix = String::from_utf8_lossy(prefix).to_ascii_lowercase();
+
+    prefix.match_indices("charset=").find_map(|(index, _)| {
+        let value = prefix[index + "charset=".len()..].trim_start_matches(['"', '\'']);
+        let end = value
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == ':'))
+            .unwrap_or(value.len());
+        Encoding::for_label(&value.as_bytes()[..end])
+    })
+}
+
+fn host_matches(host: &str, domain: &str) -> bool {
+    let domain = domain.to_ascii_lowercase();
+    host == domain || host.ends_with(&format!(".{}", domain))
+}
+
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => is_public_ipv6(ip),
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // "this network" 0.0.0.0/8
+        || a == 0
+        // shared address space (carrier-grade NAT) 100.64.0.0/10
+        || (a == 100 && (b & 0b1100_0000) == 64)
+        // IETF protocol assignments 192.0.0.0/24
+        || (a == 192 && b == 0 && c == 0)
+        // benchmarking 198.18.0.0/15
+        || (a == 198 && (b & 0b1111_1110) == 18)
+        // reserved 240.0.0.0/4
+        || a >= 240)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    if let Some(ipv4) = ip.to_ipv4_mapped() {
+        return is_public_ipv4(ipv4);
+    }
+
+    let segments = ip.segments();
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // unique local fc00::/7
+        || (segments[0] & 0xfe00) == 0xfc00
+        // link-local fe80::/10
+        || (segments[0] & 0xffc0) == 0xfe80
+        // site-local fec0::/10 (deprecated)
+        || (segments[0] & 0xffc0) == 0xfec0
+        // discard-only 100::/64
+        || segments[..4] == [0x0100, 0, 0, 0]
+        // documentation 2001:db8::/32
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8)
+        // The rest embed an IPv4 address, which may well be a private one:
+        // IPv4-compatible ::/96 (deprecated),
+        || segments[..6] == [0; 6]
+        // NAT64 64:ff9b::/96 (and local-use 64:ff9b:1::/48),
+        || (segments[0] == 0x0064 && segments[1] == 0xff9b)
+        // Teredo 2001::/32,
+        || (segments[0] == 0x2001 && segments[1] == 0x0000)
+        // and 6to4 2002::/16.
+        || segments[0] == 0x2002)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_non_public_ipv4_addresses() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "172.31.255.255",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "100.127.255.255",
+            "0.0.0.0",
+            "0.1.2.3",
+            "255.255.255.255",
+            "192.0.0.1",
+            "192.0.2.1",
+            "198.18.0.1",
+            "224.0.0.1",
+            "240.0.0.1",
+        ] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "8.8.8.8",
+            "1.1.1.1",
+            "100.128.0.1",
+            "172.32.0.1",
+            "198.20.0.1",
+        ] {
+            assert!(is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn refuses_non_public_ipv6_addresses() {
+        for ip in [
+            "::1",
+            "::",
+            "::ffff:127.0.0.1",
+            "::ffff:10.0.0.1",
+            "::ffff:169.254.169.254",
+            "::10.0.0.1",
+            "fc00::1",
+            "fd12:3456::1",
+            "fe80::1",
+            "fec0::1",
+            "feff::1",
+            "100::1",
+            "100::ffff:1",
+            "ff02::1",
+            "2001:db8::1",
+            "64:ff9b::a00:1",
+            "64:ff9b:1::1",
+            "2001:0:4136:e378:8000:63bf:3fff:fdd2",
+            "2002:a00:1::1",
+        ] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "2606:4700:4700::1111",
+            "2001:4860:4860::8888",
+            "::ffff:8.8.8.8",
+        ] {
+            assert!(is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn finds_the_end_of_the_head() {
+        assert_eq!(
+            find_head_end(b"<head><title>a</title></head><body>"),
+            Some(29)
+        );
+        assert_eq!(find_head_end(b"<HEAD></HEAD>"), Some(13));
+        assert_eq!(find_head_end(b"<head><title>a</title>"), None);
+        assert_eq!(find_head_end(b""), None);
+    }
+
+    #[test]
+    fn reads_the_charset_from_meta_tags() {
+        let charset = |html: &str| charset_from_meta(html.as_bytes()).map(|e| e.name());
+
+        assert_eq!(charset("<meta charset=\"Shift_JIS\">"), Some("Shift_JIS"));
+        assert_eq!(charset("<META CHARSET='iso-8859-1'>"), Some("windows-1252"));
+        assert_eq!(
+            charset("<meta http-equiv=\"Content-Type\" content=\"text/html; charset=euc-jp\">"),
+            Some("EUC-JP")
+        );
+        assert_eq!(charset("<meta charset=\"nonsense\">"), None);
+        assert_eq!(charset("<title>charset</title>"), None);
+        // Only the first 1024 bytes are scanned.
+        assert_eq!(
+            charset(&format!("{}<meta charset=\"euc-jp\">", " ".repeat(1024))),
+            None
+        );
+    }
+
+    #[test]
+    fn decodes_with_the_header_before_the_meta_tag() {
+        let body = b"<meta charset=\"euc-jp\"><title>caf\xe9</title>";
+        assert!(decode(body, Some("text/html; charset=ISO-8859-1")).contains("caf\u{e9}"));
+    }
+}
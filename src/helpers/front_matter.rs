@@ -0,0 +1,392 @@
+use serde_json::Value;
+use time::format_description::well_known::{Iso8601, Rfc3339};
+use time::format_description::{self, OwnedFormatItem};
+use time::{Date, OffsetDateTime};
+
+use crate::models::objects::IconObject;
+use crate::models::page::Page;
+use crate::options::{FrontMatterFormat, FrontMatterOptions};
+
+/// A front matter value. Both YAML and TOML can write all of these.
+#[derive(Debug, PartialEq)]
+enum FrontMatterValue {
+    String(String),
+    Number(serde_json::Number),
+    Bool(bool),
+    Array(Vec<FrontMatterValue>),
+}
+
+/// Writes the front matter of `page`, including the delimiter lines.
+///
+/// Properties are written by type: text as strings, multi-selects, people, files and
+/// relations as arrays, dates (the start of a range) as strings in `date_format`.
+/// Empty properties are left out.
+pub fn front_matter(page: &Page, options: &FrontMatterOptions) -> String {
+    let date_format = options.date_format.as_deref().and_then(|date_format| {
+        format_description::parse_owned::<1>(date_format)
+            .map_err(|e| println!("Invalid date_format {}: {}", date_format, e))
+            .ok()
+    });
+
+    let mut entries: Vec<(String, FrontMatterValue)> = Vec::new();
+
+    let properties = page.properties.as_object();
+    let names: Vec<&String> = if options.properties.is_empty() {
+        let mut names: Vec<&String> = properties
+            .map(|properties| properties.keys().collect())
+            .unwrap_or_default();
+        // The title goes first, as it does in Notion.
+        names.sort_by_key(|name| page.properties[name.as_str()]["type"] != "title");
+        names
+    } else {
+        options.properties.iter().collect()
+    };
+
+    for name in names {
+        let property = &page.properties[name.as_str()];
+        let default_key = if property["type"] == "title" {
+            "title"
+        } else {
+            name.as_str()
+        };
+        if let Some(value) = property_value(property, date_format.as_ref()) {
+            entries.push((key(options, name, default_key), value));
+        }
+    }
+
+    if options.created_time {
+        entries.push((
+            key(options, "created_time", "created_time"),
+            FrontMatterValue::String(format_date(&page.created_time, date_format.as_ref())),
+        ));
+    }
+    if options.last_edited_time {
+        entries.push((
+            key(options, "last_edited_time", "last_edited_time"),
+            FrontMatterValue::String(format_date(&page.last_edited_time, date_format.as_ref())),
+        ));
+    }
+    if let Some(cover) = page.cover.as_ref().filter(|_| options.cover) {
+        entries.push((
+            key(options, "cover", "cover"),
+            FrontMatterValue::String(cover.url().to_string()),
+        ));
+    }
+    let icon = page.icon.as_ref().and_then(|icon| match icon {
+        IconObject::Emoji { emoji } => Some(emoji.clone()),
+        IconObject::External { external } => Some(external.url.clone()),
+        IconObject::File { file } => Some(file.url.clone()),
+        IconObject::CustomEmoji { custom_emoji } => {
+            custom_emoji["url"].as_str().map(|url| url.to_string())
+        }
+    });
+    if let Some(icon) = icon.filter(|_| options.icon) {
+        entries.push((key(options, "icon", "icon"), FrontMatterValue::String(icon)));
+    }
+
+    match options.format {
+        FrontMatterFormat::Yaml => to_yaml(&entries),
+        FrontMatterFormat::Toml => to_toml(&entries),
+    }
+}
+
+fn key(options: &FrontMatterOptions, name: &str, default_key: &str) -> String {
+    options
+        .keys
+        .get(name)
+        .cloned()
+        .unwrap_or_else(|| default_key.to_string())
+}
+
+/// Converts a property value object, see https://developers.notion.com/reference/page-property-values
+fn property_value(
+    property: &Value,
+    date_format: Option<&OwnedFormatItem>,
+) -> Option<FrontMatterValue> {
+    let property_type = property["type"].as_str()?;
+    let value = &property[property_type];
+
+    let string = |value: &Value| {
+        value
+            .as_str()
+            .map(|text| FrontMatterValue::String(text.to_string()))
+    };
+    let names = |value: &Value, field: &str| {
+        let names: Vec<FrontMatterValue> = value
+            .as_array()?
+            .iter()
+            .filter_map(|item| string(&item[field]))
+            .collect();
+        Some(FrontMatterValue::Array(names))
+    };
+
+    match property_type {
+        "title" | "rich_text" => {
+            let text: String = value
+                .as_array()?
+                .iter()
+                .filter_map(|rich_text| rich_text["plain_text"].as_str())
+                .collect();
+            (!text.is_empty()).then_some(FrontMatterValue::String(text))
+        }
+        "number" => value.as_number().cloned().map(FrontMatterValue::Number),
+        "checkbox" => value.as_bool().map(FrontMatterValue::Bool),
+        "select" | "status" => string(&value["name"]),
+        "multi_select" => names(value, "name"),
+        "people" => names(value, "name"),
+        "relation" => names(value, "id"),
+        "files" => {
+            let urls: Vec<FrontMatterValue> = value
+                .as_array()?
+                .iter()
+                .filter_map(|file| {
+                    let file_type = file["type"].as_str()?;
+                    string(&file[file_type]["url"])
+                })
+                .collect();
+            Some(FrontMatterValue::Array(urls))
+        }
+        "date" => value["start"]
+            .as_str()
+            .map(|start| FrontMatterValue::String(format_date(start, date_format))),
+        "created_time" | "last_edited_time" => value
+            .as_str()
+            .map(|time| FrontMatterValue::String(format_date(time, date_format))),
+        "created_by" | "last_edited_by" => string(&value["name"]),
+        "url" | "email" | "phone_number" => string(value),
+        "unique_id" => {
+            let number = value["number"].as_u64()?;
+            Some(FrontMatterValue::String(match value["prefix"].as_str() {
+                Some(prefix) => format!("{}-{}", prefix, number),
+                None => number.to_string(),
+            }))
+        }
+        // Formulas and rollups hold a value of one of the types above.
+        "formula" | "rollup" => {
+            let inner_type = value["type"].as_str()?;
+            match inner_type {
+                "array" => {
+                    let items: Vec<FrontMatterValue> = value["array"]
+                        .as_array()?
+                        .iter()
+                        .filter_map(|item| property_value(item, date_format))
+                        .flat_map(|item| match item {
+                            FrontMatterValue::Array(items) => items,
+                            item => vec![item],
+                        })
+                        .collect();
+                    Some(FrontMatterValue::Array(items))
+                }
+                "string" => value["string"]
+                    .as_str()
+                    .filter(|text| !text.is_empty())
+                    .map(|text| FrontMatterValue::String(text.to_string())),
+                "boolean" => value["boolean"].as_bool().map(FrontMatterValue::Bool),
+                _ => property_value(value, date_format),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Formats an ISO 8601 date or date-time from Notion. Anything that cannot be parsed
+/// or formatted (such as a date-only value with a format that has a time) is kept as it is.
+fn format_date(date: &str, date_format: Option<&OwnedFormatItem>) -> String {
+    let Some(date_format) = date_format else {
+        return date.to_string();
+    };
+
+    let formatted = match OffsetDateTime::parse(date, &Rfc3339) {
+        Ok(date_time) => date_time.format(date_format).ok(),
+        Err(_) => Date::parse(date, &Iso8601::DATE)
+            .ok()
+            .and_then(|date| date.format(date_format).ok()),
+    };
+    formatted.unwrap_or_else(|| date.to_string())
+}
+
+/// Quotes a string in the double-quoted style that YAML and TOML share.
+fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04X}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Keys that need no quotes in either format.
+fn format_key(key: &str) -> String {
+    let is_bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if is_bare {
+        key.to_string()
+    } else {
+        quote(key)
+    }
+}
+
+fn format_scalar(value: &FrontMatterValue) -> String {
+    match value {
+        FrontMatterValue::String(text) => quote(text),
+        FrontMatterValue::Number(number) => number.to_string(),
+        FrontMatterValue::Bool(bool) => bool.to_string(),
+        FrontMatterValue::Array(items) => format!(
+            "[{}]",
+            items
+                .iter()
+                .map(format_scalar)
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+    }
+}
+
+fn to_yaml(entries: &[(String, FrontMatterValue)]) -> String {
+    let mut yaml = String::from("---\n");
+    for (key, value) in entries {
+        match value {
+            FrontMatterValue::Array(items) if !items.is_empty() => {
+                yaml.push_str(&format!("{}:\n", format_key(key)));
+                for item in items {
+                    yaml.push_str(&format!("  - {}\n", format_scalar(item)));
+                }
+            }
+            value => yaml.push_str(&format!("{}: {}\n", format_key(key), format_scalar(value))),
+        }
+    }
+    yaml.push_str("---\n");
+    yaml
+}
+
+fn to_toml(entries: &[(String, FrontMatterValue)]) -> String {
+    let mut toml = String::from("+++\n");
+    for (key, value) in entries {
+        toml.push_str(&format!("{} = {}\n", format_key(key), format_scalar(value)));
+    }
+    toml.push_str("+++\n");
+    toml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use yaml_rust2::YamlLoader;
+
+    fn page() -> Page {
+        let user = json!({ "object": "user", "id": "u1" });
+        serde_json::from_value(json!({
+            "object": "page",
+            "id": "p1",
+            "created_time": "2024-03-01T09:30:00.000Z",
+            "last_edited_time": "2024-03-02T18:00:00.000Z",
+            "created_by": user,
+            "last_edited_by": user,
+            "cover": { "type": "external", "external": { "url": "https://example.com/cover.png" } },
+            "icon": { "type": "emoji", "emoji": "🚀" },
+            "parent": { "type": "database_id", "database_id": "d1" },
+            "archived": false,
+            "properties": {
+                "Name": { "type": "title", "title": [
+                    { "plain_text": "Say \"hi\": " }, { "plain_text": "a\\b" }
+                ] },
+                "Tags": { "type": "multi_select", "multi_select": [
+                    { "name": "rust" }, { "name": "notion" }
+                ] },
+                "Published": { "type": "date", "date": { "start": "2024-03-05", "end": null } },
+                "Draft": { "type": "checkbox", "checkbox": false },
+                "Views": { "type": "number", "number": 42 },
+                "Slug": { "type": "rich_text", "rich_text": [{ "plain_text": "say-hi" }] },
+                "Category": { "type": "select", "select": null }
+            },
+            "url": "https://www.notion.so/p1",
+            "public_url": null
+        }))
+        .unwrap()
+    }
+
+    fn options() -> FrontMatterOptions {
+        FrontMatterOptions {
+            properties: [
+                "Name",
+                "Slug",
+                "Tags",
+                "Published",
+                "Draft",
+                "Views",
+                "Category",
+            ]
+            .map(String::from)
+            .to_vec(),
+            keys: [("Slug", "slug"), ("Tags", "tags"), ("Published", "date")]
+                .map(|(name, key)| (name.to_string(), key.to_string()))
+                .into(),
+            date_format: Some(String::from("[year]-[month]-[day]")),
+            ..FrontMatterOptions::default()
+        }
+    }
+
+    #[test]
+    fn writes_yaml() {
+        let yaml = front_matter(&page(), &options());
+        assert_eq!(
+            yaml,
+            "---\n\
+             title: \"Say \\\"hi\\\": a\\\\b\"\n\
+             slug: \"say-hi\"\n\
+             tags:\n  - \"rust\"\n  - \"notion\"\n\
+             date: \"2024-03-05\"\n\
+             Draft: false\n\
+             Views: 42\n\
+             created_time: \"2024-03-01\"\n\
+             last_edited_time: \"2024-03-02\"\n\
+             cover: \"https://example.com/cover.png\"\n\
+             icon: \"🚀\"\n\
+             ---\n"
+        );
+
+        let documents =
+            YamlLoader::load_from_str(yaml.trim_matches(|c| c == '-' || c == '\n')).unwrap();
+        assert_eq!(documents[0]["title"].as_str(), Some("Say \"hi\": a\\b"));
+        assert_eq!(documents[0]["tags"][1].as_str(), Some("notion"));
+        assert_eq!(documents[0]["Views"].as_i64(), Some(42));
+    }
+
+    #[test]
+    fn writes_toml() {
+        let options = FrontMatterOptions {
+            format: FrontMatterFormat::Toml,
+            date_format: None,
+            cover: false,
+            icon: false,
+            ..options()
+        };
+        let toml = front_matter(&page(), &options);
+        let table: toml::Table = toml
+            .trim_matches(|c| c == '+' || c == '\n')
+            .parse()
+            .unwrap();
+
+        assert_eq!(table["title"].as_str(), Some("Say \"hi\": a\\b"));
+        assert_eq!(table["tags"].as_array().unwrap().len(), 2);
+        assert_eq!(table["date"].as_str(), Some("2024-03-05"));
+        assert_eq!(table["Draft"].as_bool(), Some(false));
+        assert_eq!(
+            table["created_time"].as_str(),
+            Some("2024-03-01T09:30:00.000Z")
+        );
+        assert!(!table.contains_key("Category"));
+        assert!(!table.contains_key("cover"));
+    }
+}
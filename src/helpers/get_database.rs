@@ -0,0 +1,26 @@
+use lambda_runtime::Error;
+use serde_json::from_str;
+
+use reqwest::{header, Client};
+
+use crate::models::database::Database;
+
+pub async fn get_database(notion_api_key: &str, database_id: &str) -> Result<Database, Error> {
+    let client = Client::new();
+
+    let url = format!("https://api.notion.com/v1/databases/{}", database_id);
+
+    let response = client
+        .get(&url)
+        .header("Notion-Version", "2022-06-28")
+        .header(header::AUTHORIZATION, format!("Bearer {}", notion_api_key))
+        .send()
+        .await?;
+
+    let body = response.text().await?;
+
+    let database: Database =
+        from_str(&body).map_err(|e| lambda_runtime::Error::from(e.to_string()))?;
+
+    Ok(database)
+}
@@ -0,0 +1,25 @@
+use lambda_runtime::Error;
+use serde_json::from_str;
+
+use reqwest::{header, Client};
+
+use crate::models::page::Page;
+
+pub async fn get_page(notion_api_key: &str, page_id: &str) -> Result<Page, Error> {
+    let client = Client::new();
+
+    let url = format!("https://api.notion.com/v1/pages/{}", page_id);
+
+    let response = client
+        .get(&url)
+        .header("Notion-Version", "2022-06-28")
+        .header(header::AUTHORIZATION, format!("Bearer {}", notion_api_key))
+        .send()
+        .await?;
+
+    let body = response.text().await?;
+
+    let page: Page = from_str(&body).map_err(|e| lambda_runtime::Error::from(e.to_string()))?;
+
+    Ok(page)
+}
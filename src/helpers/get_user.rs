@@ -0,0 +1,25 @@
+use lambda_runtime::Error;
+use serde_json::from_str;
+
+use reqwest::{header, Client};
+
+use crate::models::objects::User;
+
+pub async fn get_user(notion_api_key: &str, user_id: &str) -> Result<User, Error> {
+    let client = Client::new();
+
+    let url = format!("https://api.notion.com/v1/users/{}", user_id);
+
+    let response = client
+        .get(&url)
+        .header("Notion-Version", "2022-06-28")
+        .header(header::AUTHORIZATION, format!("Bearer {}", notion_api_key))
+        .send()
+        .await?;
+
+    let body = response.text().await?;
+
+    let user: User = from_str(&body).map_err(|e| lambda_runtime::Error::from(e.to_string()))?;
+
+    Ok(user)
+}
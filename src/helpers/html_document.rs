@@ -0,0 +1,282 @@
+use crate::commands::generate_stylesheet::generate_stylesheet;
+use crate::helpers::escape::{escape_html, is_css_class_prefix, sanitize_url};
+use crate::models::page::Page;
+use crate::options::{DocumentOptions, HtmlOptions, StylesheetOptions};
+
+/// Typography and spacing for a page that has no site around it.
+/// Element selectors only, so that it works with any `class_prefix`.
+const LAYOUT_STYLESHEET: &str = "\
+/* Layout */
+:root { color-scheme: light dark; }
+body { margin: 0; font-family: ui-sans-serif, -apple-system, BlinkMacSystemFont, \"Segoe UI\", Helvetica, Arial, sans-serif; line-height: 1.5; }
+main { max-width: 720px; margin: 0 auto; padding: 48px 24px; }
+img, video, iframe, object { max-width: 100%; }
+img, video { height: auto; }
+pre { overflow-x: auto; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid rgba(127, 127, 127, 0.3); padding: 4px 8px; }
+";
+
+/// Wraps `body`, the rendered blocks of `page`, in a standalone document: the title,
+/// description, Open Graph and Twitter tags, canonical URL and stylesheet of `document`.
+pub fn html_document(
+    page: &Page,
+    body: &str,
+    options: &HtmlOptions,
+    document: &DocumentOptions,
+) -> String {
+    let title = page.title();
+    let description = document
+        .description
+        .clone()
+        .or_else(|| page.text_property(&document.description_property));
+    let image = document
+        .image
+        .as_deref()
+        .or_else(|| page.cover.as_ref().map(|cover| cover.url()));
+
+    let mut head = String::from("<meta charset='utf-8' />\n");
+    head.push_str("<meta name='viewport' content='width=device-width, initial-scale=1' />\n");
+    head.push_str(&format!("<title>{}</title>\n", escape_html(&title)));
+    if let Some(description) = &description {
+        head.push_str(&meta("name", "description", description));
+    }
+    if let Some(href) = document.canonical_url.as_deref().and_then(sanitize_url) {
+        head.push_str(&format!("<link rel='canonical' href='{}' />\n", href));
+    }
+
+    head.push_str(&meta("property", "og:type", "article"));
+    head.push_str(&meta("property", "og:title", &title));
+    if let Some(description) = &description {
+        head.push_str(&meta("property", "og:description", description));
+    }
+    if let Some(url) = &document.canonical_url {
+        head.push_str(&meta("property", "og:url", url));
+    }
+    if let Some(site_name) = &document.site_name {
+        head.push_str(&meta("property", "og:site_name", site_name));
+    }
+    if let Some(image) = image {
+        head.push_str(&meta("property", "og:image", image));
+    }
+
+    let card = if image.is_some() {
+        "summary_large_image"
+    } else {
+        "summary"
+    };
+    head.push_str(&meta("name", "twitter:card", card));
+    if let Some(twitter_site) = &document.twitter_site {
+        head.push_str(&meta("name", "twitter:site", twitter_site));
+    }
+    head.push_str(&meta("name", "twitter:title", &title));
+    if let Some(description) = &description {
+        head.push_str(&meta("name", "twitter:description", description));
+    }
+    if let Some(image) = image {
+        head.push_str(&meta("name", "twitter:image", image));
+    }
+
+    if document.stylesheet {
+        // The color rules are selected by prefixed class names, which could otherwise
+        // end a rule or the `<style>` element.
+        let css = if is_css_class_prefix(&options.class_prefix) {
+            generate_stylesheet(&StylesheetOptions {
+                class_prefix: options.class_prefix.clone(),
+                ..StylesheetOptions::default()
+            })
+        } else {
+            println!(
+                "The class_prefix {:?} does not make CSS identifiers, so the colors are left out of the stylesheet",
+                options.class_prefix
+            );
+            String::new()
+        };
+        head.push_str(&format!(
+            "<style>\n{}\n{}</style>\n",
+            LAYOUT_STYLESHEET, css
+        ));
+    }
+
+    format!(
+        "<!doctype html>\n<html lang='{}'>\n<head>\n{}</head>\n<body>\n<main{}>\n{}\n</main>\n</body>\n</html>\n",
+        escape_html(&document.lang),
+        head,
+        options.class_attribute(&["page"]),
+        body
+    )
+}
+
+/// A `<meta>` tag, named by `name` or, for Open Graph, by `property`.
+fn meta(attribute: &str, name: &str, content: &str) -> String {
+    format!(
+        "<meta {}='{}' content='{}' />\n",
+        attribute,
+        name,
+        escape_html(content)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scraper::{Html, Selector};
+    use serde_json::json;
+
+    fn page() -> Page {
+        let user = json!({ "object": "user", "id": "u1" });
+        serde_json::from_value(json!({
+            "object": "page",
+            "id": "p1",
+            "created_time": "2024-03-01T09:30:00.000Z",
+            "last_edited_time": "2024-03-02T18:00:00.000Z",
+            "created_by": user,
+            "last_edited_by": user,
+            "cover": { "type": "external", "external": { "url": "https://example.com/cover.png?a=1&b=2" } },
+            "icon": null,
+            "parent": { "type": "database_id", "database_id": "d1" },
+            "archived": false,
+            "properties": {
+                "Name": { "type": "title", "title": [{ "plain_text": "Fish & <Chips>" }] },
+                "Description": { "type": "rich_text", "rich_text": [
+                    { "plain_text": "A 'quick' " }, { "plain_text": "guide" }
+                ] }
+            },
+            "url": "https://www.notion.so/p1",
+            "public_url": null
+        }))
+        .unwrap()
+    }
+
+    fn attribute(document: &Html, selector: &str, name: &str) -> Option<String> {
+        document
+            .select(&Selector::parse(selector).unwrap())
+            .next()
+            .and_then(|element| element.value().attr(name))
+            .map(|value| value.to_string())
+    }
+
+    #[test]
+    fn writes_the_head_from_the_page() {
+        let html = html_document(
+            &page(),
+            "<p class='notion-paragraph'>Hi</p>",
+            &HtmlOptions::default(),
+            &DocumentOptions {
+                lang: String::from("de"),
+                canonical_url: Some(String::from("https://example.com/fish?x=1&y=2")),
+                site_name: Some(String::from("Recipes")),
+                ..DocumentOptions::default()
+            },
+        );
+        assert!(html.starts_with("<!doctype html>\n<html lang='de'>\n"));
+
+        let document = Html::parse_document(&html);
+        let title = document
+            .select(&Selector::parse("title").unwrap())
+            .next()
+            .map(|title| title.text().collect::<String>());
+        assert_eq!(title.as_deref(), Some("Fish & <Chips>"));
+
+        let description = Some(String::from("A 'quick' guide"));
+        let cover = Some(String::from("https://example.com/cover.png?a=1&b=2"));
+        let canonical_url = Some(String::from("https://example.com/fish?x=1&y=2"));
+        assert_eq!(
+            attribute(&document, "meta[name=description]", "content"),
+            description
+        );
+        assert_eq!(
+            attribute(&document, "meta[property='og:description']", "content"),
+            description
+        );
+        assert_eq!(
+            attribute(&document, "meta[property='og:image']", "content"),
+            cover
+        );
+        assert_eq!(
+            attribute(&document, "meta[name='twitter:image']", "content"),
+            cover
+        );
+        assert_eq!(
+            attribute(&document, "meta[property='og:url']", "content"),
+            canonical_url
+        );
+        assert_eq!(
+            attribute(&document, "link[rel=canonical]", "href"),
+            canonical_url
+        );
+        assert_eq!(
+            attribute(&document, "meta[property='og:site_name']", "content").as_deref(),
+            Some("Recipes")
+        );
+        assert_eq!(
+            attribute(&document, "meta[name='twitter:card']", "content").as_deref(),
+            Some("summary_large_image")
+        );
+
+        let style = document
+            .select(&Selector::parse("head style").unwrap())
+            .next()
+            .map(|style| style.text().collect::<String>())
+            .unwrap();
+        assert!(style.contains(".notion-blue"));
+        assert_eq!(
+            document
+                .select(&Selector::parse("body > main.notion-page > p.notion-paragraph").unwrap())
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn leaves_out_what_is_not_there() {
+        let mut page = page();
+        page.cover = None;
+        let html = html_document(
+            &page,
+            "",
+            &HtmlOptions::default(),
+            &DocumentOptions {
+                description_property: String::from("Summary"),
+                stylesheet: false,
+                ..DocumentOptions::default()
+            },
+        );
+
+        let document = Html::parse_document(&html);
+        for selector in [
+            "meta[name=description]",
+            "meta[property='og:image']",
+            "link[rel=canonical]",
+            "style",
+        ] {
+            assert_eq!(
+                document.select(&Selector::parse(selector).unwrap()).count(),
+                0,
+                "{}",
+                selector
+            );
+        }
+        assert_eq!(
+            attribute(&document, "meta[name='twitter:card']", "content").as_deref(),
+            Some("summary")
+        );
+    }
+
+    #[test]
+    fn leaves_the_colors_out_for_prefixes_that_are_not_identifiers() {
+        let html = html_document(
+            &page(),
+            "",
+            &HtmlOptions {
+                class_prefix: String::from("x</style><script>"),
+                ..HtmlOptions::default()
+            },
+            &DocumentOptions::default(),
+        );
+
+        assert!(html.contains(LAYOUT_STYLESHEET));
+        assert!(!html.contains("<script>"));
+        assert!(!html.contains("background-color"));
+    }
+}
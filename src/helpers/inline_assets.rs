@@ -0,0 +1,196 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use futures::stream::{self, StreamExt};
+use lambda_runtime::Error;
+use reqwest::{header, Url};
+
+use crate::helpers::escape::unescape_html;
+use crate::helpers::fetcher::build_client;
+use crate::options::FetchOptions;
+
+/// The attributes that load a file into the page: `src` of images and videos,
+/// and `data` of the `<object>` of PDFs.
+const SOURCE_ATTRIBUTES: [&str; 2] = [" src='", " data='"];
+
+/// How many files are downloaded at once.
+const CONCURRENT_DOWNLOADS: usize = 4;
+
+/// Replaces the URLs of images, videos and PDFs in `html` with `data:` URIs, so that the
+/// document needs nothing else. Files that cannot be fetched, are larger than `max_bytes`,
+/// would take the `data:` URIs past `max_total_bytes` or are something else keep their URL.
+/// Iframes (embedded players) always keep their URL.
+///
+/// `html` must be our own output, where attribute values are single-quoted and escaped.
+pub async fn inline_assets(
+    html: &str,
+    max_bytes: usize,
+    max_total_bytes: usize,
+    fetch_options: &FetchOptions,
+) -> String {
+    let mut urls = source_urls(html);
+    urls.sort_unstable();
+    urls.dedup();
+
+    // In order, so that which files fit under `max_total_bytes` does not depend on timing.
+    let mut data_uris = stream::iter(&urls)
+        .map(|url| async move { (*url, fetch_data_uri(url, max_bytes, fetch_options).await) })
+        .buffered(CONCURRENT_DOWNLOADS);
+
+    let mut html = html.to_string();
+    let mut total_bytes = 0;
+    while let Some((url, data_uri)) = data_uris.next().await {
+        match data_uri {
+            Ok(data_uri) if total_bytes + data_uri.len() > max_total_bytes => {
+                println!(
+                    "Stopped inlining at {}: the document would exceed {} bytes",
+                    unescape_html(url),
+                    max_total_bytes
+                );
+                break;
+            }
+            Ok(data_uri) => {
+                total_bytes += data_uri.len();
+                for attribute in SOURCE_ATTRIBUTES {
+                    html = html.replace(
+                        &format!("{}{}'", attribute, url),
+                        &format!("{}{}'", attribute, data_uri),
+                    );
+                }
+            }
+            Err(e) => println!("Failed to inline {}: {}", unescape_html(url), e),
+        }
+    }
+    html
+}
+
+/// The escaped values of the source attributes of `html` that are web URLs,
+/// except those of iframes.
+fn source_urls(html: &str) -> Vec<&str> {
+    SOURCE_ATTRIBUTES
+        .iter()
+        .flat_map(|attribute| {
+            html.match_indices(attribute)
+                .filter(|(start, _)| tag_name(&html[..*start]) != Some("iframe"))
+                .filter_map(|(start, _)| {
+                    let value = &html[start + attribute.len()..];
+                    value.find('\'').map(|end| &value[..end])
+                })
+        })
+        .filter(|url| url.starts_with("http://") || url.starts_with("https://"))
+        .collect()
+}
+
+/// The name of the tag that `before`, the HTML up to an attribute, ends inside of.
+fn tag_name(before: &str) -> Option<&str> {
+    let tag = &before[before.rfind('<')? + 1..];
+    tag.split(|c: char| c.is_ascii_whitespace()).next()
+}
+
+async fn fetch_data_uri(
+    url: &str,
+    max_bytes: usize,
+    fetch_options: &FetchOptions,
+) -> Result<String, Error> {
+    let url = Url::parse(&unescape_html(url))?;
+    let client = build_client(&url, fetch_options).await?;
+    let mut response = client.get(url).send().await?;
+
+    if !response.status().is_success() {
+        return Err(Error::from(format!(
+            "Unexpected status {}",
+            response.status()
+        )));
+    }
+
+    let mime_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().to_ascii_lowercase())
+        .unwrap_or_default();
+    let is_media = ["image/", "video/", "audio/"]
+        .iter()
+        .any(|prefix| mime_type.starts_with(prefix))
+        || mime_type == "application/pdf";
+    let is_plain = mime_type
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "/+.-".contains(c));
+    if !is_media || !is_plain {
+        return Err(Error::from(format!("Not a media file ({})", mime_type)));
+    }
+
+    let mut bytes: Vec<u8> = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        bytes.extend_from_slice(&chunk);
+        if bytes.len() > max_bytes {
+            return Err(Error::from(format!("Larger than {} bytes", max_bytes)));
+        }
+    }
+
+    Ok(format!(
+        "data:{};base64,{}",
+        mime_type,
+        STANDARD.encode(bytes)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::AssetOptions;
+
+    #[test]
+    fn finds_the_sources_of_media() {
+        let html = "<img src='https://example.com/a.png?x=1&amp;y=2' alt='src=&#39;no&#39;' />\
+            <object data='https://example.com/b.pdf' type='application/pdf'></object>\
+            <div data-block-id='1'><img src='/local.png' /></div>\
+            <iframe src='https://www.youtube.com/embed/x' allowfullscreen></iframe>\
+            <video src='https://example.com/c.mp4' controls></video>";
+
+        assert_eq!(
+            source_urls(html),
+            [
+                "https://example.com/a.png?x=1&amp;y=2",
+                "https://example.com/c.mp4",
+                "https://example.com/b.pdf",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn files_that_cannot_be_fetched_keep_their_url() {
+        let html = "<img src='https://blocked.example/a.png' />\
+            <iframe src='https://blocked.example/player'></iframe>";
+        let fetch_options = FetchOptions {
+            denied_hosts: vec![String::from("blocked.example")],
+            ..FetchOptions::default()
+        };
+        assert_eq!(inline_assets(html, 1024, 1024, &fetch_options).await, html);
+    }
+
+    #[test]
+    fn inlining_adds_the_asset_hosts_to_the_page_hosts() {
+        let asset_options = AssetOptions::default();
+        let open = asset_options.inline_fetch_options(&FetchOptions::default(), 100);
+        assert!(open.allowed_hosts.is_empty());
+        assert_eq!(open.max_body_bytes, 100);
+        assert_eq!(open.timeout_ms, asset_options.timeout_ms);
+
+        let restricted = asset_options.inline_fetch_options(
+            &FetchOptions {
+                allowed_hosts: vec![String::from("example.com")],
+                ..FetchOptions::default()
+            },
+            100,
+        );
+        assert!(restricted
+            .allowed_hosts
+            .iter()
+            .any(|host| host == "example.com"));
+        assert!(restricted
+            .allowed_hosts
+            .iter()
+            .any(|host| host == "amazonaws.com"));
+    }
+}
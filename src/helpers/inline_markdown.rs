@@ -0,0 +1,1097 @@
+use crate::helpers::escape::{sanitize_url, unescape_html};
+use crate::models::common::Color;
+use crate::models::rich_text::{Mention, RichTextElement};
+use crate::options::{EmphasisFallback, MarkdownFlavor, MarkdownOptions};
+
+/// Inline formatting that can span several rich text elements.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Mark {
+    /// A link, with its destination sanitized and escaped by [`link_destination`].
+    Link(String),
+    Color(Color),
+    Strikethrough,
+    Bold,
+    Italic,
+    Underline,
+}
+
+impl Mark {
+    fn open(&self, flavor: MarkdownFlavor) -> String {
+        match self {
+            Mark::Link(_) => String::from("["),
+            Mark::Color(color) => format!(
+                "<span {}='notion-{}'>",
+                class_attribute(flavor),
+                color.class_name()
+            ),
+            Mark::Strikethrough if !flavor.has_strikethrough() => String::from("<del>"),
+            Mark::Strikethrough => String::from("~~"),
+            Mark::Bold => String::from("**"),
+            Mark::Italic => String::from("*"),
+            Mark::Underline => String::from("<u>"),
+        }
+    }
+
+    fn close(&self, flavor: MarkdownFlavor) -> String {
+        match self {
+            Mark::Link(destination) => format!("]({})", destination),
+            Mark::Color(_) => String::from("</span>"),
+            Mark::Strikethrough if !flavor.has_strikethrough() => String::from("</del>"),
+            Mark::Strikethrough => String::from("~~"),
+            Mark::Bold => String::from("**"),
+            Mark::Italic => String::from("*"),
+            Mark::Underline => String::from("</u>"),
+        }
+    }
+}
+
+/// MDX reads HTML as JSX, where the class attribute is `className`.
+pub fn class_attribute(flavor: MarkdownFlavor) -> &'static str {
+    if flavor.is_jsx() {
+        "className"
+    } else {
+        "class"
+    }
+}
+
+/// A run of text with one style. Adjacent elements with the same style are merged into one.
+struct Segment {
+    text: String,
+    marks: Vec<Mark>,
+    code: bool,
+    equation: bool,
+    /// A page mention written as an Obsidian `[[wiki-link]]`.
+    wiki_link: bool,
+    /// Markdown that a renderer wrote for the element, kept as it is.
+    rendered: bool,
+}
+
+impl Segment {
+    /// Code, equations, wiki-links and rendered elements are written as a whole,
+    /// never merged or split.
+    fn is_atomic(&self) -> bool {
+        self.code || self.equation || self.wiki_link || self.rendered
+    }
+}
+
+/// A piece of the serialized output. Delimiters are kept apart from the text
+/// until it is known whether CommonMark would parse them as emphasis.
+enum Token {
+    /// Already escaped text, or a code span, equation or wiki-link.
+    Text(String),
+    /// Opens the mark; pairs with the `Close` of the same id.
+    Open(usize, Mark),
+    Close(usize, Mark),
+}
+
+/// Serializes rich text to inline Markdown.
+///
+/// Annotations that several elements share are opened once and nested, with the
+/// longest-running one outermost, so `**bold *both* bold**` survives a round trip.
+/// Whitespace is kept outside delimiters (`**a** b`, not `**a **b`), since CommonMark
+/// does not treat a delimiter next to whitespace on its inner side as emphasis.
+///
+/// The elements that `render` returns Markdown for are written as that Markdown,
+/// without their annotations.
+pub fn rich_text_to_markdown_with(
+    rich_text: &[RichTextElement],
+    options: &MarkdownOptions,
+    render: impl Fn(&RichTextElement) -> Option<String>,
+) -> String {
+    let segments = merge_segments(
+        rich_text
+            .iter()
+            .map(|element| match render(element) {
+                Some(markdown) => Segment {
+                    text: markdown,
+                    marks: Vec::new(),
+                    code: false,
+                    equation: false,
+                    wiki_link: false,
+                    rendered: true,
+                },
+                None => to_segment(element, options),
+            })
+            .collect(),
+    );
+
+    let mut tokens = tokenize(&segments, options);
+    // Spacing goes first: a space outside a delimiter can make it flanking by itself.
+    if options.cjk_spacing {
+        space_cjk_boundaries(&mut tokens);
+    }
+    fix_emphasis(&mut tokens, options.emphasis_fallback(), options.flavor);
+
+    tokens
+        .iter()
+        .map(|token| rendered(token, options.flavor))
+        .collect()
+}
+
+fn tokenize(segments: &[Segment], options: &MarkdownOptions) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut open_marks: Vec<(usize, Mark)> = Vec::new();
+    let mut pending_whitespace = String::new();
+    let mut next_id = 0;
+
+    for (index, segment) in segments.iter().enumerate() {
+        if segment.text.trim().is_empty() && !segment.is_atomic() {
+            pending_whitespace.push_str(&segment.text);
+            continue;
+        }
+
+        // Close everything from the top of the stack down to the lowest mark that ends here.
+        let keep = open_marks
+            .iter()
+            .position(|(_, mark)| !segment.marks.contains(mark))
+            .unwrap_or(open_marks.len());
+        for (id, mark) in open_marks.drain(keep..).rev() {
+            tokens.push(Token::Close(id, mark));
+        }
+
+        let (leading, core, trailing) = split_whitespace(&segment.text);
+        pending_whitespace.push_str(leading);
+        if !pending_whitespace.is_empty() {
+            tokens.push(Token::Text(std::mem::take(&mut pending_whitespace)));
+        }
+
+        let mut new_marks: Vec<&Mark> = segment
+            .marks
+            .iter()
+            .filter(|mark| !open_marks.iter().any(|(_, open_mark)| open_mark == *mark))
+            .collect();
+        new_marks.sort_by_key(|mark| std::cmp::Reverse(run_length(&segments[index..], mark)));
+        for mark in new_marks {
+            tokens.push(Token::Open(next_id, mark.clone()));
+            open_marks.push((next_id, mark.clone()));
+            next_id += 1;
+        }
+
+        let text = if segment.code {
+            code_span(core)
+        } else if segment.equation {
+            inline_equation(core, options.flavor)
+        } else if segment.wiki_link {
+            wiki_link(core)
+        } else if segment.rendered {
+            core.to_string()
+        } else {
+            let core = if options.cjk_spacing {
+                space_cjk(core)
+            } else {
+                core.to_string()
+            };
+            escape_line_starts(
+                &escape_markdown(&core, options.flavor),
+                at_line_start(&tokens),
+            )
+        };
+        if !text.is_empty() {
+            tokens.push(Token::Text(text));
+        }
+        pending_whitespace.push_str(trailing);
+    }
+
+    for (id, mark) in open_marks.into_iter().rev() {
+        tokens.push(Token::Close(id, mark));
+    }
+    if !pending_whitespace.is_empty() {
+        tokens.push(Token::Text(pending_whitespace));
+    }
+
+    tokens
+}
+
+/// CommonMark only parses `**` as emphasis when it is left-flanking (opening) or
+/// right-flanking (closing). Punctuation on the inner side needs whitespace or punctuation
+/// on the outer side, which text without spaces between words, such as Japanese, rarely has:
+/// `**「強調」**です` is not bold. Such pairs are rewritten as `fallback` says.
+fn fix_emphasis(tokens: &mut Vec<Token>, fallback: EmphasisFallback, flavor: MarkdownFlavor) {
+    match fallback {
+        EmphasisFallback::Html => {
+            // An HTML tag puts new characters next to the remaining delimiters,
+            // which can break (or fix) them in turn.
+            loop {
+                let broken_ids: Vec<usize> = unrecognized_delimiters(tokens, flavor)
+                    .into_iter()
+                    .filter_map(|index| match &tokens[index] {
+                        Token::Open(id, _) | Token::Close(id, _) => Some(*id),
+                        Token::Text(_) => None,
+                    })
+                    .collect();
+                if broken_ids.is_empty() {
+                    break;
+                }
+                for token in tokens.iter_mut() {
+                    let html = match token {
+                        Token::Open(id, mark) if broken_ids.contains(id) => html_tag(mark, false),
+                        Token::Close(id, mark) if broken_ids.contains(id) => html_tag(mark, true),
+                        _ => None,
+                    };
+                    if let Some(html) = html {
+                        *token = Token::Text(html);
+                    }
+                }
+            }
+        }
+        EmphasisFallback::ZeroWidthSpace => {
+            // A zero-width space is neither whitespace nor punctuation, so on the
+            // inner side of a delimiter run it makes the run flanking again.
+            let mut positions: Vec<usize> = unrecognized_delimiters(tokens, flavor)
+                .into_iter()
+                .map(|index| match &tokens[index] {
+                    Token::Open(_, _) => run_end(tokens, index, flavor) + 1,
+                    _ => run_start(tokens, index, flavor),
+                })
+                .collect();
+            positions.sort_unstable();
+            positions.dedup();
+            for position in positions.into_iter().rev() {
+                tokens.insert(position, Token::Text(String::from("\u{200B}")));
+            }
+        }
+        EmphasisFallback::None => {}
+    }
+}
+
+/// The indices of the emphasis delimiters that are not flanking the way they need to be:
+/// left-flanking to open, right-flanking to close.
+fn unrecognized_delimiters(tokens: &[Token], flavor: MarkdownFlavor) -> Vec<usize> {
+    (0..tokens.len())
+        .filter(|index| match &tokens[*index] {
+            Token::Open(_, mark) if delimiter_char(mark, flavor).is_some() => {
+                let (before, after) = neighbours(tokens, *index, flavor);
+                !is_left_flanking(before, after)
+            }
+            Token::Close(_, mark) if delimiter_char(mark, flavor).is_some() => {
+                let (before, after) = neighbours(tokens, *index, flavor);
+                !is_right_flanking(before, after)
+            }
+            _ => false,
+        })
+        .collect()
+}
+
+/// The characters on either side of the delimiter run that contains `tokens[index]`.
+/// `None` stands for the start or end of the text.
+fn neighbours(
+    tokens: &[Token],
+    index: usize,
+    flavor: MarkdownFlavor,
+) -> (Option<char>, Option<char>) {
+    let before = tokens[..run_start(tokens, index, flavor)]
+        .iter()
+        .rev()
+        .find_map(|token| rendered(token, flavor).chars().next_back());
+    let after = tokens[run_end(tokens, index, flavor) + 1..]
+        .iter()
+        .find_map(|token| rendered(token, flavor).chars().next());
+    (before, after)
+}
+
+fn rendered(token: &Token, flavor: MarkdownFlavor) -> String {
+    match token {
+        Token::Text(text) => text.clone(),
+        Token::Open(_, mark) => mark.open(flavor),
+        Token::Close(_, mark) => mark.close(flavor),
+    }
+}
+
+/// Delimiters of the same character next to each other form one run, e.g. `***`.
+fn run_char(token: &Token, flavor: MarkdownFlavor) -> Option<char> {
+    match token {
+        Token::Open(_, mark) | Token::Close(_, mark) => delimiter_char(mark, flavor),
+        Token::Text(_) => None,
+    }
+}
+
+fn run_start(tokens: &[Token], index: usize, flavor: MarkdownFlavor) -> usize {
+    let c = run_char(&tokens[index], flavor);
+    let mut start = index;
+    while start > 0 && c.is_some() && run_char(&tokens[start - 1], flavor) == c {
+        start -= 1;
+    }
+    start
+}
+
+fn run_end(tokens: &[Token], index: usize, flavor: MarkdownFlavor) -> usize {
+    let c = run_char(&tokens[index], flavor);
+    let mut end = index;
+    while end + 1 < tokens.len() && c.is_some() && run_char(&tokens[end + 1], flavor) == c {
+        end += 1;
+    }
+    end
+}
+
+/// The character of emphasis delimiters, which are subject to the flanking rules.
+fn delimiter_char(mark: &Mark, flavor: MarkdownFlavor) -> Option<char> {
+    match mark {
+        Mark::Bold | Mark::Italic => Some('*'),
+        Mark::Strikethrough if flavor.has_strikethrough() => Some('~'),
+        Mark::Strikethrough | Mark::Link(_) | Mark::Color(_) | Mark::Underline => None,
+    }
+}
+
+fn html_tag(mark: &Mark, close: bool) -> Option<String> {
+    let name = match mark {
+        Mark::Bold => "strong",
+        Mark::Italic => "em",
+        Mark::Strikethrough => "del",
+        Mark::Link(_) | Mark::Color(_) | Mark::Underline => return None,
+    };
+    Some(if close {
+        format!("</{}>", name)
+    } else {
+        format!("<{}>", name)
+    })
+}
+
+fn is_left_flanking(before: Option<char>, after: Option<char>) -> bool {
+    let after_is_whitespace = after.is_none_or(char::is_whitespace);
+    let after_is_punctuation = after.is_some_and(is_punctuation);
+    let before_is_space_or_punctuation =
+        before.is_none_or(|c| c.is_whitespace() || is_punctuation(c));
+    !after_is_whitespace && (!after_is_punctuation || before_is_space_or_punctuation)
+}
+
+fn is_right_flanking(before: Option<char>, after: Option<char>) -> bool {
+    let before_is_whitespace = before.is_none_or(char::is_whitespace);
+    let before_is_punctuation = before.is_some_and(is_punctuation);
+    let after_is_space_or_punctuation =
+        after.is_none_or(|c| c.is_whitespace() || is_punctuation(c));
+    !before_is_whitespace && (!before_is_punctuation || after_is_space_or_punctuation)
+}
+
+/// Unicode punctuation and symbols, as far as they occur in running text:
+/// ASCII, Latin-1, general punctuation, CJK symbols and punctuation, and full-width forms.
+fn is_punctuation(c: char) -> bool {
+    c.is_ascii_punctuation()
+        || matches!(c,
+            '\u{00A1}'..='\u{00BF}'
+            | '\u{00D7}'
+            | '\u{00F7}'
+            | '\u{2010}'..='\u{2027}'
+            | '\u{2030}'..='\u{205E}'
+            | '\u{2190}'..='\u{23FF}'
+            | '\u{25A0}'..='\u{27BF}'
+            | '\u{3001}'..='\u{3003}'
+            | '\u{3008}'..='\u{3020}'
+            | '\u{3030}'
+            | '\u{303D}'
+            | '\u{30A0}'
+            | '\u{30FB}'
+            | '\u{FE10}'..='\u{FE19}'
+            | '\u{FE30}'..='\u{FE6B}'
+            | '\u{FF01}'..='\u{FF0F}'
+            | '\u{FF1A}'..='\u{FF20}'
+            | '\u{FF3B}'..='\u{FF40}'
+            | '\u{FF5B}'..='\u{FF65}')
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{309F}'
+        | '\u{30A1}'..='\u{30FA}'
+        | '\u{30FC}'..='\u{30FF}'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{FF66}'..='\u{FF9F}')
+}
+
+fn is_latin(c: char) -> bool {
+    c.is_ascii_alphanumeric()
+}
+
+fn needs_space(before: char, after: char) -> bool {
+    (is_cjk(before) && is_latin(after)) || (is_latin(before) && is_cjk(after))
+}
+
+/// Puts a space between CJK characters and Latin letters or digits, e.g. `Rustで書く` → `Rust で書く`.
+fn space_cjk(text: &str) -> String {
+    let mut spaced = String::with_capacity(text.len());
+    let mut previous: Option<char> = None;
+    for c in text.chars() {
+        if previous.is_some_and(|previous| needs_space(previous, c)) {
+            spaced.push(' ');
+        }
+        spaced.push(c);
+        previous = Some(c);
+    }
+    spaced
+}
+
+/// Applies [`space_cjk`] across formatting, e.g. `**Rust**で` → `**Rust** で`.
+/// The space goes where closing delimiters end and opening ones begin, outside both.
+fn space_cjk_boundaries(tokens: &mut Vec<Token>) {
+    let mut index = 0;
+    let mut previous: Option<char> = None;
+    // Where a space would go: after the last text and any delimiters it closed.
+    let mut boundary = 0;
+
+    while index < tokens.len() {
+        match &tokens[index] {
+            Token::Text(text) => {
+                let first = text.chars().next();
+                if let (Some(previous), Some(first)) = (previous, first) {
+                    if needs_space(previous, first) && boundary <= index {
+                        tokens.insert(boundary, Token::Text(String::from(" ")));
+                        index += 1;
+                    }
+                }
+                if let Token::Text(text) = &tokens[index] {
+                    previous = text.chars().next_back();
+                }
+                boundary = index + 1;
+            }
+            Token::Close(_, _) => boundary = index + 1,
+            Token::Open(_, _) => {}
+        }
+        index += 1;
+    }
+}
+
+fn to_segment(element: &RichTextElement, options: &MarkdownOptions) -> Segment {
+    let (text, annotations, href, equation) = match element {
+        RichTextElement::Text {
+            plain_text,
+            annotations,
+            href,
+            ..
+        }
+        | RichTextElement::Mention {
+            plain_text,
+            annotations,
+            href,
+            ..
+        } => (plain_text.clone(), annotations, href, false),
+        RichTextElement::Equation {
+            equation,
+            annotations,
+            href,
+            ..
+        } => (equation.expression.clone(), annotations, href, true),
+    };
+
+    let wiki_link = options.flavor == MarkdownFlavor::Obsidian
+        && matches!(
+            element,
+            RichTextElement::Mention {
+                mention: Mention::Page { .. },
+                ..
+            }
+        );
+
+    let mut marks = Vec::new();
+    // Links whose scheme is not allowed are written as their text.
+    if let Some(destination) = href
+        .as_deref()
+        .filter(|_| !wiki_link)
+        .and_then(link_destination)
+    {
+        marks.push(Mark::Link(destination));
+    }
+    if options.color_spans && annotations.color != Color::Default {
+        marks.push(Mark::Color(annotations.color));
+    }
+    if annotations.strikethrough {
+        marks.push(Mark::Strikethrough);
+    }
+    if annotations.bold {
+        marks.push(Mark::Bold);
+    }
+    if annotations.italic {
+        marks.push(Mark::Italic);
+    }
+    // Hugo would leave `<u>` out together with the text in it.
+    if annotations.underline && options.flavor.has_inline_html() {
+        marks.push(Mark::Underline);
+    }
+
+    Segment {
+        text,
+        marks,
+        code: annotations.code,
+        equation,
+        wiki_link,
+        rendered: false,
+    }
+}
+
+fn merge_segments(segments: Vec<Segment>) -> Vec<Segment> {
+    let mut merged: Vec<Segment> = Vec::new();
+    for segment in segments {
+        match merged.last_mut() {
+            Some(last)
+                if !last.equation
+                    && !segment.equation
+                    && !last.wiki_link
+                    && !segment.wiki_link
+                    && !last.rendered
+                    && !segment.rendered
+                    && last.code == segment.code
+                    && last.marks == segment.marks =>
+            {
+                last.text.push_str(&segment.text);
+            }
+            _ => merged.push(segment),
+        }
+    }
+    merged
+}
+
+/// How many segments in a row, starting with the first, carry `mark`.
+fn run_length(segments: &[Segment], mark: &Mark) -> usize {
+    segments
+        .iter()
+        .take_while(|segment| segment.marks.contains(mark) || segment.text.trim().is_empty())
+        .count()
+}
+
+fn split_whitespace(text: &str) -> (&str, &str, &str) {
+    let core_start = text.len() - text.trim_start().len();
+    let core_end = text.trim_end().len().max(core_start);
+    (
+        &text[..core_start],
+        &text[core_start..core_end],
+        &text[core_end..],
+    )
+}
+
+/// Wraps `code` in enough backticks that none inside can end the span.
+fn code_span(code: &str) -> String {
+    let longest_run = code
+        .split(|c| c != '`')
+        .map(|run| run.len())
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat(longest_run + 1);
+    // A space keeps a backtick at either end from joining the fence.
+    if code.starts_with('`') || code.ends_with('`') {
+        format!("{} {} {}", fence, code, fence)
+    } else {
+        format!("{}{}{}", fence, code, fence)
+    }
+}
+
+/// Writes an inline equation in the math syntax of `flavor`. CommonMark has none,
+/// so the TeX is kept as code.
+fn inline_equation(expression: &str, flavor: MarkdownFlavor) -> String {
+    match flavor {
+        MarkdownFlavor::CommonMark => code_span(expression),
+        MarkdownFlavor::Hugo => format!("\\({}\\)", expression),
+        MarkdownFlavor::Gfm | MarkdownFlavor::Mdx | MarkdownFlavor::Obsidian => {
+            format!("${}$", expression)
+        }
+    }
+}
+
+fn has_dollar_math(flavor: MarkdownFlavor) -> bool {
+    matches!(
+        flavor,
+        MarkdownFlavor::Gfm | MarkdownFlavor::Mdx | MarkdownFlavor::Obsidian
+    )
+}
+
+/// Writes `[[title]]`. Obsidian note names cannot contain the characters that
+/// would end the link early or point into the note, so they are left out.
+fn wiki_link(title: &str) -> String {
+    let title: String = title
+        .chars()
+        .filter(|c| !matches!(c, '[' | ']' | '|' | '#' | '^'))
+        .collect();
+    format!("[[{}]]", title.trim())
+}
+
+/// Escapes characters that would otherwise start emphasis, links, code, raw HTML or
+/// character references such as `&lt;`, and depending on the flavor, math or JavaScript
+/// expressions.
+pub fn escape_markdown(text: &str, flavor: MarkdownFlavor) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for (index, c) in text.char_indices() {
+        if matches!(c, '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '~')
+            || (c == '&' && starts_character_reference(&text[index..]))
+            || (flavor.is_jsx() && matches!(c, '{' | '}'))
+            || (has_dollar_math(flavor) && c == '$')
+        {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Whether `text` starts with what CommonMark reads as a character reference:
+/// `&name;`, `&#123;` or `&#x1F;`.
+fn starts_character_reference(text: &str) -> bool {
+    let Some(rest) = text.strip_prefix('&') else {
+        return false;
+    };
+    let Some((reference, _)) = rest.split_once(';') else {
+        return false;
+    };
+    if let Some(hex) = reference
+        .strip_prefix("#x")
+        .or_else(|| reference.strip_prefix("#X"))
+    {
+        (1..=6).contains(&hex.len()) && hex.chars().all(|c| c.is_ascii_hexdigit())
+    } else if let Some(digits) = reference.strip_prefix('#') {
+        (1..=7).contains(&digits.len()) && digits.chars().all(|c| c.is_ascii_digit())
+    } else {
+        reference.len() <= 32
+            && reference.starts_with(|c: char| c.is_ascii_alphabetic())
+            && reference.chars().all(|c| c.is_ascii_alphanumeric())
+    }
+}
+
+/// Whether text added after `tokens` starts a line, so that it could start a block.
+fn at_line_start(tokens: &[Token]) -> bool {
+    for token in tokens.iter().rev() {
+        match token {
+            Token::Text(text) => {
+                let text = text.trim_end_matches(' ');
+                if text.ends_with('\n') {
+                    return true;
+                }
+                if !text.is_empty() {
+                    return false;
+                }
+            }
+            Token::Open(..) | Token::Close(..) => return false,
+        }
+    }
+    true
+}
+
+/// Escapes what would turn a line of text into a heading, quote, list item or thematic break,
+/// or the line before it into a setext heading. The first line is only escaped when the
+/// text begins `at_line_start`.
+fn escape_line_starts(text: &str, at_line_start: bool) -> String {
+    text.split('\n')
+        .enumerate()
+        .map(|(index, line)| {
+            if index == 0 && !at_line_start {
+                return line.to_string();
+            }
+            let indent = line.len() - line.trim_start_matches(' ').len();
+            let (indent, rest) = line.split_at(indent);
+
+            let is_underline = |marker: char| {
+                rest.starts_with(marker) && rest.trim_end().chars().all(|c| c == marker)
+            };
+            if rest.starts_with('#')
+                || rest.starts_with("- ")
+                || rest.starts_with("+ ")
+                || rest.trim_end() == "+"
+                || is_underline('-')
+                || is_underline('=')
+            {
+                return format!("{}\\{}", indent, rest);
+            }
+
+            let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+            let after_digits = &rest[digits..];
+            if (1..=9).contains(&digits)
+                && (after_digits.starts_with(". ")
+                    || after_digits.starts_with(") ")
+                    || after_digits == "."
+                    || after_digits == ")")
+            {
+                return format!("{}{}\\{}", indent, &rest[..digits], after_digits);
+            }
+
+            line.to_string()
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Returns `url` as a link destination, or `None` when its scheme is not allowed
+/// (see [`sanitize_url`]).
+pub fn link_destination(url: &str) -> Option<String> {
+    sanitize_url(url).map(|url| escape_link_destination(&unescape_html(&url)))
+}
+
+/// Keeps a link destination in one piece: spaces and control characters would end it,
+/// unbalanced parentheses close it early, `<` and `>` read as an autolink or HTML,
+/// and a backslash escapes the character after it. All of them are percent-encoded.
+/// An `&` that would start a character reference is escaped instead, since encoding it
+/// would change the query.
+fn escape_link_destination(url: &str) -> String {
+    let mut escaped = String::with_capacity(url.len());
+    for (index, c) in url.char_indices() {
+        if c == '&' && starts_character_reference(&url[index..]) {
+            escaped.push_str("\\&");
+        } else if c.is_ascii_control() || " ()<>\\".contains(c) {
+            escaped.push_str(&format!("%{:02X}", c as u32));
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{annotations, mention, styled_text, text};
+    use pulldown_cmark::{Event, Options, Parser, Tag};
+    use serde_json::json;
+
+    fn rich_text_to_markdown(rich_text: &[RichTextElement], options: &MarkdownOptions) -> String {
+        rich_text_to_markdown_with(rich_text, options, |_| None)
+    }
+
+    fn styled(plain_text: &str, annotation: Option<&str>) -> RichTextElement {
+        rich(plain_text, annotation.as_slice(), None)
+    }
+
+    /// Text with all of `styles` and, if given, a link.
+    fn rich(plain_text: &str, styles: &[&str], href: Option<&str>) -> RichTextElement {
+        serde_json::from_value(styled_text(plain_text, styles, href)).unwrap()
+    }
+
+    fn element(plain_text: &str, bold: bool) -> RichTextElement {
+        styled(plain_text, bold.then_some("bold"))
+    }
+
+    fn equation(expression: &str) -> RichTextElement {
+        serde_json::from_value(json!({
+            "type": "equation",
+            "equation": { "expression": expression },
+            "annotations": annotations(&[]),
+            "plain_text": expression,
+            "href": null
+        }))
+        .unwrap()
+    }
+
+    fn page_mention(title: &str) -> RichTextElement {
+        serde_json::from_value(mention(
+            json!({ "type": "page", "page": { "id": "abc" } }),
+            title,
+            Some("https://www.notion.so/abc"),
+        ))
+        .unwrap()
+    }
+
+    /// One of each construct the flavors disagree on.
+    fn constructs() -> Vec<RichTextElement> {
+        vec![
+            styled("Costs $5 {each}, ", None),
+            styled("was", Some("strikethrough")),
+            styled(" ", None),
+            styled("now", Some("underline")),
+            styled(": ", None),
+            equation("e^{i\\pi}"),
+            styled(", see ", None),
+            page_mention("Road [map]"),
+        ]
+    }
+
+    fn flavored(flavor: MarkdownFlavor) -> MarkdownOptions {
+        MarkdownOptions {
+            flavor,
+            ..MarkdownOptions::default()
+        }
+    }
+
+    fn parse(markdown: &str, options: Options) -> Vec<Event<'_>> {
+        Parser::new_ext(markdown, options).collect()
+    }
+
+    fn markdown(rich_text: &[RichTextElement], options: MarkdownOptions) -> String {
+        rich_text_to_markdown(rich_text, &options)
+    }
+
+    #[test]
+    fn keeps_delimiters_that_are_recognized() {
+        let rich_text = [
+            element("これは", false),
+            element("強調", true),
+            element("です", false),
+        ];
+        assert_eq!(
+            markdown(&rich_text, MarkdownOptions::default()),
+            "これは**強調**です"
+        );
+    }
+
+    #[test]
+    fn falls_back_to_html_next_to_cjk_punctuation() {
+        let rich_text = [element("「強調」", true), element("です", false)];
+        assert_eq!(
+            markdown(&rich_text, MarkdownOptions::default()),
+            "<strong>「強調」</strong>です"
+        );
+    }
+
+    #[test]
+    fn falls_back_to_zero_width_space() {
+        let rich_text = [element("「強調」", true), element("です", false)];
+        let options = MarkdownOptions {
+            emphasis_fallback: Some(EmphasisFallback::ZeroWidthSpace),
+            ..MarkdownOptions::default()
+        };
+        assert_eq!(markdown(&rich_text, options), "**「強調」\u{200B}**です");
+    }
+
+    #[test]
+    fn spaces_cjk_and_latin_text() {
+        let rich_text = [
+            element("Rustで", false),
+            element("Notion", true),
+            element("を読む", false),
+        ];
+        let options = MarkdownOptions {
+            cjk_spacing: true,
+            ..MarkdownOptions::default()
+        };
+        assert_eq!(markdown(&rich_text, options), "Rust で **Notion** を読む");
+    }
+
+    #[test]
+    fn commonmark_flavor() {
+        let markdown = markdown(&constructs(), flavored(MarkdownFlavor::CommonMark));
+        assert_eq!(
+            markdown,
+            "Costs $5 {each}, <del>was</del> <u>now</u>: `e^{i\\pi}`, \
+             see [Road \\[map\\]](https://www.notion.so/abc)"
+        );
+
+        let events = parse(&markdown, Options::empty());
+        assert!(events.contains(&Event::InlineHtml("<del>".into())));
+        assert!(events.contains(&Event::Code("e^{i\\pi}".into())));
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, Event::Start(Tag::Link { .. }))));
+    }
+
+    #[test]
+    fn gfm_flavor() {
+        let markdown = markdown(&constructs(), flavored(MarkdownFlavor::Gfm));
+        assert_eq!(
+            markdown,
+            "Costs \\$5 {each}, ~~was~~ <u>now</u>: $e^{i\\pi}$, \
+             see [Road \\[map\\]](https://www.notion.so/abc)"
+        );
+
+        let events = parse(
+            &markdown,
+            Options::ENABLE_STRIKETHROUGH | Options::ENABLE_MATH,
+        );
+        assert!(events.contains(&Event::Start(Tag::Strikethrough)));
+        assert!(events.contains(&Event::InlineMath("e^{i\\pi}".into())));
+        assert_eq!(
+            events
+                .iter()
+                .filter(|event| matches!(event, Event::InlineMath(_)))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn mdx_flavor() {
+        let markdown = markdown(&constructs(), flavored(MarkdownFlavor::Mdx));
+        assert_eq!(
+            markdown,
+            "Costs \\$5 \\{each\\}, ~~was~~ <u>now</u>: $e^{i\\pi}$, \
+             see [Road \\[map\\]](https://www.notion.so/abc)"
+        );
+
+        let options = MarkdownOptions {
+            color_spans: true,
+            ..flavored(MarkdownFlavor::Mdx)
+        };
+        let mut blue = text("blue");
+        blue["annotations"]["color"] = json!("blue");
+        let rich_text: RichTextElement = serde_json::from_value(blue).unwrap();
+        assert_eq!(
+            rich_text_to_markdown(&[rich_text], &options),
+            "<span className='notion-blue'>blue</span>"
+        );
+    }
+
+    #[test]
+    fn obsidian_flavor() {
+        let markdown = markdown(&constructs(), flavored(MarkdownFlavor::Obsidian));
+        assert_eq!(
+            markdown,
+            "Costs \\$5 {each}, ~~was~~ <u>now</u>: $e^{i\\pi}$, see [[Road map]]"
+        );
+    }
+
+    #[test]
+    fn hugo_flavor() {
+        let markdown = markdown(&constructs(), flavored(MarkdownFlavor::Hugo));
+        assert_eq!(
+            markdown,
+            "Costs $5 {each}, ~~was~~ now: \\(e^{i\\pi}\\), \
+             see [Road \\[map\\]](https://www.notion.so/abc)"
+        );
+
+        // Hugo leaves raw HTML out, so emphasis falls back to a zero-width space.
+        let rich_text = [element("「強調」", true), element("です", false)];
+        assert_eq!(
+            rich_text_to_markdown(&rich_text, &flavored(MarkdownFlavor::Hugo)),
+            "**「強調」\u{200B}**です"
+        );
+    }
+
+    #[test]
+    fn nests_shared_marks_with_the_longest_outermost() {
+        let rich_text = [
+            rich("bold ", &["bold"], None),
+            rich("both", &["bold", "italic"], None),
+            rich(" bold", &["bold"], None),
+        ];
+        assert_eq!(
+            markdown(&rich_text, MarkdownOptions::default()),
+            "**bold *both* bold**"
+        );
+
+        let rich_text = [
+            rich("see ", &["bold"], Some("https://example.com/")),
+            rich("this", &["bold"], Some("https://example.com/")),
+            rich(" now", &["bold"], None),
+        ];
+        assert_eq!(
+            markdown(&rich_text, MarkdownOptions::default()),
+            "**[see this](https://example.com/) now**"
+        );
+    }
+
+    #[test]
+    fn keeps_whitespace_outside_delimiters() {
+        let rich_text = [
+            rich(" a ", &["bold"], None),
+            rich("b", &[], None),
+            rich("  c", &["italic"], None),
+        ];
+        let spaced = markdown(&rich_text, MarkdownOptions::default());
+        assert_eq!(spaced, " **a** b  *c*");
+
+        let strong = parse(&spaced, Options::empty())
+            .into_iter()
+            .filter(|event| matches!(event, Event::Start(Tag::Strong | Tag::Emphasis)))
+            .count();
+        assert_eq!(strong, 2);
+
+        // Whitespace alone carries no marks, so it does not split a run.
+        let rich_text = [
+            rich("a", &["bold"], None),
+            rich(" ", &[], None),
+            rich("b", &["bold"], None),
+        ];
+        assert_eq!(markdown(&rich_text, MarkdownOptions::default()), "**a b**");
+    }
+
+    #[test]
+    fn escapes_text_and_link_destinations() {
+        let rich_text = [rich("*not* _em_ [x] `y`", &[], None)];
+        let escaped = markdown(&rich_text, MarkdownOptions::default());
+        let text: String = parse(&escaped, Options::empty())
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::Text(text) => Some(text.to_string()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(text, "*not* _em_ [x] `y`");
+
+        // Newlines are dropped by `sanitize_url`, as browsers do; other controls are encoded.
+        let href = "https://example.com/a b(c)<d>\\e\n\u{7}?x=1&y=2";
+        let linked = markdown(&[rich("link", &[], Some(href))], MarkdownOptions::default());
+        assert_eq!(
+            linked,
+            "[link](https://example.com/a%20b%28c%29%3Cd%3E%5Ce%07?x=1&y=2)"
+        );
+        let destinations: Vec<String> = parse(&linked, Options::empty())
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::Start(Tag::Link { dest_url, .. }) => Some(dest_url.to_string()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            destinations,
+            ["https://example.com/a%20b%28c%29%3Cd%3E%5Ce%07?x=1&y=2"]
+        );
+    }
+
+    #[test]
+    fn character_references_and_block_starts_stay_text() {
+        let plain = "Write &lt; or &amp;amp; or &#60; & more\n\
+                     Title\n---\nOther\n===\n# not a heading\n- not a list\n+\n2. not a list";
+        let escaped = markdown(&[rich(plain, &[], None)], MarkdownOptions::default());
+        let events = parse(&escaped, Options::empty());
+        assert!(
+            events.iter().all(|event| !matches!(
+                event,
+                Event::Start(Tag::Heading { .. } | Tag::List(_) | Tag::Item) | Event::Rule
+            )),
+            "{}",
+            escaped
+        );
+        let text: String = events
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::Text(text) => Some(text.to_string()),
+                Event::SoftBreak => Some(String::from("\n")),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(text, plain);
+
+        // Only text that starts a line is escaped, and code is left as it is.
+        assert_eq!(
+            markdown(
+                &[rich("Sum", &["bold"], None), rich(" - 3", &[], None)],
+                MarkdownOptions::default()
+            ),
+            "**Sum** - 3"
+        );
+        assert_eq!(
+            markdown(
+                &[rich("a", &["bold"], None), rich("\n# b", &[], None)],
+                MarkdownOptions::default()
+            ),
+            "**a**\n\\# b"
+        );
+        assert_eq!(
+            markdown(
+                &[rich("a\n# b\n---", &["code"], None)],
+                MarkdownOptions::default()
+            ),
+            "`a\n# b\n---`"
+        );
+
+        // A link destination keeps its references as they are.
+        let linked = markdown(
+            &[rich("link", &[], Some("https://example.com/?a=&lt;"))],
+            MarkdownOptions::default(),
+        );
+        let destinations: Vec<String> = parse(&linked, Options::empty())
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::Start(Tag::Link { dest_url, .. }) => Some(dest_url.to_string()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(destinations, ["https://example.com/?a=&lt;"]);
+    }
+
+    #[test]
+    fn links_with_refused_schemes_are_plain_text() {
+        let rich_text = [
+            rich("click", &["bold"], Some("javascript:alert(1)")),
+            rich(" or ", &[], None),
+            rich("this", &[], Some(" java\tscript:alert(1)")),
+        ];
+        assert_eq!(
+            markdown(&rich_text, MarkdownOptions::default()),
+            "**click** or this"
+        );
+    }
+}
@@ -0,0 +1,681 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use aws_config::{BehaviorVersion, Region};
+use aws_sdk_dynamodb::types::AttributeValue;
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use crate::helpers::fetch_file_size::fetch_file_size;
+use crate::helpers::fetch_link_metadata::{fetch_link_metadata, LinkMetadata};
+use crate::options::{FetchOptions, MetadataCacheOptions, MetadataStoreOptions};
+
+/// How many URLs are fetched at once.
+const CONCURRENT_FETCHES: usize = 8;
+
+/// What was looked up for a cache key.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CachedValue {
+    LinkMetadata(LinkMetadata),
+    /// The size of a file in bytes.
+    FileSize(u64),
+}
+
+/// ## CacheEntry - Struct
+///
+/// A cached lookup. `value` is `None` when fetching failed,
+/// so that broken links are not fetched again on every render.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct CacheEntry {
+    pub value: Option<CachedValue>,
+    /// Seconds since the Unix epoch.
+    pub fetched_at: u64,
+}
+
+/// A persistent tier behind the in-memory cache.
+#[async_trait]
+pub trait MetadataStore: Send + Sync {
+    async fn get(&self, key: &str) -> Option<CacheEntry>;
+    async fn put(&self, key: &str, entry: &CacheEntry);
+
+    /// Writes the entries put since the last flush, for stores that batch their writes.
+    async fn flush(&self) {}
+}
+
+/// Creates the store described by `options`. Entries are kept for `ttl_seconds`
+/// where the store can expire them itself.
+pub async fn build_metadata_store(
+    options: &MetadataStoreOptions,
+    ttl_seconds: u64,
+) -> Box<dyn MetadataStore> {
+    match options {
+        MetadataStoreOptions::File { path } => Box::new(FileMetadataStore::new(path)),
+        MetadataStoreOptions::DynamoDb {
+            table,
+            region,
+            endpoint_url,
+        } => {
+            let mut loader = aws_config::defaults(BehaviorVersion::latest());
+            if let Some(region) = region {
+                loader = loader.region(Region::new(region.clone()));
+            }
+            let sdk_config = loader.load().await;
+
+            let mut config = aws_sdk_dynamodb::config::Builder::from(&sdk_config);
+            if let Some(endpoint_url) = endpoint_url {
+                config = config.endpoint_url(endpoint_url);
+            }
+
+            Box::new(DynamoDbMetadataStore {
+                client: aws_sdk_dynamodb::Client::from_conf(config.build()),
+                table: table.clone(),
+                ttl_seconds,
+            })
+        }
+    }
+}
+
+/// ## FileMetadataStore - Struct
+///
+/// Stores every entry in a single JSON file, e.g. under `/tmp` or a mounted EFS volume.
+/// The file is read once, on the first lookup, and written back once per flush.
+pub struct FileMetadataStore {
+    path: PathBuf,
+    entries: tokio::sync::Mutex<Option<HashMap<String, CacheEntry>>>,
+    /// Whether entries were put since the file was last written.
+    changed: AtomicBool,
+}
+
+impl FileMetadataStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileMetadataStore {
+            path: path.into(),
+            entries: tokio::sync::Mutex::new(None),
+            changed: AtomicBool::new(false),
+        }
+    }
+
+    async fn entries(&self) -> tokio::sync::MutexGuard<'_, Option<HashMap<String, CacheEntry>>> {
+        let mut entries = self.entries.lock().await;
+        if entries.is_none() {
+            let stored = tokio::fs::read_to_string(&self.path)
+                .await
+                .ok()
+                .and_then(|json| serde_json::from_str(&json).ok())
+                .unwrap_or_default();
+            *entries = Some(stored);
+        }
+        entries
+    }
+}
+
+#[async_trait]
+impl MetadataStore for FileMetadataStore {
+    async fn get(&self, key: &str) -> Option<CacheEntry> {
+        self.entries()
+            .await
+            .as_ref()
+            .and_then(|entries| entries.get(key).cloned())
+    }
+
+    async fn put(&self, key: &str, entry: &CacheEntry) {
+        let mut entries = self.entries().await;
+        entries
+            .get_or_insert_with(HashMap::new)
+            .insert(key.to_string(), entry.clone());
+        self.changed.store(true, Ordering::Relaxed);
+    }
+
+    async fn flush(&self) {
+        let entries = self.entries().await;
+        if !self.changed.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        // The lock is held while writing, so that writes cannot interleave.
+        if let Ok(json) = serde_json::to_string(&*entries) {
+            if let Err(e) = tokio::fs::write(&self.path, json).await {
+                println!("Failed to write the metadata cache: {}", e);
+            }
+        }
+    }
+}
+
+/// ## DynamoDbMetadataStore - Struct
+///
+/// Stores each entry as an item of a DynamoDB table (or a DynamoDB-compatible stand-in),
+/// keyed by the string attribute `key`. Items carry an `expires_at` timestamp,
+/// so that the table's time to live can delete them once no invocation would use them.
+pub struct DynamoDbMetadataStore {
+    client: aws_sdk_dynamodb::Client,
+    table: String,
+    ttl_seconds: u64,
+}
+
+#[async_trait]
+impl MetadataStore for DynamoDbMetadataStore {
+    async fn get(&self, key: &str) -> Option<CacheEntry> {
+        let output = self
+            .client
+            .get_item()
+            .table_name(&self.table)
+            .key("key", AttributeValue::S(key.to_string()))
+            .send()
+            .await;
+        match output {
+            Ok(output) => match output.item?.get("entry") {
+                Some(AttributeValue::S(json)) => serde_json::from_str(json).ok(),
+                _ => None,
+            },
+            Err(e) => {
+                println!("Failed to read the metadata cache: {}", e);
+                None
+            }
+        }
+    }
+
+    async fn put(&self, key: &str, entry: &CacheEntry) {
+        let Ok(json) = serde_json::to_string(entry) else {
+            return;
+        };
+        let result = self
+            .client
+            .put_item()
+            .table_name(&self.table)
+            .item("key", AttributeValue::S(key.to_string()))
+            .item("entry", AttributeValue::S(json))
+            .item(
+                "expires_at",
+                AttributeValue::N((entry.fetched_at + self.ttl_seconds).to_string()),
+            )
+            .send()
+            .await;
+        if let Err(e) = result {
+            println!("Failed to write the metadata cache: {}", e);
+        }
+    }
+}
+
+type Memory = Mutex<HashMap<String, CacheEntry>>;
+
+/// Entries kept in memory for as long as the Lambda execution environment stays warm.
+fn memory() -> &'static Memory {
+    static MEMORY: OnceLock<Memory> = OnceLock::new();
+    MEMORY.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// A panic while the lock was held cannot leave a half-written entry behind,
+/// so the cache stays usable.
+fn lock(memory: &Memory) -> MutexGuard<'_, HashMap<String, CacheEntry>> {
+    memory.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// ## LinkMetadataCache - Struct
+///
+/// Looks up link metadata and file sizes in memory, then in the persistent store,
+/// and only fetches the URL when neither has a fresh entry.
+///
+/// Entries are kept per URL and host policy (`allowed_hosts` and `denied_hosts`), so an
+/// invocation never sees what was fetched, or failed, under another invocation's policy.
+pub struct LinkMetadataCache {
+    options: MetadataCacheOptions,
+    store: Option<Box<dyn MetadataStore>>,
+    memory: &'static Memory,
+}
+
+impl LinkMetadataCache {
+    pub async fn new(options: MetadataCacheOptions) -> Self {
+        let store = match &options.store {
+            Some(store_options) => {
+                let ttl_seconds = options.ttl_seconds.max(options.negative_ttl_seconds);
+                Some(build_metadata_store(store_options, ttl_seconds).await)
+            }
+            None => None,
+        };
+        LinkMetadataCache {
+            options,
+            store,
+            memory: memory(),
+        }
+    }
+
+    async fn get(&self, url: &str, fetch_options: &FetchOptions) -> Option<LinkMetadata> {
+        let fetch = async {
+            match fetch_link_metadata(url, fetch_options).await {
+                Ok(metadata) => Some(CachedValue::LinkMetadata(metadata)),
+                Err(e) => {
+                    println!("Failed to fetch metadata for {}: {}", url, e);
+                    None
+                }
+            }
+        };
+        match self.lookup(cache_key(url, fetch_options), fetch).await {
+            Some(CachedValue::LinkMetadata(metadata)) => Some(metadata),
+            _ => None,
+        }
+    }
+
+    async fn get_file_size(&self, url: &str, fetch_options: &FetchOptions) -> Option<u64> {
+        let fetch = async {
+            match fetch_file_size(url, fetch_options).await {
+                Ok(size) => size.map(CachedValue::FileSize),
+                Err(e) => {
+                    println!("Failed to fetch the size of {}: {}", url, e);
+                    None
+                }
+            }
+        };
+        let key = format!("file_size\n{}", cache_key(file_key(url), fetch_options));
+        match self.lookup(key, fetch).await {
+            Some(CachedValue::FileSize(size)) => Some(size),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of `key` from memory or the store while it is fresh,
+    /// or else the result of `fetch`, which is then kept in both.
+    async fn lookup(
+        &self,
+        key: String,
+        fetch: impl Future<Output = Option<CachedValue>>,
+    ) -> Option<CachedValue> {
+        let memory_entry = lock(self.memory).get(&key).cloned();
+        if let Some(entry) = memory_entry {
+            if self.is_fresh(&entry) {
+                return entry.value;
+            }
+        }
+
+        if let Some(store) = &self.store {
+            if let Some(entry) = store.get(&key).await {
+                if self.is_fresh(&entry) {
+                    self.remember(key, entry.clone());
+                    return entry.value;
+                }
+            }
+        }
+
+        let entry = CacheEntry {
+            value: fetch.await,
+            fetched_at: now(),
+        };
+        if let Some(store) = &self.store {
+            store.put(&key, &entry).await;
+        }
+        self.remember(key, entry.clone());
+
+        entry.value
+    }
+
+    /// Looks up the metadata of every URL, a few at a time. URLs without metadata are left out.
+    pub async fn get_all<'a>(
+        &'a self,
+        urls: impl IntoIterator<Item = &'a str>,
+        fetch_options: &FetchOptions,
+    ) -> HashMap<String, LinkMetadata> {
+        self.get_each(
+            urls.into_iter().map(|url| (url, fetch_options)),
+            |url, fetch_options| self.get(url, fetch_options),
+        )
+        .await
+    }
+
+    /// Looks up the size of every file, each with its own fetch options, a few at a time.
+    /// Files whose size is unknown are left out.
+    pub async fn get_file_sizes<'a>(
+        &'a self,
+        urls: impl IntoIterator<Item = (&'a str, &'a FetchOptions)>,
+    ) -> HashMap<String, u64> {
+        self.get_each(urls, |url, fetch_options| {
+            self.get_file_size(url, fetch_options)
+        })
+        .await
+    }
+
+    async fn get_each<'a, T, F, Fut>(
+        &'a self,
+        urls: impl IntoIterator<Item = (&'a str, &'a FetchOptions)>,
+        get: F,
+    ) -> HashMap<String, T>
+    where
+        F: Fn(&'a str, &'a FetchOptions) -> Fut,
+        Fut: Future<Output = Option<T>>,
+    {
+        let urls: HashMap<&str, &FetchOptions> = urls.into_iter().collect();
+        // Created up front, so that the stream holds no closure over borrowed URLs,
+        // whose futures would not be `Send` in the renderers' async traits.
+        let lookups: Vec<_> = urls
+            .into_iter()
+            .map(|(url, fetch_options)| {
+                let value = get(url, fetch_options);
+                async move { value.await.map(|value| (url.to_string(), value)) }
+            })
+            .collect();
+
+        let values: Vec<Option<(String, T)>> = stream::iter(lookups)
+            .buffer_unordered(CONCURRENT_FETCHES)
+            .collect()
+            .await;
+
+        if let Some(store) = &self.store {
+            store.flush().await;
+        }
+        values.into_iter().flatten().collect()
+    }
+
+    /// Keeps `entry` in memory. When the cache is full, expired entries are dropped first,
+    /// then the oldest ones.
+    fn remember(&self, key: String, entry: CacheEntry) {
+        let max_entries = self.options.max_entries;
+        if max_entries == 0 {
+            return;
+        }
+
+        let mut memory = lock(self.memory);
+        if memory.len() >= max_entries && !memory.contains_key(&key) {
+            memory.retain(|_, entry| self.is_fresh(entry));
+            while memory.len() >= max_entries {
+                let oldest = memory
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.fetched_at)
+                    .map(|(key, _)| key.clone());
+                match oldest {
+                    Some(oldest) => memory.remove(&oldest),
+                    None => break,
+                };
+            }
+        }
+        memory.insert(key, entry);
+    }
+
+    fn is_fresh(&self, entry: &CacheEntry) -> bool {
+        let ttl_seconds = match entry.value {
+            Some(_) => self.options.ttl_seconds,
+            None => self.options.negative_ttl_seconds,
+        };
+        now().saturating_sub(entry.fetched_at) < ttl_seconds
+    }
+}
+
+/// The URL, followed by the host policy when there is one.
+fn cache_key(url: &str, fetch_options: &FetchOptions) -> String {
+    if fetch_options.allowed_hosts.is_empty() && fetch_options.denied_hosts.is_empty() {
+        return url.to_string();
+    }
+
+    let normalize = |hosts: &[String]| {
+        let mut hosts: Vec<String> = hosts.iter().map(|host| host.to_ascii_lowercase()).collect();
+        hosts.sort_unstable();
+        hosts.dedup();
+        hosts.join(",")
+    };
+    format!(
+        "{}\nallow={}\ndeny={}",
+        url,
+        normalize(&fetch_options.allowed_hosts),
+        normalize(&fetch_options.denied_hosts)
+    )
+}
+
+/// Presigned S3 URLs (Notion-hosted files) are signed anew for every request,
+/// while the path keeps identifying the same file.
+fn file_key(url: &str) -> &str {
+    match url.split_once('?') {
+        Some((path, query)) if query.contains("X-Amz-Signature=") => path,
+        _ => url,
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A cache with its own memory, so that tests do not share entries.
+    fn cache(options: MetadataCacheOptions) -> LinkMetadataCache {
+        LinkMetadataCache {
+            options,
+            store: None,
+            memory: Box::leak(Box::new(Mutex::new(HashMap::new()))),
+        }
+    }
+
+    /// Fetching anything from `blocked.example` fails before any request is made.
+    fn blocking() -> FetchOptions {
+        FetchOptions {
+            denied_hosts: vec![String::from("blocked.example")],
+            ..FetchOptions::default()
+        }
+    }
+
+    fn metadata(title: &str) -> Option<LinkMetadata> {
+        Some(LinkMetadata {
+            title: Some(title.to_string()),
+            ..LinkMetadata::default()
+        })
+    }
+
+    fn cached(metadata: Option<LinkMetadata>) -> Option<CachedValue> {
+        metadata.map(CachedValue::LinkMetadata)
+    }
+
+    #[tokio::test]
+    async fn serves_fresh_entries_and_refetches_expired_ones() {
+        let cache = cache(MetadataCacheOptions {
+            ttl_seconds: 60,
+            ..MetadataCacheOptions::default()
+        });
+        let url = "https://blocked.example/a";
+        let key = cache_key(url, &blocking());
+
+        cache.remember(
+            key.clone(),
+            CacheEntry {
+                value: cached(metadata("Cached")),
+                fetched_at: now() - 30,
+            },
+        );
+        assert_eq!(cache.get(url, &blocking()).await, metadata("Cached"));
+
+        cache.remember(
+            key.clone(),
+            CacheEntry {
+                value: cached(metadata("Cached")),
+                fetched_at: now() - 90,
+            },
+        );
+        assert_eq!(cache.get(url, &blocking()).await, None);
+        assert!(lock(cache.memory)[&key].value.is_none());
+    }
+
+    #[tokio::test]
+    async fn remembers_failures_for_the_negative_ttl() {
+        let cache = cache(MetadataCacheOptions {
+            negative_ttl_seconds: 60,
+            ..MetadataCacheOptions::default()
+        });
+        let url = "https://blocked.example/b";
+        let key = cache_key(url, &blocking());
+
+        assert_eq!(cache.get(url, &blocking()).await, None);
+        let failure = lock(cache.memory)[&key].clone();
+        assert!(failure.value.is_none());
+
+        // A fresh failure is served as it is, without trying again.
+        assert_eq!(cache.get(url, &blocking()).await, None);
+        assert_eq!(lock(cache.memory)[&key].fetched_at, failure.fetched_at);
+
+        let expired = CacheEntry {
+            value: None,
+            fetched_at: now() - 90,
+        };
+        assert!(!cache.is_fresh(&expired));
+    }
+
+    #[tokio::test]
+    async fn keeps_entries_apart_by_host_policy() {
+        let cache = cache(MetadataCacheOptions::default());
+        let url = "https://blocked.example/c";
+        cache.remember(
+            url.to_string(),
+            CacheEntry {
+                value: cached(metadata("Fetched without a policy")),
+                fetched_at: now(),
+            },
+        );
+
+        // The invocation that denies the host does not get the other one's metadata.
+        assert_eq!(cache.get(url, &blocking()).await, None);
+        // Nor does its failure reach an invocation that allows the host.
+        assert_eq!(
+            cache.get(url, &FetchOptions::default()).await,
+            metadata("Fetched without a policy")
+        );
+
+        let reordered = FetchOptions {
+            denied_hosts: vec![
+                String::from("BLOCKED.example"),
+                String::from("blocked.example"),
+            ],
+            ..FetchOptions::default()
+        };
+        assert_eq!(cache_key(url, &reordered), cache_key(url, &blocking()));
+    }
+
+    #[tokio::test]
+    async fn file_sizes_are_kept_apart_from_metadata_and_without_signatures() {
+        let cache = cache(MetadataCacheOptions::default());
+        let url = "https://blocked.example/f.pdf";
+        let fetch_options = blocking();
+        cache.remember(
+            format!("file_size\n{}", cache_key(url, &fetch_options)),
+            CacheEntry {
+                value: Some(CachedValue::FileSize(42)),
+                fetched_at: now(),
+            },
+        );
+
+        let signed = "https://blocked.example/f.pdf?X-Amz-Signature=2";
+        let other = "https://blocked.example/f.pdf?version=2";
+        let file_sizes = cache
+            .get_file_sizes([(signed, &fetch_options), (other, &fetch_options)])
+            .await;
+        assert_eq!(file_sizes, HashMap::from([(signed.to_string(), 42)]));
+
+        // The size is not taken for the metadata of the same URL.
+        assert_eq!(cache.get(url, &fetch_options).await, None);
+    }
+
+    #[test]
+    fn evicts_expired_then_oldest_entries_when_full() {
+        let cache = cache(MetadataCacheOptions {
+            max_entries: 2,
+            ttl_seconds: 60,
+            ..MetadataCacheOptions::default()
+        });
+        let entry = |age| CacheEntry {
+            value: cached(metadata("x")),
+            fetched_at: now() - age,
+        };
+
+        cache.remember(String::from("expired"), entry(90));
+        cache.remember(String::from("old"), entry(20));
+        cache.remember(String::from("new"), entry(10));
+        cache.remember(String::from("newest"), entry(0));
+
+        let mut keys: Vec<String> = lock(cache.memory).keys().cloned().collect();
+        keys.sort();
+        assert_eq!(keys, ["new", "newest"]);
+    }
+
+    #[tokio::test]
+    async fn the_file_store_reads_its_file_once_and_writes_it_on_flush() {
+        let path =
+            std::env::temp_dir().join(format!("link-metadata-cache-{}.json", std::process::id()));
+        let entry = CacheEntry {
+            value: cached(metadata("Stored")),
+            fetched_at: 1,
+        };
+
+        let store = FileMetadataStore::new(&path);
+        assert!(store.get("a").await.is_none());
+        store.put("a", &entry).await;
+        store.put("b", &entry).await;
+        assert!(!path.exists());
+        store.flush().await;
+
+        // Later changes to the file are not read again by the same store...
+        tokio::fs::write(&path, "{}").await.unwrap();
+        assert_eq!(
+            store.get("a").await.unwrap().value,
+            cached(metadata("Stored"))
+        );
+        // ...nor overwritten when nothing was put since the last flush...
+        store.flush().await;
+        assert_eq!(tokio::fs::read_to_string(&path).await.unwrap(), "{}");
+
+        // ...but a new store starts from the file.
+        store.put("c", &entry).await;
+        store.flush().await;
+        let reopened = FileMetadataStore::new(&path);
+        assert!(reopened.get("a").await.is_some());
+        assert!(reopened.get("c").await.is_some());
+
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn get_all_flushes_the_store_once() {
+        let path = std::env::temp_dir().join(format!(
+            "link-metadata-cache-get-all-{}.json",
+            std::process::id()
+        ));
+        let cache = LinkMetadataCache {
+            store: Some(Box::new(FileMetadataStore::new(&path))),
+            ..cache(MetadataCacheOptions::default())
+        };
+
+        let urls = ["https://blocked.example/d", "https://blocked.example/e"];
+        assert!(cache.get_all(urls, &blocking()).await.is_empty());
+
+        let stored: HashMap<String, CacheEntry> =
+            serde_json::from_str(&tokio::fs::read_to_string(&path).await.unwrap()).unwrap();
+        assert_eq!(stored.len(), 2);
+        assert!(stored.values().all(|entry| entry.value.is_none()));
+
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+
+    #[test]
+    fn stores_are_selected_by_type() {
+        let options: MetadataCacheOptions = serde_json::from_value(serde_json::json!({
+            "store": { "type": "dynamodb", "table": "link-metadata",
+                "endpoint_url": "http://localhost:8000" }
+        }))
+        .unwrap();
+        assert!(matches!(
+            options.store,
+            Some(MetadataStoreOptions::DynamoDb { ref table, .. }) if table == "link-metadata"
+        ));
+
+        let options: MetadataCacheOptions = serde_json::from_value(serde_json::json!({
+            "store": { "type": "file", "path": "/tmp/link-metadata.json" }
+        }))
+        .unwrap();
+        assert!(matches!(
+            options.store,
+            Some(MetadataStoreOptions::File { .. })
+        ));
+    }
+}
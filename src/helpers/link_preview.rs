@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+
+use reqwest::Url;
+
+use crate::helpers::fetch_link_metadata::LinkMetadata;
+use crate::models::block::Block;
+use crate::models::rich_text::{Mention, RichTextElement};
+
+/// Paths on github.com whose first segment is not a user or an organization.
+const GITHUB_RESERVED_OWNERS: [&str; 12] = [
+    "about",
+    "apps",
+    "collections",
+    "explore",
+    "features",
+    "login",
+    "marketplace",
+    "orgs",
+    "settings",
+    "sponsors",
+    "topics",
+    "users",
+];
+
+/// What a link preview points at, for providers that are understood without a fetch.
+#[derive(Debug, PartialEq, Eq)]
+pub enum LinkPreviewKind {
+    GitHubRepository {
+        owner: String,
+        repo: String,
+    },
+    GitHubIssue {
+        owner: String,
+        repo: String,
+        number: u64,
+    },
+    GitHubPullRequest {
+        owner: String,
+        repo: String,
+        number: u64,
+    },
+    /// Any other link, which is previewed from its Open Graph metadata.
+    Generic,
+}
+
+impl LinkPreviewKind {
+    pub fn parse(url: &str) -> Self {
+        let url = match Url::parse(url) {
+            Ok(url) => url,
+            Err(_) => return LinkPreviewKind::Generic,
+        };
+
+        let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+        if host != "github.com" && host != "www.github.com" {
+            return LinkPreviewKind::Generic;
+        }
+
+        let segments: Vec<&str> = url
+            .path_segments()
+            .map(|segments| segments.filter(|s| !s.is_empty()).collect())
+            .unwrap_or_default();
+
+        match segments.as_slice() {
+            [owner, ..] if GITHUB_RESERVED_OWNERS.contains(owner) => LinkPreviewKind::Generic,
+            [owner, repo, "issues", number, ..] => match number.parse() {
+                Ok(number) => LinkPreviewKind::GitHubIssue {
+                    owner: owner.to_string(),
+                    repo: repo.to_string(),
+                    number,
+                },
+                Err(_) => LinkPreviewKind::Generic,
+            },
+            [owner, repo, "pull", number, ..] => match number.parse() {
+                Ok(number) => LinkPreviewKind::GitHubPullRequest {
+                    owner: owner.to_string(),
+                    repo: repo.to_string(),
+                    number,
+                },
+                Err(_) => LinkPreviewKind::Generic,
+            },
+            [owner, repo] => LinkPreviewKind::GitHubRepository {
+                owner: owner.to_string(),
+                repo: repo.trim_end_matches(".git").to_string(),
+            },
+            _ => LinkPreviewKind::Generic,
+        }
+    }
+
+    /// A short inline label, e.g. `owner/repo#123`.
+    pub fn label(&self) -> Option<String> {
+        match self {
+            LinkPreviewKind::GitHubRepository { owner, repo } => {
+                Some(format!("{}/{}", owner, repo))
+            }
+            LinkPreviewKind::GitHubIssue {
+                owner,
+                repo,
+                number,
+            }
+            | LinkPreviewKind::GitHubPullRequest {
+                owner,
+                repo,
+                number,
+            } => Some(format!("{}/{}#{}", owner, repo, number)),
+            LinkPreviewKind::Generic => None,
+        }
+    }
+
+    /// Card metadata for known providers. `None` means the link has to be fetched.
+    pub fn metadata(&self) -> Option<LinkMetadata> {
+        let description = match self {
+            LinkPreviewKind::GitHubRepository { .. } => String::from("Repository"),
+            LinkPreviewKind::GitHubIssue { number, .. } => format!("Issue #{}", number),
+            LinkPreviewKind::GitHubPullRequest { number, .. } => {
+                format!("Pull request #{}", number)
+            }
+            LinkPreviewKind::Generic => return None,
+        };
+
+        Some(LinkMetadata {
+            title: self.label(),
+            description: Some(description),
+            image: None,
+            site_name: Some(String::from("GitHub")),
+            favicon: Some(String::from("https://github.com/favicon.ico")),
+        })
+    }
+}
+
+/// Returns the card metadata of a link preview, from its provider or from `link_metadata`.
+pub fn link_preview_metadata(
+    url: &str,
+    link_metadata: &HashMap<String, LinkMetadata>,
+) -> LinkMetadata {
+    LinkPreviewKind::parse(url)
+        .metadata()
+        .or_else(|| link_metadata.get(url).cloned())
+        .unwrap_or_default()
+}
+
+/// Returns the URLs on a page whose metadata has to be fetched: bookmarks,
+/// and link previews (blocks and mentions) that no provider understands.
+pub fn collect_link_urls(blocks: &[Block]) -> Vec<&str> {
+    let mut urls = Vec::new();
+
+    for block in blocks {
+        match block {
+            Block::Bookmark(bookmark_block) => urls.push(bookmark_block.bookmark.url.as_str()),
+            Block::LinkPreview(link_preview_block) => {
+                let url = link_preview_block.link_preview.url.as_str();
+                if LinkPreviewKind::parse(url) == LinkPreviewKind::Generic {
+                    urls.push(url);
+                }
+            }
+            _ => {}
+        }
+
+        for rich_text in block.rich_text() {
+            if let RichTextElement::Mention {
+                mention: Mention::LinkPreview { link_preview },
+                ..
+            } = rich_text
+            {
+                if LinkPreviewKind::parse(&link_preview.url) == LinkPreviewKind::Generic {
+                    urls.push(link_preview.url.as_str());
+                }
+            }
+        }
+    }
+
+    urls
+}
+
+/// Replaces the text of link preview mentions with the provider label
+/// or the fetched title, so that they read like the card they stand for.
+pub fn resolve_link_preview_mentions(
+    blocks: &mut [Block],
+    link_metadata: &HashMap<String, LinkMetadata>,
+) {
+    for block in blocks.iter_mut() {
+        for rich_text in block.rich_text_mut() {
+            if let RichTextElement::Mention {
+                mention: Mention::LinkPreview { link_preview },
+                plain_text,
+                href,
+                ..
+            } = rich_text
+            {
+                let label = LinkPreviewKind::parse(&link_preview.url)
+                    .label()
+                    .or_else(|| {
+                        link_metadata
+                            .get(&link_preview.url)
+                            .and_then(|metadata| metadata.title.clone())
+                    });
+                if let Some(label) = label {
+                    *plain_text = label;
+                }
+                if href.is_none() {
+                    *href = Some(link_preview.url.clone());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{block, mention};
+    use serde_json::json;
+
+    #[test]
+    fn parses_github_issues_pull_requests_and_repositories() {
+        assert_eq!(
+            LinkPreviewKind::parse("https://github.com/rust-lang/rust/issues/123#issuecomment-1"),
+            LinkPreviewKind::GitHubIssue {
+                owner: String::from("rust-lang"),
+                repo: String::from("rust"),
+                number: 123,
+            }
+        );
+        assert_eq!(
+            LinkPreviewKind::parse("https://www.github.com/tokio-rs/tokio/pull/42/files"),
+            LinkPreviewKind::GitHubPullRequest {
+                owner: String::from("tokio-rs"),
+                repo: String::from("tokio"),
+                number: 42,
+            }
+        );
+        assert_eq!(
+            LinkPreviewKind::parse("https://GitHub.com/serde-rs/serde.git/"),
+            LinkPreviewKind::GitHubRepository {
+                owner: String::from("serde-rs"),
+                repo: String::from("serde"),
+            }
+        );
+
+        for url in [
+            "https://github.com/rust-lang/rust/issues/new",
+            "https://github.com/rust-lang/rust/tree/master",
+            "https://github.com/rust-lang",
+            "https://github.com/settings/profile",
+            "https://github.com/",
+            "https://gitlab.com/owner/repo/issues/1",
+            "https://www.figma.com/file/abc/Design",
+            "not a url",
+        ] {
+            assert_eq!(
+                LinkPreviewKind::parse(url),
+                LinkPreviewKind::Generic,
+                "{}",
+                url
+            );
+        }
+    }
+
+    #[test]
+    fn labels_and_describes_known_providers() {
+        let issue = LinkPreviewKind::parse("https://github.com/a/b/issues/7");
+        assert_eq!(issue.label().as_deref(), Some("a/b#7"));
+        let metadata = issue.metadata().unwrap();
+        assert_eq!(metadata.description.as_deref(), Some("Issue #7"));
+        assert_eq!(metadata.site_name.as_deref(), Some("GitHub"));
+
+        let repository = LinkPreviewKind::parse("https://github.com/a/b");
+        assert_eq!(repository.label().as_deref(), Some("a/b"));
+        assert_eq!(LinkPreviewKind::Generic.label(), None);
+        assert_eq!(LinkPreviewKind::Generic.metadata(), None);
+
+        // Generic links fall back to the fetched metadata.
+        let fetched = LinkMetadata {
+            title: Some(String::from("Design")),
+            ..LinkMetadata::default()
+        };
+        let link_metadata = HashMap::from([(
+            String::from("https://www.figma.com/file/abc"),
+            fetched.clone(),
+        )]);
+        assert_eq!(
+            link_preview_metadata("https://www.figma.com/file/abc", &link_metadata),
+            fetched
+        );
+        assert_eq!(
+            link_preview_metadata("https://example.com/", &link_metadata),
+            LinkMetadata::default()
+        );
+    }
+
+    #[test]
+    fn only_generic_links_are_fetched_and_mentions_read_like_their_card() {
+        let mention = |url: &str| {
+            mention(
+                json!({ "type": "link_preview", "link_preview": { "url": url } }),
+                url,
+                None,
+            )
+        };
+        let mut blocks = vec![
+            block(
+                "bookmark",
+                json!({ "url": "https://example.com/post", "caption": [] }),
+            ),
+            block(
+                "link_preview",
+                json!({ "url": "https://github.com/a/b/pull/1" }),
+            ),
+            block(
+                "link_preview",
+                json!({ "url": "https://www.figma.com/file/abc" }),
+            ),
+            block(
+                "paragraph",
+                json!({
+                    "rich_text": [
+                        mention("https://github.com/a/b/issues/2"),
+                        mention("https://example.com/doc"),
+                    ],
+                    "color": "default"
+                }),
+            ),
+        ];
+
+        assert_eq!(
+            collect_link_urls(&blocks),
+            [
+                "https://example.com/post",
+                "https://www.figma.com/file/abc",
+                "https://example.com/doc"
+            ]
+        );
+
+        let link_metadata = HashMap::from([(
+            String::from("https://example.com/doc"),
+            LinkMetadata {
+                title: Some(String::from("The doc")),
+                ..LinkMetadata::default()
+            },
+        )]);
+        resolve_link_preview_mentions(&mut blocks, &link_metadata);
+        let labels: Vec<(&str, Option<&str>)> = blocks[3]
+            .rich_text()
+            .into_iter()
+            .map(|element| match element {
+                RichTextElement::Mention {
+                    plain_text, href, ..
+                } => (plain_text.as_str(), href.as_deref()),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                ("a/b#2", Some("https://github.com/a/b/issues/2")),
+                ("The doc", Some("https://example.com/doc")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use reqwest::Url;
+
+use crate::models::block::Block;
+use crate::models::rich_text::RichTextElement;
+use crate::options::LinkOptions;
+
+/// A page (and optionally a block on it) that a Notion URL points at.
+/// Ids are lower-case and without dashes.
+#[derive(Debug, PartialEq, Eq)]
+pub struct NotionLink {
+    pub page_id: String,
+    pub block_id: Option<String>,
+}
+
+impl NotionLink {
+    /// Recognizes `notion.so`, `notion.com` (with or without `www.`) and `*.notion.site` URLs such as
+    /// `https://www.notion.so/workspace/Title-0123...cdef#4567...89ab`.
+    pub fn parse(url: &str) -> Option<Self> {
+        let url = Url::parse(url).ok()?;
+        let host = url.host_str()?.to_ascii_lowercase();
+        let host = host.trim_start_matches("www.");
+        if host != "notion.so" && host != "notion.com" && !host.ends_with(".notion.site") {
+            return None;
+        }
+
+        // A page opened as a side peek is linked as `?p=<page id>`.
+        let peeked_page_id = url
+            .query_pairs()
+            .find(|(key, _)| key == "p")
+            .and_then(|(_, value)| extract_id(&value));
+
+        let page_id = peeked_page_id.or_else(|| {
+            url.path_segments()?
+                .rev()
+                .find(|segment| !segment.is_empty())
+                .and_then(extract_id)
+        })?;
+
+        let block_id = url.fragment().and_then(extract_id);
+
+        Some(NotionLink { page_id, block_id })
+    }
+}
+
+/// ## LinkRewriter - Struct
+///
+/// Rewrites links to Notion pages into links to the pages on our own site,
+/// through a map of page ids to paths or the page URL template of the mention options.
+pub struct LinkRewriter {
+    link_map: HashMap<String, String>,
+    page_url_template: Option<String>,
+    unresolved: Mutex<Vec<String>>,
+}
+
+impl LinkRewriter {
+    pub fn new(options: LinkOptions, page_url_template: Option<String>) -> Self {
+        let link_map = options
+            .link_map
+            .into_iter()
+            .filter_map(|(id, path)| extract_id(&id).map(|id| (id, path)))
+            .collect();
+
+        LinkRewriter {
+            link_map,
+            page_url_template,
+            unresolved: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the rewritten URL for Notion links, or `None` for any other URL.
+    /// Notion links that cannot be resolved are kept as they are and reported.
+    pub fn rewrite_url(&self, url: &str) -> Option<String> {
+        let link = NotionLink::parse(url)?;
+
+        let path = self.link_map.get(&link.page_id).cloned().or_else(|| {
+            self.page_url_template
+                .as_deref()
+                .map(|template| page_url(template, &link.page_id))
+        });
+
+        match path {
+            Some(path) => Some(match &link.block_id {
+                Some(block_id) => format!("{}#{}", path, block_anchor(block_id)),
+                None => path,
+            }),
+            None => {
+                self.unresolved.lock().unwrap().push(url.to_string());
+                None
+            }
+        }
+    }
+
+    /// Rewrites every link in `blocks`: rich text links, bookmarks and link previews.
+    pub fn rewrite(&self, blocks: &mut [Block]) {
+        for block in blocks.iter_mut() {
+            match block {
+                Block::Bookmark(bookmark_block) => {
+                    self.rewrite_in_place(&mut bookmark_block.bookmark.url);
+                }
+                Block::LinkPreview(link_preview_block) => {
+                    self.rewrite_in_place(&mut link_preview_block.link_preview.url);
+                }
+                _ => {}
+            }
+
+            for rich_text in block.rich_text_mut() {
+                let href = match rich_text {
+                    RichTextElement::Text { text, href, .. } => {
+                        if let Some(link) = &mut text.link {
+                            self.rewrite_in_place(&mut link.url);
+                        }
+                        href
+                    }
+                    RichTextElement::Mention { href, .. }
+                    | RichTextElement::Equation { href, .. } => href,
+                };
+                if let Some(href) = href {
+                    self.rewrite_in_place(href);
+                }
+            }
+        }
+    }
+
+    /// Notion links that could not be resolved so far, without duplicates.
+    pub fn unresolved(&self) -> Vec<String> {
+        let mut unresolved = self.unresolved.lock().unwrap().clone();
+        unresolved.sort_unstable();
+        unresolved.dedup();
+        unresolved
+    }
+
+    fn rewrite_in_place(&self, url: &mut String) {
+        if let Some(rewritten) = self.rewrite_url(url) {
+            *url = rewritten;
+        }
+    }
+}
+
+/// Fills in a page URL template: `{id}` with the dashed id of the page and `{compact_id}`
+/// with the id without dashes, whichever form `page_id` is in.
+pub fn page_url(template: &str, page_id: &str) -> String {
+    let Some(compact_id) = extract_id(page_id) else {
+        return template
+            .replace("{id}", page_id)
+            .replace("{compact_id}", page_id);
+    };
+    let dashed_id = format!(
+        "{}-{}-{}-{}-{}",
+        &compact_id[..8],
+        &compact_id[8..12],
+        &compact_id[12..16],
+        &compact_id[16..20],
+        &compact_id[20..]
+    );
+    template
+        .replace("{id}", &dashed_id)
+        .replace("{compact_id}", &compact_id)
+}
+
+/// The fragment that links to the block `block_id` point at, and so the `id` of its element:
+/// the id without dashes.
+pub fn block_anchor(block_id: &str) -> String {
+    extract_id(block_id).unwrap_or_else(|| block_id.replace('-', ""))
+}
+
+/// Extracts a 32-digit hex id from the end of `value`, with or without dashes,
+/// e.g. from `Title-0123456789abcdef0123456789abcdef`.
+fn extract_id(value: &str) -> Option<String> {
+    let hex: String = value
+        .chars()
+        .rev()
+        .filter(|c| *c != '-')
+        .take_while(|c| c.is_ascii_hexdigit())
+        .take(32)
+        .collect::<Vec<char>>()
+        .into_iter()
+        .rev()
+        .collect();
+
+    if hex.len() == 32 {
+        Some(hex.to_ascii_lowercase())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE_ID: &str = "0123456789abcdef0123456789abcdef";
+    const BLOCK_ID: &str = "fedcba9876543210fedcba9876543210";
+
+    fn link(block_id: Option<&str>) -> Option<NotionLink> {
+        Some(NotionLink {
+            page_id: String::from(PAGE_ID),
+            block_id: block_id.map(String::from),
+        })
+    }
+
+    #[test]
+    fn parses_notion_links() {
+        for url in [
+            "https://www.notion.so/workspace/About-us-0123456789abcdef0123456789abcdef",
+            "https://notion.so/0123456789ABCDEF0123456789ABCDEF",
+            "https://www.notion.com/01234567-89ab-cdef-0123-456789abcdef",
+            "https://acme.notion.site/About-0123456789abcdef0123456789abcdef/",
+            "https://www.notion.so/workspace/Board-1111?p=0123456789abcdef0123456789abcdef&pm=s",
+        ] {
+            assert_eq!(NotionLink::parse(url), link(None), "{}", url);
+        }
+
+        assert_eq!(
+            NotionLink::parse(&format!(
+                "https://www.notion.so/About-{}#{}",
+                PAGE_ID, BLOCK_ID
+            )),
+            link(Some(BLOCK_ID))
+        );
+        assert_eq!(
+            NotionLink::parse(&format!("https://www.notion.so/About-{}#intro", PAGE_ID)),
+            link(None)
+        );
+
+        for url in [
+            "https://example.com/About-0123456789abcdef0123456789abcdef",
+            "https://notion.site.example.com/0123456789abcdef0123456789abcdef",
+            "https://www.notion.so/About-0123",
+            "https://www.notion.so/",
+            "/About-0123456789abcdef0123456789abcdef",
+        ] {
+            assert_eq!(NotionLink::parse(url), None, "{}", url);
+        }
+    }
+
+    #[test]
+    fn rewrites_through_the_link_map_then_the_template() {
+        let rewriter = LinkRewriter::new(
+            LinkOptions {
+                link_map: HashMap::from([(
+                    String::from("01234567-89ab-cdef-0123-456789abcdef"),
+                    String::from("/about"),
+                )]),
+            },
+            Some(String::from("/posts/{id}?c={compact_id}")),
+        );
+
+        assert_eq!(
+            rewriter.rewrite_url(&format!(
+                "https://www.notion.so/About-{}#{}",
+                PAGE_ID, BLOCK_ID
+            )),
+            Some(format!("/about#{}", BLOCK_ID))
+        );
+        assert_eq!(
+            rewriter.rewrite_url("https://acme.notion.site/Post-ffffffffffffffffffffffffffffffff"),
+            Some(String::from(
+                "/posts/ffffffff-ffff-ffff-ffff-ffffffffffff?c=ffffffffffffffffffffffffffffffff"
+            ))
+        );
+        assert_eq!(rewriter.rewrite_url("https://example.com/"), None);
+        assert!(rewriter.unresolved().is_empty());
+
+        let rewriter = LinkRewriter::new(LinkOptions::default(), None);
+        let url = "https://www.notion.so/Post-ffffffffffffffffffffffffffffffff";
+        assert_eq!(rewriter.rewrite_url(url), None);
+        assert_eq!(rewriter.unresolved(), [url]);
+    }
+
+    #[test]
+    fn page_urls_use_one_id_convention_for_both_id_forms() {
+        for id in [PAGE_ID, "01234567-89AB-cdef-0123-456789abcdef"] {
+            assert_eq!(
+                page_url("/p/{id}/{compact_id}", id),
+                format!("/p/01234567-89ab-cdef-0123-456789abcdef/{}", PAGE_ID)
+            );
+        }
+    }
+}
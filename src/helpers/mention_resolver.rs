@@ -0,0 +1,426 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use futures::future::join_all;
+use lambda_runtime::Error;
+
+use crate::helpers::get_database::get_database;
+use crate::helpers::get_page::get_page;
+use crate::helpers::get_user::get_user;
+use crate::helpers::link_rewriter::page_url;
+use crate::models::block::Block;
+use crate::models::objects::User;
+use crate::models::rich_text::{Mention, RichTextElement};
+use crate::options::MentionOptions;
+
+/// The title and link of a mentioned page or database.
+#[derive(Debug, Clone)]
+pub struct ResolvedLink {
+    pub title: String,
+    pub url: String,
+}
+
+impl ResolvedLink {
+    /// Links a mention here, titled with the title unless it is empty.
+    fn apply(&self, plain_text: &mut String, href: &mut Option<String>) {
+        if !self.title.is_empty() {
+            *plain_text = self.title.clone();
+        }
+        *href = Some(self.url.clone());
+    }
+}
+
+/// Where mentioned pages, databases and users are looked up.
+#[async_trait]
+pub trait MentionSource: Send + Sync {
+    /// The title of the page `page_id` and its Notion URL, the public one if it is shared.
+    async fn page(&self, page_id: &str) -> Result<ResolvedLink, Error>;
+
+    /// The title of the database `database_id` and its Notion URL, the public one if it is shared.
+    async fn database(&self, database_id: &str) -> Result<ResolvedLink, Error>;
+
+    async fn user(&self, user_id: &str) -> Result<User, Error>;
+}
+
+/// Looks mentions up through the Notion API.
+struct NotionMentionSource {
+    notion_api_key: String,
+}
+
+#[async_trait]
+impl MentionSource for NotionMentionSource {
+    async fn page(&self, page_id: &str) -> Result<ResolvedLink, Error> {
+        let page = get_page(&self.notion_api_key, page_id).await?;
+        Ok(ResolvedLink {
+            title: page.title(),
+            url: page.public_url.unwrap_or(page.url),
+        })
+    }
+
+    async fn database(&self, database_id: &str) -> Result<ResolvedLink, Error> {
+        let database = get_database(&self.notion_api_key, database_id).await?;
+        Ok(ResolvedLink {
+            title: database.title(),
+            url: database.public_url.unwrap_or(database.url),
+        })
+    }
+
+    async fn user(&self, user_id: &str) -> Result<User, Error> {
+        get_user(&self.notion_api_key, user_id).await
+    }
+}
+
+/// ## MentionResolver - Struct
+///
+/// Resolves page, database and user mentions through the Notion API.
+/// Results (including failures) are cached for the rest of the invocation.
+pub struct MentionResolver {
+    source: Box<dyn MentionSource>,
+    options: MentionOptions,
+    links: Mutex<HashMap<String, Option<ResolvedLink>>>,
+    users: Mutex<HashMap<String, Option<User>>>,
+}
+
+impl MentionResolver {
+    pub fn new(notion_api_key: &str, options: MentionOptions) -> Self {
+        Self::with_source(
+            Box::new(NotionMentionSource {
+                notion_api_key: notion_api_key.to_string(),
+            }),
+            options,
+        )
+    }
+
+    /// A resolver that looks mentions up in `source` instead of the Notion API.
+    pub fn with_source(source: Box<dyn MentionSource>, options: MentionOptions) -> Self {
+        MentionResolver {
+            source,
+            options,
+            links: Mutex::new(HashMap::new()),
+            users: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Fills in the title and link of every page and database mention,
+    /// and the name and avatar of every user mention, in `blocks`.
+    /// An empty title or name leaves the text of the mention as Notion wrote it.
+    pub async fn resolve(&self, blocks: &mut [Block]) {
+        let mut page_ids = Vec::new();
+        let mut database_ids = Vec::new();
+        let mut user_ids = Vec::new();
+
+        for rich_text in blocks.iter().flat_map(|block| block.rich_text()) {
+            if let RichTextElement::Mention { mention, .. } = rich_text {
+                match mention {
+                    Mention::Page { page } => page_ids.push(page.id.clone()),
+                    Mention::Database { database } => database_ids.push(database.id.clone()),
+                    Mention::User { user } => user_ids.push(user.id.clone()),
+                    _ => {}
+                }
+            }
+        }
+
+        let page_ids = self.uncached(&self.links, page_ids);
+        let database_ids = self.uncached(&self.links, database_ids);
+        let user_ids = self.uncached(&self.users, user_ids);
+
+        let (pages, databases, users) = futures::join!(
+            join_all(page_ids.iter().map(|id| self.fetch_page(id))),
+            join_all(database_ids.iter().map(|id| self.fetch_database(id))),
+            join_all(user_ids.iter().map(|id| self.fetch_user(id))),
+        );
+
+        {
+            let mut links = self.links.lock().unwrap();
+            links.extend(page_ids.into_iter().zip(pages));
+            links.extend(database_ids.into_iter().zip(databases));
+            self.users
+                .lock()
+                .unwrap()
+                .extend(user_ids.into_iter().zip(users));
+        }
+
+        let links = self.links.lock().unwrap();
+        let users = self.users.lock().unwrap();
+
+        for rich_text in blocks.iter_mut().flat_map(|block| block.rich_text_mut()) {
+            if let RichTextElement::Mention {
+                mention,
+                plain_text,
+                href,
+                ..
+            } = rich_text
+            {
+                match mention {
+                    Mention::Page { page: mentioned } => {
+                        if let Some(Some(link)) = links.get(&mentioned.id) {
+                            link.apply(plain_text, href);
+                        }
+                    }
+                    Mention::Database {
+                        database: mentioned,
+                    } => {
+                        if let Some(Some(link)) = links.get(&mentioned.id) {
+                            link.apply(plain_text, href);
+                        }
+                    }
+                    Mention::User { user: mentioned } => {
+                        if let Some(Some(user)) = users.get(&mentioned.id) {
+                            if let Some(name) = user.name.as_ref().filter(|name| !name.is_empty()) {
+                                *plain_text = format!("@{}", name);
+                                mentioned.name = Some(name.clone());
+                            }
+                            if mentioned.avatar_url.is_none() {
+                                mentioned.avatar_url = user.avatar_url.clone();
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Deduplicates `ids` and drops those already in `cache`.
+    fn uncached<T>(&self, cache: &Mutex<HashMap<String, T>>, mut ids: Vec<String>) -> Vec<String> {
+        let cache = cache.lock().unwrap();
+        ids.sort_unstable();
+        ids.dedup();
+        ids.retain(|id| !cache.contains_key(id));
+        ids
+    }
+
+    async fn fetch_page(&self, page_id: &str) -> Option<ResolvedLink> {
+        match self.source.page(page_id).await {
+            Ok(link) => Some(ResolvedLink {
+                url: self.url_for(page_id, &link.url),
+                ..link
+            }),
+            Err(e) => {
+                println!("Failed to resolve the page mention {}: {}", page_id, e);
+                None
+            }
+        }
+    }
+
+    async fn fetch_database(&self, database_id: &str) -> Option<ResolvedLink> {
+        match self.source.database(database_id).await {
+            Ok(link) => Some(ResolvedLink {
+                url: self.url_for(database_id, &link.url),
+                ..link
+            }),
+            Err(e) => {
+                println!(
+                    "Failed to resolve the database mention {}: {}",
+                    database_id, e
+                );
+                None
+            }
+        }
+    }
+
+    async fn fetch_user(&self, user_id: &str) -> Option<User> {
+        match self.source.user(user_id).await {
+            Ok(user) => Some(user),
+            Err(e) => {
+                println!("Failed to resolve the user mention {}: {}", user_id, e);
+                None
+            }
+        }
+    }
+
+    fn url_for(&self, id: &str, notion_url: &str) -> String {
+        match &self.options.page_url_template {
+            Some(template) => page_url(template, id),
+            None => notion_url.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{block, mention};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    const PAGE_ID: &str = "0123456789abcdef0123456789abcdef";
+
+    /// Knows one page, one untitled page, one database and one user, and records every lookup.
+    struct Stub {
+        lookups: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl MentionSource for Stub {
+        async fn page(&self, page_id: &str) -> Result<ResolvedLink, Error> {
+            self.lookups
+                .lock()
+                .unwrap()
+                .push(format!("page {}", page_id));
+            let title = match page_id {
+                PAGE_ID => "Setup",
+                "untitled" => "",
+                _ => return Err(Error::from("Could not find the page")),
+            };
+            Ok(ResolvedLink {
+                title: String::from(title),
+                url: format!("https://www.notion.so/{}", page_id),
+            })
+        }
+
+        async fn database(&self, database_id: &str) -> Result<ResolvedLink, Error> {
+            self.lookups
+                .lock()
+                .unwrap()
+                .push(format!("database {}", database_id));
+            Ok(ResolvedLink {
+                title: String::from("Tasks"),
+                url: String::from("https://acme.notion.site/Tasks"),
+            })
+        }
+
+        async fn user(&self, user_id: &str) -> Result<User, Error> {
+            self.lookups
+                .lock()
+                .unwrap()
+                .push(format!("user {}", user_id));
+            Ok(serde_json::from_value(json!({
+                "object": "user",
+                "id": user_id,
+                "name": "Ada",
+                "avatar_url": "https://example.com/ada.png"
+            }))
+            .unwrap())
+        }
+    }
+
+    fn resolver(page_url_template: Option<&str>) -> (MentionResolver, Arc<Mutex<Vec<String>>>) {
+        let lookups = Arc::new(Mutex::new(Vec::new()));
+        let resolver = MentionResolver::with_source(
+            Box::new(Stub {
+                lookups: lookups.clone(),
+            }),
+            MentionOptions {
+                page_url_template: page_url_template.map(String::from),
+            },
+        );
+        (resolver, lookups)
+    }
+
+    fn blocks() -> Vec<Block> {
+        let page = |id: &str, plain_text: &str| {
+            mention(
+                json!({ "type": "page", "page": { "id": id } }),
+                plain_text,
+                None,
+            )
+        };
+        vec![block(
+            "paragraph",
+            json!({
+                "rich_text": [
+                    page(PAGE_ID, "Old title"),
+                    page("untitled", "As Notion wrote it"),
+                    page("missing", "Missing"),
+                    mention(json!({ "type": "database", "database": { "id": "d1" } }), "Old", None),
+                    mention(json!({ "type": "user", "user": { "id": "u1" } }), "@Anonymous", None),
+                    page(PAGE_ID, "Old title"),
+                ],
+                "color": "default"
+            }),
+        )]
+    }
+
+    /// The text and link of every mention in `blocks`.
+    fn mentions(blocks: &[Block]) -> Vec<(String, Option<String>)> {
+        blocks
+            .iter()
+            .flat_map(|block| block.rich_text())
+            .map(|element| match element {
+                RichTextElement::Mention {
+                    plain_text, href, ..
+                } => (plain_text.clone(), href.clone()),
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn urls_follow_the_template_or_else_notion() {
+        let (templated, _) = resolver(Some("/posts/{id}"));
+        assert_eq!(
+            templated.url_for(PAGE_ID, "https://www.notion.so/Setup"),
+            "/posts/01234567-89ab-cdef-0123-456789abcdef"
+        );
+
+        let (untemplated, _) = resolver(None);
+        assert_eq!(
+            untemplated.url_for(PAGE_ID, "https://www.notion.so/Setup"),
+            "https://www.notion.so/Setup"
+        );
+    }
+
+    #[tokio::test]
+    async fn mentions_get_their_title_and_link() {
+        let (resolver, _) = resolver(Some("/p/{compact_id}"));
+        let mut blocks = blocks();
+        resolver.resolve(&mut blocks).await;
+
+        let page = (String::from("Setup"), Some(format!("/p/{}", PAGE_ID)));
+        assert_eq!(
+            mentions(&blocks),
+            [
+                page.clone(),
+                // An empty title keeps the text of the mention.
+                (
+                    String::from("As Notion wrote it"),
+                    Some(String::from("/p/untitled"))
+                ),
+                (String::from("Missing"), None),
+                (String::from("Tasks"), Some(String::from("/p/d1"))),
+                (String::from("@Ada"), None),
+                page,
+            ]
+        );
+
+        match &blocks[0].rich_text()[4] {
+            RichTextElement::Mention {
+                mention: Mention::User { user },
+                ..
+            } => {
+                assert_eq!(user.name.as_deref(), Some("Ada"));
+                assert_eq!(
+                    user.avatar_url.as_deref(),
+                    Some("https://example.com/ada.png")
+                );
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn each_mention_is_looked_up_once_per_invocation() {
+        let (resolver, lookups) = resolver(None);
+        resolver.resolve(&mut blocks()).await;
+        let mut second = blocks();
+        resolver.resolve(&mut second).await;
+
+        let mut lookups = lookups.lock().unwrap().clone();
+        lookups.sort();
+        assert_eq!(
+            lookups,
+            [
+                String::from("database d1"),
+                format!("page {}", PAGE_ID),
+                String::from("page missing"),
+                String::from("page untitled"),
+                String::from("user u1"),
+            ]
+        );
+        // Cached results, failures included, still apply to later blocks.
+        assert_eq!(mentions(&second)[0].0, "Setup");
+        assert_eq!(mentions(&second)[2], (String::from("Missing"), None));
+    }
+}
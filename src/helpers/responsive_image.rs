@@ -0,0 +1,364 @@
+use std::io::Cursor;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, ImageReader, Limits};
+
+use crate::options::{ImageOptions, PlaceholderKind};
+
+/// ## ResponsiveImage - Struct
+///
+/// A localized image together with its resized copies, for `srcset`.
+#[derive(Debug, Clone)]
+pub struct ResponsiveImage {
+    pub width: u32,
+    pub height: u32,
+    /// Copies in the format of the original, including the original itself.
+    pub sources: Vec<ImageSource>,
+    /// WebP copies of every size in `sources`, or none when WebP turned out larger overall.
+    pub webp_sources: Vec<ImageSource>,
+    pub sizes: String,
+    pub placeholder: Option<Placeholder>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImageSource {
+    pub url: String,
+    pub width: u32,
+}
+
+#[derive(Debug, Clone)]
+pub enum Placeholder {
+    Blurhash(String),
+    /// A `data:` URI.
+    Lqip(String),
+}
+
+/// Formats a `srcset` attribute value, e.g. `a.jpg 480w, b.jpg 960w`.
+pub fn srcset(sources: &[ImageSource]) -> String {
+    sources
+        .iter()
+        .map(|source| format!("{} {}w", source.url, source.width))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// ## ProcessedImage - Struct
+///
+/// The result of [`process_image`], before the copies are stored.
+pub struct ProcessedImage {
+    pub width: u32,
+    pub height: u32,
+    pub variants: Vec<ImageVariant>,
+    pub placeholder: Option<Placeholder>,
+}
+
+pub struct ImageVariant {
+    pub width: u32,
+    pub extension: &'static str,
+    pub content_type: &'static str,
+    pub bytes: Vec<u8>,
+}
+
+impl ImageVariant {
+    pub fn is_webp(&self) -> bool {
+        self.extension == "webp"
+    }
+}
+
+/// Images larger than this in either dimension are not decoded, since a small file
+/// can decode to gigabytes of pixels.
+const MAX_DIMENSION: u32 = 12_000;
+
+/// The quality of lossy WebP copies, in line with the JPEG copies.
+const WEBP_QUALITY: f32 = 82.0;
+
+/// Reads the dimensions of an image and makes its resized copies and placeholder.
+/// Returns `None` for anything that cannot be decoded, such as SVG, or that is larger
+/// than [`MAX_DIMENSION`].
+///
+/// Animated GIFs only get their dimensions, since resizing would drop the animation.
+/// WebP copies are made of every size, lossy for JPEGs and lossless otherwise, and are
+/// kept only when they are smaller than the copies in the original format altogether,
+/// so that `<picture>` offers WebP for all sizes or for none.
+pub fn process_image(bytes: &[u8], options: &ImageOptions) -> Option<ProcessedImage> {
+    let format = image::guess_format(bytes).ok()?;
+    let image = decode(bytes, format)?;
+    let (width, height) = (image.width(), image.height());
+
+    let mut variants = Vec::new();
+
+    if format != ImageFormat::Gif {
+        let mut widths: Vec<u32> = options
+            .widths
+            .iter()
+            .copied()
+            .filter(|variant_width| *variant_width > 0 && *variant_width < width)
+            .collect();
+        widths.sort_unstable();
+        widths.dedup();
+
+        let make_webp = options.webp && format != ImageFormat::WebP;
+        let mut webp_variants = Vec::new();
+        let mut original_bytes = bytes.len();
+
+        for variant_width in widths {
+            let resized = image.resize(variant_width, u32::MAX, FilterType::Lanczos3);
+            if let Some(variant) = encode(&resized, format) {
+                if make_webp {
+                    webp_variants.push(encode_webp(&resized, format));
+                }
+                original_bytes += variant.bytes.len();
+                variants.push(variant);
+            }
+        }
+
+        if make_webp {
+            webp_variants.push(encode_webp(&image, format));
+            let webp_bytes: usize = webp_variants
+                .iter()
+                .map(|variant| variant.bytes.len())
+                .sum();
+            if webp_bytes < original_bytes {
+                variants.extend(webp_variants);
+            }
+        }
+    }
+
+    let placeholder = match options.placeholder {
+        PlaceholderKind::None => None,
+        PlaceholderKind::Blurhash => blurhash(&image).map(Placeholder::Blurhash),
+        PlaceholderKind::Lqip => lqip(&image).map(Placeholder::Lqip),
+    };
+
+    Some(ProcessedImage {
+        width,
+        height,
+        variants,
+        placeholder,
+    })
+}
+
+/// Encodes a resized copy in the format of the original.
+fn encode(image: &DynamicImage, format: ImageFormat) -> Option<ImageVariant> {
+    let mut bytes = Vec::new();
+    let (extension, content_type) = match format {
+        ImageFormat::Jpeg => {
+            JpegEncoder::new_with_quality(&mut bytes, 82)
+                .encode_image(&image.to_rgb8())
+                .ok()?;
+            ("jpg", "image/jpeg")
+        }
+        ImageFormat::Png => {
+            image.write_with_encoder(PngEncoder::new(&mut bytes)).ok()?;
+            ("png", "image/png")
+        }
+        ImageFormat::WebP => {
+            image
+                .to_rgba8()
+                .write_with_encoder(WebPEncoder::new_lossless(&mut bytes))
+                .ok()?;
+            ("webp", "image/webp")
+        }
+        _ => return None,
+    };
+
+    Some(ImageVariant {
+        width: image.width(),
+        extension,
+        content_type,
+        bytes,
+    })
+}
+
+/// Decodes an image, refusing dimensions above [`MAX_DIMENSION`] before any pixels are read.
+fn decode(bytes: &[u8], format: ImageFormat) -> Option<DynamicImage> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+
+    let mut reader = ImageReader::with_format(Cursor::new(bytes), format);
+    reader.limits(limits);
+    reader.decode().ok()
+}
+
+/// Encodes a WebP copy: lossy for photos that were lossy already, lossless for the rest.
+fn encode_webp(image: &DynamicImage, original_format: ImageFormat) -> ImageVariant {
+    let bytes = if original_format == ImageFormat::Jpeg {
+        let rgb = image.to_rgb8();
+        webp::Encoder::from_rgb(rgb.as_raw(), rgb.width(), rgb.height())
+            .encode(WEBP_QUALITY)
+            .to_vec()
+    } else {
+        let rgba = image.to_rgba8();
+        webp::Encoder::from_rgba(rgba.as_raw(), rgba.width(), rgba.height())
+            .encode_lossless()
+            .to_vec()
+    };
+
+    ImageVariant {
+        width: image.width(),
+        extension: "webp",
+        content_type: "image/webp",
+        bytes,
+    }
+}
+
+fn blurhash(image: &DynamicImage) -> Option<String> {
+    let thumbnail = image.thumbnail(32, 32).to_rgba8();
+    blurhash::encode(
+        4,
+        3,
+        thumbnail.width(),
+        thumbnail.height(),
+        thumbnail.as_raw(),
+    )
+    .ok()
+}
+
+fn lqip(image: &DynamicImage) -> Option<String> {
+    let thumbnail = image.thumbnail(16, 16).to_rgb8();
+    let mut bytes = Vec::new();
+    JpegEncoder::new_with_quality(&mut bytes, 50)
+        .encode_image(&thumbnail)
+        .ok()?;
+    Some(format!("data:image/jpeg;base64,{}", STANDARD.encode(bytes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    /// A photo-like image, with enough detail that the encoders have something to do.
+    fn photo(width: u32, height: u32, format: ImageFormat) -> Vec<u8> {
+        let image = RgbImage::from_fn(width, height, |x, y| {
+            let noise = (x.wrapping_mul(7919) ^ y.wrapping_mul(104_729)) % 32;
+            Rgb([(x % 256) as u8, (y % 256) as u8, (noise * 8) as u8])
+        });
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgb8(image)
+            .write_to(&mut Cursor::new(&mut bytes), format)
+            .unwrap();
+        bytes
+    }
+
+    fn options(widths: Vec<u32>, placeholder: PlaceholderKind) -> ImageOptions {
+        ImageOptions {
+            widths,
+            placeholder,
+            ..ImageOptions::default()
+        }
+    }
+
+    fn widths(processed: &ProcessedImage, webp: bool) -> Vec<u32> {
+        processed
+            .variants
+            .iter()
+            .filter(|variant| variant.is_webp() == webp)
+            .map(|variant| variant.width)
+            .collect()
+    }
+
+    #[test]
+    fn makes_copies_below_the_original_width() {
+        let bytes = photo(800, 400, ImageFormat::Jpeg);
+        let processed = process_image(
+            &bytes,
+            &options(vec![960, 480, 0, 200, 480, 800], PlaceholderKind::None),
+        )
+        .unwrap();
+
+        assert_eq!((processed.width, processed.height), (800, 400));
+        assert_eq!(widths(&processed, false), [200, 480]);
+        // Lossy WebP beats JPEG, for every size and the original.
+        assert_eq!(widths(&processed, true), [200, 480, 800]);
+        for variant in &processed.variants {
+            let expected = if variant.is_webp() {
+                "image/webp"
+            } else {
+                "image/jpeg"
+            };
+            assert_eq!(variant.content_type, expected);
+        }
+    }
+
+    #[test]
+    fn webp_copies_are_made_of_every_size_or_none() {
+        for format in [ImageFormat::Jpeg, ImageFormat::Png] {
+            let processed = process_image(
+                &photo(600, 300, format),
+                &options(vec![150, 300], PlaceholderKind::None),
+            )
+            .unwrap();
+            let webp_widths = widths(&processed, true);
+            assert!(
+                webp_widths.is_empty() || webp_widths == [150, 300, 600],
+                "{:?}: {:?}",
+                format,
+                webp_widths
+            );
+        }
+
+        let processed = process_image(
+            &photo(600, 300, ImageFormat::Jpeg),
+            &ImageOptions {
+                webp: false,
+                ..options(vec![300], PlaceholderKind::None)
+            },
+        )
+        .unwrap();
+        assert!(widths(&processed, true).is_empty());
+    }
+
+    #[test]
+    fn gifs_and_oversized_or_undecodable_images_are_left_alone() {
+        let processed = process_image(
+            &photo(300, 200, ImageFormat::Gif),
+            &options(vec![100], PlaceholderKind::None),
+        )
+        .unwrap();
+        assert_eq!((processed.width, processed.height), (300, 200));
+        assert!(processed.variants.is_empty());
+
+        let wide = photo(MAX_DIMENSION + 1, 1, ImageFormat::Png);
+        assert!(process_image(&wide, &ImageOptions::default()).is_none());
+        assert!(process_image(b"<svg></svg>", &ImageOptions::default()).is_none());
+    }
+
+    #[test]
+    fn placeholders_follow_the_options() {
+        let bytes = photo(64, 48, ImageFormat::Png);
+
+        let processed = process_image(&bytes, &options(vec![], PlaceholderKind::Blurhash)).unwrap();
+        assert!(
+            matches!(processed.placeholder, Some(Placeholder::Blurhash(hash)) if !hash.is_empty())
+        );
+
+        let processed = process_image(&bytes, &options(vec![], PlaceholderKind::Lqip)).unwrap();
+        assert!(matches!(
+            processed.placeholder,
+            Some(Placeholder::Lqip(uri)) if uri.starts_with("data:image/jpeg;base64,")
+        ));
+
+        let processed = process_image(&bytes, &options(vec![], PlaceholderKind::None)).unwrap();
+        assert!(processed.placeholder.is_none());
+    }
+
+    #[test]
+    fn formats_srcset() {
+        let source = |url: &str, width| ImageSource {
+            url: url.to_string(),
+            width,
+        };
+        assert_eq!(
+            srcset(&[source("a.jpg", 480), source("b.jpg", 960)]),
+            "a.jpg 480w, b.jpg 960w"
+        );
+        assert_eq!(srcset(&[]), "");
+    }
+}
@@ -5,11 +5,17 @@ use lambda_runtime::{service_fn, Error, LambdaEvent};
 use serde_json::Value;
 
 mod commands;
+mod context;
+#[cfg(test)]
+mod fixtures;
 mod helpers;
 mod models;
+mod options;
+mod renderer;
 
 use crate::commands::convert_page_to_html::convert_page_to_html_command;
 use crate::commands::convert_page_to_markdown::convert_page_to_markdown_command;
+use crate::commands::generate_stylesheet::generate_stylesheet_command;
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
@@ -24,13 +30,10 @@ async fn handle_lambda_event(event: LambdaEvent<Value>) -> Result<String, Error>
         .as_str()
         .ok_or_else(|| Error::from("The command field is missing or not a string"))?;
 
-    event["NOTION_API_KEY"]
-        .as_str()
-        .ok_or_else(|| Error::from("The NOTION_API_KEY field is missing or not a string"))?;
-
     match command {
         "convert_page_to_html" => convert_page_to_html_command(event).await,
         "convert_page_to_markdown" => convert_page_to_markdown_command(event).await,
+        "generate_stylesheet" => generate_stylesheet_command(event),
         _ => Err(Error::from(format!("Unknown command: {}", command))),
     }
 }
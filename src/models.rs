@@ -1,5 +1,6 @@
 pub mod block;
 pub mod common;
+pub mod database;
 pub mod objects;
 pub mod page;
 pub mod rich_text;
@@ -83,6 +83,109 @@ pub enum Block {
     Video(VideoBlock),
 }
 
+impl Block {
+    /// Returns every rich text element of the block, including captions and table cells.
+    pub fn rich_text(&self) -> Vec<&RichTextElement> {
+        let fields: Vec<&Vec<RichTextElement>> = match self {
+            Block::Bookmark(bookmark_block) => vec![&bookmark_block.bookmark.caption],
+            Block::BulletedListItem(bulleted_list_item_block) => {
+                vec![&bulleted_list_item_block.bulleted_list_item.rich_text]
+            }
+            Block::Callout(callout_block) => vec![&callout_block.callout.rich_text],
+            Block::Code(code_block) => vec![&code_block.code.rich_text, &code_block.code.caption],
+            Block::Embed(embed_block) => vec![&embed_block.embed.caption],
+            Block::File(file_block) => vec![&file_block.file.caption],
+            Block::Heading1(heading_1) => vec![&heading_1.heading_1.rich_text],
+            Block::Heading2(heading_2) => vec![&heading_2.heading_2.rich_text],
+            Block::Heading3(heading_3) => vec![&heading_3.heading_3.rich_text],
+            Block::Image(image_block) => vec![&image_block.image.caption],
+            Block::NumberedListItem(numbered_list_item_block) => {
+                vec![&numbered_list_item_block.numbered_list_item.rich_text]
+            }
+            Block::Paragraph(paragraph_block) => vec![&paragraph_block.paragraph.rich_text],
+            Block::Pdf(pdf_block) => vec![&pdf_block.pdf.caption],
+            Block::Quote(quote_block) => vec![&quote_block.quote.rich_text],
+            Block::TableRow(table_row_block) => table_row_block.table_row.cells.iter().collect(),
+            Block::Template(template_block) => vec![&template_block.template.rich_text],
+            Block::ToDo(todo_block) => vec![&todo_block.to_do.rich_text],
+            Block::Toggle(toggle_block) => vec![&toggle_block.toggle.rich_text],
+            Block::Video(video_block) => vec![&video_block.video.caption],
+            Block::Breadcrumb(_)
+            | Block::ChildDatabase(_)
+            | Block::ChildPage(_)
+            | Block::Column(_)
+            | Block::ColumnList(_)
+            | Block::Divider(_)
+            | Block::Equation(_)
+            | Block::LinkPreview(_)
+            | Block::Mention(_)
+            | Block::SyncedBlock(_)
+            | Block::Table(_)
+            | Block::TableOfContents(_)
+            | Block::Unsupported(_) => Vec::new(),
+        };
+        fields.into_iter().flatten().collect()
+    }
+
+    /// Mutable counterpart of [`Block::rich_text`], used to resolve mentions in place.
+    pub fn rich_text_mut(&mut self) -> Vec<&mut RichTextElement> {
+        let fields: Vec<&mut Vec<RichTextElement>> = match self {
+            Block::Bookmark(bookmark_block) => vec![&mut bookmark_block.bookmark.caption],
+            Block::BulletedListItem(bulleted_list_item_block) => {
+                vec![&mut bulleted_list_item_block.bulleted_list_item.rich_text]
+            }
+            Block::Callout(callout_block) => vec![&mut callout_block.callout.rich_text],
+            Block::Code(code_block) => {
+                vec![&mut code_block.code.rich_text, &mut code_block.code.caption]
+            }
+            Block::Embed(embed_block) => vec![&mut embed_block.embed.caption],
+            Block::File(file_block) => vec![&mut file_block.file.caption],
+            Block::Heading1(heading_1) => vec![&mut heading_1.heading_1.rich_text],
+            Block::Heading2(heading_2) => vec![&mut heading_2.heading_2.rich_text],
+            Block::Heading3(heading_3) => vec![&mut heading_3.heading_3.rich_text],
+            Block::Image(image_block) => vec![&mut image_block.image.caption],
+            Block::NumberedListItem(numbered_list_item_block) => {
+                vec![&mut numbered_list_item_block.numbered_list_item.rich_text]
+            }
+            Block::Paragraph(paragraph_block) => vec![&mut paragraph_block.paragraph.rich_text],
+            Block::Pdf(pdf_block) => vec![&mut pdf_block.pdf.caption],
+            Block::Quote(quote_block) => vec![&mut quote_block.quote.rich_text],
+            Block::TableRow(table_row_block) => {
+                table_row_block.table_row.cells.iter_mut().collect()
+            }
+            Block::Template(template_block) => vec![&mut template_block.template.rich_text],
+            Block::ToDo(todo_block) => vec![&mut todo_block.to_do.rich_text],
+            Block::Toggle(toggle_block) => vec![&mut toggle_block.toggle.rich_text],
+            Block::Video(video_block) => vec![&mut video_block.video.caption],
+            Block::Breadcrumb(_)
+            | Block::ChildDatabase(_)
+            | Block::ChildPage(_)
+            | Block::Column(_)
+            | Block::ColumnList(_)
+            | Block::Divider(_)
+            | Block::Equation(_)
+            | Block::LinkPreview(_)
+            | Block::Mention(_)
+            | Block::SyncedBlock(_)
+            | Block::Table(_)
+            | Block::TableOfContents(_)
+            | Block::Unsupported(_) => Vec::new(),
+        };
+        fields.into_iter().flatten().collect()
+    }
+
+    /// Returns the file of image, file, video and PDF blocks.
+    pub fn file_mut(&mut self) -> Option<&mut FileObject> {
+        match self {
+            Block::File(file_block) => Some(&mut file_block.file.file),
+            Block::Image(image_block) => Some(&mut image_block.image.file),
+            Block::Pdf(pdf_block) => Some(&mut pdf_block.pdf.file),
+            Block::Video(video_block) => Some(&mut video_block.video.file),
+            _ => None,
+        }
+    }
+}
+
 /// --------------------------------------------------------------------------------
 /// Bookmark
 /// https://developers.notion.com/reference/block#bookmark
@@ -408,6 +511,25 @@ impl ProgrammingLanguage {
             ProgrammingLanguage::JavaCCppCSharp => "javac-cpp-csharp",
         }
     }
+
+    /// The language of a fenced code block in Markdown, named the way highlighters
+    /// (Shiki, Prism, highlight.js, Chroma) and GitHub name it. `None` for plain text.
+    pub fn to_fence_language(&self) -> Option<&str> {
+        let language = match self {
+            ProgrammingLanguage::PlainText => return None,
+            ProgrammingLanguage::Docker => "dockerfile",
+            ProgrammingLanguage::Flow => "javascript",
+            ProgrammingLanguage::Markup => "html",
+            ProgrammingLanguage::ObjectiveC => "objectivec",
+            ProgrammingLanguage::VbNet => "vbnet",
+            ProgrammingLanguage::VisualBasic => "vb",
+            ProgrammingLanguage::WebAssembly => "wasm",
+            // One language for several. C++ highlighting suits all four well enough.
+            ProgrammingLanguage::JavaCCppCSharp => "cpp",
+            language => language.to_class_name(),
+        };
+        Some(language)
+    }
 }
 
 /// --------------------------------------------------------------------------------
@@ -456,6 +578,8 @@ pub struct EmbedBlock {
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct EmbedField {
+    #[serde(default)]
+    pub caption: Vec<RichTextElement>,
     pub url: String,
 }
 
@@ -483,9 +607,44 @@ pub struct EquationField {
 pub struct FileBlock {
     #[serde(flatten)]
     pub base: BaseBlock,
+    pub file: FileField,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct FileField {
+    #[serde(default)]
+    pub caption: Vec<RichTextElement>,
+    /// The name of the file as shown in Notion.
+    pub name: Option<String>,
+    #[serde(flatten)]
     pub file: FileObject,
 }
 
+impl FileField {
+    /// Returns the file name, falling back to the last segment of the URL.
+    pub fn file_name(&self) -> String {
+        match &self.name {
+            Some(name) if !name.trim().is_empty() => name.clone(),
+            _ => {
+                let url = self.file.url();
+                let path = url.split(['?', '#']).next().unwrap_or(url);
+                path.rsplit('/').next().unwrap_or(path).to_string()
+            }
+        }
+    }
+
+    /// Returns the upper-cased file extension (e.g. `PDF`), if the file name has one.
+    pub fn extension(&self) -> Option<String> {
+        let file_name = self.file_name();
+        match file_name.rsplit_once('.') {
+            Some((stem, extension)) if !stem.is_empty() && !extension.is_empty() => {
+                Some(extension.to_uppercase())
+            }
+            _ => None,
+        }
+    }
+}
+
 /// --------------------------------------------------------------------------------
 /// Heading
 /// https://developers.notion.com/reference/block#headings
@@ -519,7 +678,15 @@ pub struct Heading3Block {
 pub struct ImageBlock {
     #[serde(flatten)]
     pub base: BaseBlock,
-    pub image: FileObject,
+    pub image: ImageField,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ImageField {
+    #[serde(default)]
+    pub caption: Vec<RichTextElement>,
+    #[serde(flatten)]
+    pub file: FileObject,
 }
 
 /// --------------------------------------------------------------------------------
@@ -546,7 +713,24 @@ pub struct LinkPreviewField {
 pub struct NumberedListItemBlock {
     #[serde(flatten)]
     pub base: BaseBlock,
-    pub numbered_list_item: RichText,
+    pub numbered_list_item: NumberedListItemField,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct NumberedListItemField {
+    pub rich_text: Vec<RichTextElement>,
+    pub color: Color,
+    /// The number of the first item, only set on the first item of a list.
+    pub list_start_index: Option<u32>,
+    pub list_format: Option<ListFormat>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ListFormat {
+    Numbers,
+    Letters,
+    Roman,
 }
 
 /// --------------------------------------------------------------------------------
@@ -588,10 +772,11 @@ pub struct PdfBlock {
 }
 
 #[derive(Deserialize, Serialize, Debug)]
-#[serde(tag = "type", rename_all = "snake_case")]
-pub enum PDFField {
-    External { external: Value },
-    File { file: Value },
+pub struct PDFField {
+    #[serde(default)]
+    pub caption: Vec<RichTextElement>,
+    #[serde(flatten)]
+    pub file: FileObject,
 }
 
 /// --------------------------------------------------------------------------------
@@ -691,7 +876,15 @@ pub struct TemplateBlock {
 pub struct ToDoBlock {
     #[serde(flatten)]
     pub base: BaseBlock,
-    pub to_do: RichText,
+    pub to_do: ToDoField,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ToDoField {
+    pub rich_text: Vec<RichTextElement>,
+    #[serde(default)]
+    pub checked: bool,
+    pub color: Color,
 }
 
 /// --------------------------------------------------------------------------------
@@ -722,5 +915,65 @@ pub struct UnsupportedBlock {
 pub struct VideoBlock {
     #[serde(flatten)]
     pub base: BaseBlock,
-    pub video: FileObject,
+    pub video: VideoField,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct VideoField {
+    #[serde(default)]
+    pub caption: Vec<RichTextElement>,
+    #[serde(flatten)]
+    pub file: FileObject,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn file(name: Option<&str>, url: &str) -> FileField {
+        serde_json::from_value(json!({
+            "caption": [],
+            "name": name,
+            "type": "file",
+            "file": { "url": url, "expiry_time": "2024-01-01T01:00:00.000Z" }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn files_are_named_by_notion_or_their_url() {
+        let report = file(
+            Some("Q1 report.pdf"),
+            "https://s3.example.com/x/a.bin?X-Amz=1",
+        );
+        assert_eq!(report.file_name(), "Q1 report.pdf");
+        assert_eq!(report.extension().as_deref(), Some("PDF"));
+
+        let unnamed = file(Some(" "), "https://example.com/files/data.tar.gz?v=2#top");
+        assert_eq!(unnamed.file_name(), "data.tar.gz");
+        assert_eq!(unnamed.extension().as_deref(), Some("GZ"));
+
+        for name in ["README", ".env", "trailing."] {
+            assert_eq!(
+                file(Some(name), "https://example.com/").extension(),
+                None,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn external_files_deserialize_like_notion_hosted_ones() {
+        let external: FileField = serde_json::from_value(json!({
+            "caption": [],
+            "type": "external",
+            "external": { "url": "https://example.com/slides.key" }
+        }))
+        .unwrap();
+        assert_eq!(external.name, None);
+        assert_eq!(external.file_name(), "slides.key");
+        assert_eq!(external.extension().as_deref(), Some("KEY"));
+    }
 }
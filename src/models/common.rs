@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum Color {
     Blue,
@@ -23,3 +23,86 @@ pub enum Color {
     Yellow,
     YellowBackground,
 }
+
+impl Color {
+    pub const ALL: [Color; 19] = [
+        Color::Blue,
+        Color::BlueBackground,
+        Color::Brown,
+        Color::BrownBackground,
+        Color::Default,
+        Color::Gray,
+        Color::GrayBackground,
+        Color::Green,
+        Color::GreenBackground,
+        Color::Orange,
+        Color::OrangeBackground,
+        Color::Pink,
+        Color::PinkBackground,
+        Color::Purple,
+        Color::PurpleBackground,
+        Color::Red,
+        Color::RedBackground,
+        Color::Yellow,
+        Color::YellowBackground,
+    ];
+
+    /// The color as a class name, e.g. `blue-background`.
+    pub fn class_name(&self) -> &'static str {
+        match self {
+            Color::Blue => "blue",
+            Color::BlueBackground => "blue-background",
+            Color::Brown => "brown",
+            Color::BrownBackground => "brown-background",
+            Color::Default => "default",
+            Color::Gray => "gray",
+            Color::GrayBackground => "gray-background",
+            Color::Green => "green",
+            Color::GreenBackground => "green-background",
+            Color::Orange => "orange",
+            Color::OrangeBackground => "orange-background",
+            Color::Pink => "pink",
+            Color::PinkBackground => "pink-background",
+            Color::Purple => "purple",
+            Color::PurpleBackground => "purple-background",
+            Color::Red => "red",
+            Color::RedBackground => "red-background",
+            Color::Yellow => "yellow",
+            Color::YellowBackground => "yellow-background",
+        }
+    }
+
+    pub fn is_background(&self) -> bool {
+        self.class_name().ends_with("-background")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn class_names_match_the_notion_api() {
+        for color in Color::ALL {
+            let json = serde_json::to_value(color).unwrap();
+            assert_eq!(
+                json.as_str().map(|name| name.replace('_', "-")).as_deref(),
+                Some(color.class_name())
+            );
+            assert_eq!(
+                color.is_background(),
+                json.as_str().unwrap().ends_with("_background"),
+                "{:?}",
+                color
+            );
+        }
+        let mut class_names: Vec<&str> =
+            Color::ALL.iter().map(|color| color.class_name()).collect();
+        class_names.sort_unstable();
+        class_names.dedup();
+        assert_eq!(class_names.len(), Color::ALL.len());
+        assert_eq!(Color::GrayBackground.class_name(), "gray-background");
+        assert!(Color::GrayBackground.is_background());
+        assert!(!Color::Default.is_background());
+    }
+}
@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::rich_text::RichTextElement;
+
+/// --------------------------------------------------------------------------------
+/// ## Database - Struct
+///
+/// for more details - https://developers.notion.com/reference/database
+///
+/// Only the fields needed to link to a database are modelled.
+/// --------------------------------------------------------------------------------
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Database {
+    /// Always "database".
+    pub object: String,
+
+    /// Unique identifier for the database.
+    pub id: String,
+
+    /// Name of the database as it appears in Notion.
+    pub title: Vec<RichTextElement>,
+
+    /// The URL of the Notion database.
+    pub url: String,
+
+    /// The public page URL if the page has been published to the web. Otherwise, null.
+    pub public_url: Option<String>,
+}
+
+impl Database {
+    pub fn title(&self) -> String {
+        self.title
+            .iter()
+            .map(|rich_text| rich_text.to_plaintext())
+            .collect()
+    }
+}
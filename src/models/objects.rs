@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 /// for more details - https://developers.notion.com/reference/user
 #[derive(Deserialize, Serialize, Debug)]
@@ -20,6 +21,18 @@ pub struct EmojiObject {
     pub emoji: String,
 }
 
+/// ## IconObject - Enum
+///
+/// Page icons are either an emoji or an image file.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum IconObject {
+    Emoji { emoji: String },
+    External { external: External },
+    File { file: File },
+    CustomEmoji { custom_emoji: Value },
+}
+
 /// ## ParentObject - Struct
 ///
 /// for more details - https://developers.notion.com/reference/parent-object
@@ -96,3 +109,13 @@ pub enum FileObject {
         file: File,
     },
 }
+
+impl FileObject {
+    /// Returns the URL of the file, regardless of where it is hosted.
+    pub fn url(&self) -> &str {
+        match self {
+            FileObject::External { external } => &external.url,
+            FileObject::File { file } => &file.url,
+        }
+    }
+}
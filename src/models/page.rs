@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use crate::models::objects::{EmojiObject, FileObject, ParentObject, User};
+use crate::models::objects::{FileObject, IconObject, ParentObject, User};
 
 /// --------------------------------------------------------------------------------
 /// ## Page - Struct
@@ -34,7 +34,7 @@ pub struct Page {
     pub cover: Option<FileObject>,
 
     /// Page icon.
-    pub icon: Option<EmojiObject>,
+    pub icon: Option<IconObject>,
 
     /// Information about the page's parent. See Parent object.
     pub parent: ParentObject,
@@ -62,3 +62,35 @@ pub struct Page {
     /// The public page URL if the page has been published to the web. Otherwise, null.
     pub public_url: Option<String>,
 }
+
+impl Page {
+    /// Returns the plain text of the page's `title` property.
+    pub fn title(&self) -> String {
+        self.properties
+            .as_object()
+            .and_then(|properties| {
+                properties
+                    .values()
+                    .find(|property| property["type"] == "title")
+            })
+            .map(plain_text)
+            .unwrap_or_default()
+    }
+
+    /// Returns the plain text of the `title` or `rich_text` property `name`, if it is not empty.
+    pub fn text_property(&self, name: &str) -> Option<String> {
+        Some(plain_text(self.properties.get(name)?)).filter(|text| !text.is_empty())
+    }
+}
+
+fn plain_text(property: &Value) -> String {
+    property[property["type"].as_str().unwrap_or_default()]
+        .as_array()
+        .map(|rich_text| {
+            rich_text
+                .iter()
+                .filter_map(|element| element["plain_text"].as_str())
+                .collect()
+        })
+        .unwrap_or_default()
+}
@@ -1,7 +1,9 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use crate::helpers::escape::{escape_html, link_attributes, sanitize_url};
 use crate::models::common::Color;
+use crate::options::HtmlOptions;
 
 /// ## RichText - Struct
 ///
@@ -46,7 +48,8 @@ pub enum RichTextElement {
 }
 
 impl RichTextElement {
-    pub fn to_html(&self) -> String {
+    /// Links whose scheme is not allowed are rendered as plain text.
+    pub fn to_html(&self, options: &HtmlOptions) -> String {
         let (plain_text, annotations, href) = match self {
             RichTextElement::Text {
                 plain_text,
@@ -68,41 +71,62 @@ impl RichTextElement {
             } => (plain_text, annotations, href),
         };
 
-        let color_class = match annotations.color {
-            Color::Blue => "blue",
-            Color::BlueBackground => "blue-background",
-            Color::Brown => "brown",
-            Color::BrownBackground => "brown-background",
-            Color::Default => "default",
-            Color::Gray => "gray",
-            Color::GrayBackground => "gray-background",
-            Color::Green => "green",
-            Color::GreenBackground => "green-background",
-            Color::Orange => "orange",
-            Color::OrangeBackground => "orange-background",
-            Color::Pink => "pink",
-            Color::PinkBackground => "pink-background",
-            Color::Purple => "purple",
-            Color::PurpleBackground => "purple-background",
-            Color::Red => "red",
-            Color::RedBackground => "red-background",
-            Color::Yellow => "yellow",
-            Color::YellowBackground => "yellow-background",
-        };
-
-        let mut html = format!("<span class='notion-rich-text {}'>", color_class);
+        let mut class_names = vec![options.class_name("rich-text")];
+        if annotations.color != Color::Default {
+            class_names.push(options.class_name(annotations.color.class_name()));
+        }
+        if let RichTextElement::Mention { mention, .. } = self {
+            class_names.push(options.class_name(match mention {
+                Mention::Database { .. } => "database-mention",
+                Mention::Date { .. } => "date-mention",
+                Mention::LinkPreview { .. } => "link-preview-mention",
+                Mention::Page { .. } => "page-mention",
+                Mention::TemplateMention { .. } => "template-mention",
+                Mention::User { .. } => "user-mention",
+            }));
+        }
 
-        let escaped_plain_text = plain_text
-            .replace("&", "&amp;")
-            .replace("<", "&lt;")
-            .replace(">", "&gt;")
-            .replace("\"", "&quot;")
-            .replace("'", "&#39;");
+        // Without classes, the span would be left with nothing to say.
+        let class_attribute = options.raw_class_attribute(&class_names);
+        let mut html = if class_attribute.is_empty() {
+            String::new()
+        } else {
+            format!("<span{}>", class_attribute)
+        };
 
-        match href {
-            Some(link) => html.push_str(&format!("<a href='{}'>", link)),
-            None => {}
+        let text_html = match self {
+            RichTextElement::Mention {
+                mention: Mention::Date { date },
+                ..
+            } => date.to_html(plain_text),
+            RichTextElement::Mention {
+                mention:
+                    Mention::User {
+                        user:
+                            UserMention {
+                                avatar_url: Some(avatar_url),
+                                ..
+                            },
+                    },
+                ..
+            } => match sanitize_url(avatar_url) {
+                Some(avatar_url) => format!(
+                    "<img src='{}' alt=''{} />{}",
+                    avatar_url,
+                    options.class_attribute(&["user-avatar"]),
+                    escape_html(plain_text)
+                ),
+                None => escape_html(plain_text),
+            },
+            _ => escape_html(plain_text),
         };
+
+        let link_attributes = href
+            .as_deref()
+            .and_then(|href| link_attributes(href, options.link_target.as_deref()));
+        if let Some(link_attributes) = &link_attributes {
+            html.push_str(&format!("<a{}>", link_attributes));
+        }
         if annotations.code {
             html.push_str("<code>");
         }
@@ -120,7 +144,7 @@ impl RichTextElement {
         }
 
         // insert text
-        html.push_str(&escaped_plain_text);
+        html.push_str(&text_html);
 
         // end tag (in reverse order)
         if annotations.underline {
@@ -138,60 +162,17 @@ impl RichTextElement {
         if annotations.code {
             html.push_str("</code>");
         }
-        match href {
-            Some(_) => html.push_str("</a>"),
-            None => {}
-        };
+        if link_attributes.is_some() {
+            html.push_str("</a>");
+        }
 
-        html.push_str("</span>");
+        if !class_attribute.is_empty() {
+            html.push_str("</span>");
+        }
 
         html
     }
 
-    pub fn to_markdown(&self) -> String {
-        let (plain_text, annotations, href) = match self {
-            RichTextElement::Text {
-                plain_text,
-                annotations,
-                href,
-                ..
-            }
-            | RichTextElement::Mention {
-                plain_text,
-                annotations,
-                href,
-                ..
-            }
-            | RichTextElement::Equation {
-                plain_text,
-                annotations,
-                href,
-                ..
-            } => (plain_text, annotations, href),
-        };
-
-        let mut markdown = String::new();
-
-        match href {
-            Some(link) => {
-                markdown.push_str(&format!("[{}]({})", plain_text, link));
-            }
-            None => {
-                if annotations.code {
-                    markdown.push_str(&format!("`{}`", plain_text));
-                } else if annotations.bold {
-                    markdown.push_str(&format!("**{}**", plain_text));
-                } else if annotations.italic {
-                    markdown.push_str(&format!("*{}*", plain_text));
-                } else if annotations.strikethrough {
-                    markdown.push_str(&format!("~{}~", plain_text));
-                }
-            }
-        };
-
-        markdown
-    }
-
     pub fn to_plaintext(&self) -> String {
         match self {
             RichTextElement::Text { plain_text, .. }
@@ -209,27 +190,11 @@ pub struct RichText {
 }
 
 impl RichText {
-    pub fn to_html(&self) -> String {
-        let mut html = String::new();
-        for rich_text_element in &self.rich_text {
-            html.push_str(&rich_text_element.to_html());
-        }
-        html
-    }
-
-    pub fn to_markdown(&self) -> String {
-        let mut markdown = String::new();
-        for rich_text_element in &self.rich_text {
-            markdown.push_str(&rich_text_element.to_markdown());
-        }
-        markdown
-    }
-
     #[allow(dead_code)]
     pub fn to_plaintext(&self) -> String {
         let mut text = String::new();
         for rich_text_element in &self.rich_text {
-            text.push_str(&rich_text_element.to_markdown());
+            text.push_str(&rich_text_element.to_plaintext());
         }
         text
     }
@@ -249,6 +214,7 @@ pub struct Equation {
 /// --------------------------------------------------------------------------------
 #[derive(Deserialize, Serialize, Debug)]
 #[serde(tag = "type", rename_all = "snake_case")]
+#[allow(clippy::enum_variant_names)]
 pub enum Mention {
     Database { database: DatabaseMention },
     Date { date: DateMention },
@@ -269,6 +235,36 @@ pub struct DateMention {
     pub end: Option<String>,
 }
 
+impl DateMention {
+    /// Renders the date (or range) as `<time>` elements. Notion writes ranges
+    /// as `start → end` in `plain_text`, which is used for the visible text.
+    pub fn to_html(&self, plain_text: &str) -> String {
+        let (start_text, end_text) = match plain_text.split_once('→') {
+            Some((start_text, end_text)) => (start_text.trim(), Some(end_text.trim())),
+            None => (plain_text, None),
+        };
+
+        let start_html = match &self.start {
+            Some(start) => format!(
+                "<time datetime='{}'>{}</time>",
+                escape_html(start),
+                escape_html(start_text)
+            ),
+            None => escape_html(start_text),
+        };
+
+        match &self.end {
+            Some(end) => format!(
+                "{} → <time datetime='{}'>{}</time>",
+                start_html,
+                escape_html(end),
+                escape_html(end_text.unwrap_or(end))
+            ),
+            None => start_html,
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct LinkPreviewMention {
     pub url: String,
@@ -282,6 +278,8 @@ pub struct PageMention {
 #[derive(Deserialize, Serialize, Debug)]
 pub struct UserMention {
     pub id: String,
+    pub name: Option<String>,
+    pub avatar_url: Option<String>,
 }
 
 /// --------------------------------------------------------------------------------
@@ -343,3 +341,78 @@ pub struct Annotations {
     /// - `yellow_background`
     pub color: Color,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{styled_text, text};
+    use proptest::prelude::*;
+    use scraper::{Html, Selector};
+    use serde_json::json;
+
+    fn text_element(plain_text: &str, href: &str) -> RichTextElement {
+        serde_json::from_value(styled_text(plain_text, &["bold", "code"], Some(href))).unwrap()
+    }
+
+    #[test]
+    fn inline_colors_are_prefixed_classes() {
+        let options = HtmlOptions {
+            class_prefix: String::from("n-"),
+            ..HtmlOptions::default()
+        };
+        let mut blue = text("blue");
+        blue["annotations"]["color"] = json!("blue_background");
+        let blue: RichTextElement = serde_json::from_value(blue).unwrap();
+        let plain: RichTextElement = serde_json::from_value(text("plain")).unwrap();
+
+        assert_eq!(
+            blue.to_html(&options),
+            "<span class='n-rich-text n-blue-background'>blue</span>"
+        );
+        assert_eq!(
+            plain.to_html(&options),
+            "<span class='n-rich-text'>plain</span>"
+        );
+    }
+
+    proptest! {
+        /// No text or link can add elements or attributes to the rendered rich text,
+        /// and the only links rendered are web, mail and relative ones.
+        #[test]
+        fn rendered_rich_text_cannot_be_broken_out_of(
+            plain_text in "[^\\u{0}\\r]*",
+            href in "(javascript:|JAVASCRIPT:|data:|https://|mailto:|/|\\t)?[^\\u{0}\\r]*",
+        ) {
+            let options = HtmlOptions {
+                link_target: Some(String::from("_blank")),
+                ..HtmlOptions::default()
+            };
+            let html = text_element(&plain_text, &href).to_html(&options);
+            let fragment = Html::parse_fragment(&html);
+
+            let names: Vec<&str> = fragment
+                .select(&Selector::parse("*").unwrap())
+                .map(|element| element.value().name())
+                .filter(|name| *name != "html")
+                .collect();
+            let expected: &[&str] = match sanitize_url(&href) {
+                Some(_) => &["span", "a", "code", "strong"],
+                None => &["span", "code", "strong"],
+            };
+            prop_assert_eq!(names, expected);
+
+            let span = fragment.select(&Selector::parse("span").unwrap()).next().unwrap();
+            prop_assert_eq!(span.text().collect::<String>(), plain_text.clone());
+            prop_assert_eq!(span.value().attrs().count(), 1);
+
+            if let Some(link) = fragment.select(&Selector::parse("a").unwrap()).next() {
+                let href = link.value().attr("href").unwrap();
+                prop_assert!(sanitize_url(href).is_some());
+                prop_assert!(link
+                    .value()
+                    .attrs()
+                    .all(|(name, _)| ["href", "rel", "target"].contains(&name)));
+            }
+        }
+    }
+}
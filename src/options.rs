@@ -0,0 +1,748 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::helpers::embed::DEFAULT_EMBED_ALLOWED_HOSTS;
+use crate::helpers::escape::escape_html;
+
+/// ## HtmlOptions - Struct
+///
+/// Options for `convert_page_to_html`, passed as `html_options` in the event.
+/// Every field is optional and falls back to its default.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct HtmlOptions {
+    /// Hosts (and their subdomains) that `Embed` and `Video` blocks may be
+    /// rendered as iframes from. Anything else is rendered as a plain link.
+    pub embed_allowed_hosts: Vec<String>,
+
+    /// Whether images are rendered with `loading='lazy'`.
+    pub lazy_load_images: bool,
+
+    /// A `target` for links that leave the site, e.g. `_blank`.
+    pub link_target: Option<String>,
+
+    /// Put before every class name, as in `notion-paragraph` or `notion-rich-text notion-blue`.
+    pub class_prefix: String,
+
+    /// Whether elements have classes at all. Without them, `language-*` on code is all that is left.
+    pub classes: bool,
+
+    /// Added to the level of headings, e.g. `1` for `<h2>` to `<h4>` under a page title in `<h1>`.
+    /// Levels above 6 are rendered as `<h6>`.
+    pub heading_offset: u8,
+
+    /// The element to render blocks of a type as, e.g. `{ "callout": "aside" }`.
+    /// Only blocks whose element carries no structure can be remapped: not list items,
+    /// toggles, tables or dividers.
+    pub elements: HashMap<String, String>,
+
+    /// Whether block elements have an `id` with the id of the block without dashes,
+    /// which links to the block (`#<id>`) point at.
+    pub block_ids: bool,
+
+    /// Whether block elements have a `data-block-id` with the id of the block.
+    pub data_block_id: bool,
+
+    /// Extra attributes of block elements by block type, e.g. `{ "callout": { "role": "note" } }`.
+    pub block_attributes: HashMap<String, HashMap<String, String>>,
+
+    /// Wraps the page in a standalone document with a `<head>` when set.
+    /// The output is a fragment otherwise.
+    pub document: Option<DocumentOptions>,
+}
+
+impl Default for HtmlOptions {
+    fn default() -> Self {
+        HtmlOptions {
+            embed_allowed_hosts: DEFAULT_EMBED_ALLOWED_HOSTS
+                .iter()
+                .map(|host| host.to_string())
+                .collect(),
+            lazy_load_images: false,
+            link_target: None,
+            class_prefix: String::from("notion-"),
+            classes: true,
+            heading_offset: 0,
+            elements: HashMap::new(),
+            block_ids: true,
+            data_block_id: false,
+            block_attributes: HashMap::new(),
+            document: None,
+        }
+    }
+}
+
+impl HtmlOptions {
+    /// The class name `name` with `class_prefix`, e.g. `notion-paragraph`.
+    pub fn class_name(&self, name: &str) -> String {
+        format!("{}{}", self.class_prefix, name)
+    }
+
+    /// A ` class='...'` attribute of the non-empty `names`, each with `class_prefix`,
+    /// or nothing when classes are off.
+    pub fn class_attribute(&self, names: &[&str]) -> String {
+        let class_names: Vec<String> = names
+            .iter()
+            .filter(|name| !name.is_empty())
+            .map(|name| self.class_name(name))
+            .collect();
+        self.raw_class_attribute(&class_names)
+    }
+
+    /// Like [`HtmlOptions::class_attribute`], for class names that already have their prefix.
+    pub fn raw_class_attribute(&self, class_names: &[String]) -> String {
+        if !self.classes || class_names.is_empty() {
+            return String::new();
+        }
+        format!(" class='{}'", escape_html(&class_names.join(" ")))
+    }
+
+    /// The element to render a block of `block_type` as, `default` unless `elements` maps it
+    /// to one of [`STRUCTURAL_ELEMENTS`], or for text blocks to one of [`TEXT_ELEMENTS`].
+    /// Anything else is ignored.
+    pub fn element(&self, block_type: &str, default: &str) -> String {
+        if let Some(element) = self.elements.get(block_type) {
+            let element = element.to_ascii_lowercase();
+            if STRUCTURAL_ELEMENTS.contains(&element.as_str())
+                || (TEXT_BLOCKS.contains(&block_type) && TEXT_ELEMENTS.contains(&element.as_str()))
+            {
+                return element;
+            }
+            println!(
+                "{:?} is not an element that {} blocks can be rendered as!",
+                element, block_type
+            );
+        }
+        default.to_string()
+    }
+
+    /// The element of a heading of `level`, shifted by `heading_offset`.
+    pub fn heading_element(&self, block_type: &str, level: u8) -> String {
+        let level = level.saturating_add(self.heading_offset).min(6);
+        self.element(block_type, &format!("h{}", level))
+    }
+}
+
+/// The elements that `elements` may map any block to: containers without behavior of their
+/// own, unlike `script`, `iframe`, `form` or `a`, that may hold other blocks.
+pub const STRUCTURAL_ELEMENTS: [&str; 7] = [
+    "div", "section", "aside", "article", "figure", "header", "footer",
+];
+
+/// The elements that `elements` may map [`TEXT_BLOCKS`] to as well. They may only hold text,
+/// so a column or a figure in one would be taken apart by the browser.
+pub const TEXT_ELEMENTS: [&str; 7] = ["p", "h1", "h2", "h3", "h4", "h5", "h6"];
+
+/// The blocks whose element holds nothing but their rich text.
+const TEXT_BLOCKS: [&str; 6] = [
+    "paragraph",
+    "heading_1",
+    "heading_2",
+    "heading_3",
+    "quote",
+    "callout",
+];
+
+/// ## DocumentOptions - Struct
+///
+/// The `<head>` of a standalone document, passed as `document` in `html_options`.
+/// The title is the title of the page.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct DocumentOptions {
+    /// The `lang` of the `<html>` element.
+    pub lang: String,
+
+    /// The meta description. Defaults to the text of `description_property`.
+    pub description: Option<String>,
+
+    /// The page property the description is read from when `description` is unset.
+    pub description_property: String,
+
+    /// The canonical URL, also used as `og:url`.
+    pub canonical_url: Option<String>,
+
+    /// The `og:site_name`.
+    pub site_name: Option<String>,
+
+    /// The `twitter:site`, e.g. `@notion`.
+    pub twitter_site: Option<String>,
+
+    /// The `og:image` and `twitter:image`. Defaults to the page cover.
+    pub image: Option<String>,
+
+    /// Whether the stylesheet of `generate_stylesheet` is embedded, with a basic layout.
+    pub stylesheet: bool,
+
+    /// Whether images, videos and PDFs are embedded as `data:` URIs, so that the document
+    /// is a single portable file. The asset store is not used then.
+    pub inline_assets: bool,
+
+    /// Files larger than this, in bytes, keep their URL instead of being embedded.
+    pub inline_max_bytes: usize,
+
+    /// The most bytes of `data:` URIs embedded in one document. Files past it keep their URL.
+    pub inline_max_total_bytes: usize,
+}
+
+impl Default for DocumentOptions {
+    fn default() -> Self {
+        DocumentOptions {
+            lang: String::from("en"),
+            description: None,
+            description_property: String::from("Description"),
+            canonical_url: None,
+            site_name: None,
+            twitter_site: None,
+            image: None,
+            stylesheet: true,
+            inline_assets: false,
+            inline_max_bytes: 10 * 1024 * 1024,
+            inline_max_total_bytes: 50 * 1024 * 1024,
+        }
+    }
+}
+
+/// ## MarkdownOptions - Struct
+///
+/// Options for `convert_page_to_markdown`, passed as `markdown_options` in the event.
+#[derive(Deserialize, Serialize, Debug, Default)]
+#[serde(default)]
+pub struct MarkdownOptions {
+    /// The dialect of the output, for the constructs that plain CommonMark lacks.
+    pub flavor: MarkdownFlavor,
+
+    /// Whether block and inline colors are kept as `<span class='notion-<color>'>`
+    /// around the text. Markdown has no colors, so they are dropped by default.
+    pub color_spans: bool,
+
+    /// What to do with bold, italic and strikethrough that CommonMark would not parse
+    /// as such, e.g. `**「強調」**です`. Defaults to what suits the flavor.
+    pub emphasis_fallback: Option<EmphasisFallback>,
+
+    /// Whether a space is put between CJK characters and Latin letters or digits.
+    pub cjk_spacing: bool,
+
+    /// Front matter to put before the content, made from the page's properties.
+    pub front_matter: Option<FrontMatterOptions>,
+
+    /// Written between columns where they are flattened (Obsidian and Hugo), e.g. `---`.
+    /// Columns are only separated by a blank line when unset.
+    pub column_separator: Option<String>,
+}
+
+impl MarkdownOptions {
+    pub fn emphasis_fallback(&self) -> EmphasisFallback {
+        self.emphasis_fallback
+            .unwrap_or_else(|| self.flavor.emphasis_fallback())
+    }
+}
+
+/// ## MarkdownFlavor - Enum
+///
+/// The Markdown dialect that `convert_page_to_markdown` writes.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MarkdownFlavor {
+    /// Plain CommonMark: strikethrough and tables are written as HTML, equations as code.
+    #[serde(rename = "commonmark")]
+    CommonMark,
+    /// GitHub Flavored Markdown, with `$` math as GitHub renders it.
+    #[default]
+    #[serde(rename = "gfm")]
+    Gfm,
+    /// MDX (e.g. Docusaurus) with remark-gfm and remark-math. Braces are escaped
+    /// and inline HTML is written as JSX.
+    #[serde(rename = "mdx")]
+    Mdx,
+    /// Obsidian, where page mentions become `[[wiki-links]]`.
+    #[serde(rename = "obsidian")]
+    Obsidian,
+    /// Hugo's Goldmark, which leaves out raw HTML unless `unsafe` is set, and
+    /// passes `\(`/`$$` math through to KaTeX or MathJax.
+    #[serde(rename = "hugo")]
+    Hugo,
+}
+
+impl MarkdownFlavor {
+    /// Whether `~~` is strikethrough.
+    pub fn has_strikethrough(self) -> bool {
+        self != MarkdownFlavor::CommonMark
+    }
+
+    /// Whether pipe tables are supported.
+    pub fn has_tables(self) -> bool {
+        self != MarkdownFlavor::CommonMark
+    }
+
+    /// Whether inline HTML makes it into the rendered page.
+    pub fn has_inline_html(self) -> bool {
+        self != MarkdownFlavor::Hugo
+    }
+
+    /// Whether Markdown between HTML tags is still rendered, given blank lines around it.
+    /// Obsidian shows it as text, and Hugo leaves the tags out.
+    pub fn renders_markdown_in_html(self) -> bool {
+        !matches!(self, MarkdownFlavor::Obsidian | MarkdownFlavor::Hugo)
+    }
+
+    /// MDX parses HTML as JSX, where `class` is `className` and every element must be closed.
+    pub fn is_jsx(self) -> bool {
+        self == MarkdownFlavor::Mdx
+    }
+
+    fn emphasis_fallback(self) -> EmphasisFallback {
+        if self.has_inline_html() {
+            EmphasisFallback::Html
+        } else {
+            EmphasisFallback::ZeroWidthSpace
+        }
+    }
+}
+
+/// ## EmphasisFallback - Enum
+///
+/// How emphasis is written when its delimiters would not be recognized, which happens
+/// when punctuation on the inner side meets a letter on the outer side.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EmphasisFallback {
+    /// `<strong>`, `<em>` and `<del>`, which every flavor with inline HTML renders.
+    Html,
+    /// A zero-width space between the delimiter and the punctuation. Keeps the output
+    /// free of HTML, but the invisible character ends up in the rendered text.
+    ZeroWidthSpace,
+    /// The delimiters are written as they are.
+    None,
+}
+
+/// ## FrontMatterOptions - Struct
+///
+/// Which page properties go into the front matter, and under which keys.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct FrontMatterOptions {
+    pub format: FrontMatterFormat,
+
+    /// Names of the properties to include, in this order. Every property when empty.
+    pub properties: Vec<String>,
+
+    /// Front matter keys by property name, e.g. `{ "Tags": "tags" }`. The page metadata
+    /// (`created_time`, `last_edited_time`, `cover` and `icon`) can be renamed the same way.
+    /// Keys default to the property name, and to `title` for the title property.
+    pub keys: HashMap<String, String>,
+
+    /// How dates are written, as a `time` format description such as `[year]-[month]-[day]`.
+    /// Dates are kept as Notion writes them (ISO 8601) when unset.
+    pub date_format: Option<String>,
+
+    pub created_time: bool,
+    pub last_edited_time: bool,
+    pub cover: bool,
+    pub icon: bool,
+}
+
+impl Default for FrontMatterOptions {
+    fn default() -> Self {
+        FrontMatterOptions {
+            format: FrontMatterFormat::Yaml,
+            properties: Vec::new(),
+            keys: HashMap::new(),
+            date_format: None,
+            created_time: true,
+            last_edited_time: true,
+            cover: true,
+            icon: true,
+        }
+    }
+}
+
+/// ## FrontMatterFormat - Enum
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FrontMatterFormat {
+    /// Between `---` lines.
+    Yaml,
+    /// Between `+++` lines, as Hugo reads it.
+    Toml,
+}
+
+/// ## StylesheetOptions - Struct
+///
+/// Options for `generate_stylesheet`, passed as `stylesheet_options` in the event.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct StylesheetOptions {
+    pub dark_mode: DarkMode,
+
+    /// The class that switches to the dark palette when `dark_mode` is `class`.
+    pub dark_class: String,
+
+    /// The `class_prefix` of the HTML the stylesheet is for.
+    pub class_prefix: String,
+}
+
+impl Default for StylesheetOptions {
+    fn default() -> Self {
+        StylesheetOptions {
+            dark_mode: DarkMode::Media,
+            dark_class: String::from("notion-dark"),
+            class_prefix: String::from("notion-"),
+        }
+    }
+}
+
+/// ## DarkMode - Enum
+///
+/// When the dark palette applies.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DarkMode {
+    /// Follows `prefers-color-scheme: dark`.
+    Media,
+    /// Applies under an element with `dark_class`, e.g. `<html class='notion-dark'>`.
+    Class,
+    /// Only the light palette is generated.
+    None,
+}
+
+/// ## FetchOptions - Struct
+///
+/// Limits for outgoing requests to URLs found in a page (bookmark metadata and
+/// file sizes), passed as `fetch_options` in the event.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct FetchOptions {
+    /// Time allowed to establish a connection, in milliseconds.
+    pub connect_timeout_ms: u64,
+
+    /// Time allowed for the whole request, including reading the body, in milliseconds.
+    pub timeout_ms: u64,
+
+    /// The maximum number of body bytes read from a single response.
+    pub max_body_bytes: usize,
+
+    /// The maximum number of redirects followed.
+    pub max_redirects: usize,
+
+    pub user_agent: String,
+
+    /// If not empty, only these hosts (and their subdomains) are fetched.
+    pub allowed_hosts: Vec<String>,
+
+    /// Hosts (and their subdomains) that are never fetched.
+    pub denied_hosts: Vec<String>,
+}
+
+impl Default for FetchOptions {
+    fn default() -> Self {
+        FetchOptions {
+            connect_timeout_ms: 2_000,
+            timeout_ms: 5_000,
+            max_body_bytes: 512 * 1024,
+            max_redirects: 5,
+            user_agent: format!(
+                "{}/{} (+https://github.com/46ki75/notion-lambda-utils)",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION")
+            ),
+            allowed_hosts: Vec::new(),
+            denied_hosts: Vec::new(),
+        }
+    }
+}
+
+/// ## MetadataCacheOptions - Struct
+///
+/// How long bookmark metadata is cached, passed as `metadata_cache_options` in the event.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct MetadataCacheOptions {
+    /// How long successfully fetched metadata is reused, in seconds.
+    pub ttl_seconds: u64,
+
+    /// How long a failed fetch is remembered before the URL is tried again, in seconds.
+    pub negative_ttl_seconds: u64,
+
+    /// A persistent tier behind the in-memory cache, shared across cold starts.
+    pub store: Option<MetadataStoreOptions>,
+
+    /// How many entries are kept in memory. Expired entries are dropped first, then the oldest.
+    pub max_entries: usize,
+}
+
+impl Default for MetadataCacheOptions {
+    fn default() -> Self {
+        MetadataCacheOptions {
+            ttl_seconds: 24 * 60 * 60,
+            negative_ttl_seconds: 10 * 60,
+            store: None,
+            max_entries: 1000,
+        }
+    }
+}
+
+/// ## MetadataStoreOptions - Enum
+///
+/// Tagged by `type`, e.g. `{ "type": "dynamodb", "table": "link-metadata" }`.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MetadataStoreOptions {
+    /// A JSON file, e.g. under `/tmp` or on a mounted EFS volume.
+    File { path: String },
+
+    /// A DynamoDB table whose partition key is the string attribute `key`.
+    #[serde(rename = "dynamodb")]
+    DynamoDb {
+        table: String,
+
+        /// Defaults to the region of the Lambda environment.
+        region: Option<String>,
+
+        /// A DynamoDB-compatible endpoint such as DynamoDB Local or LocalStack.
+        endpoint_url: Option<String>,
+    },
+}
+
+/// ## MentionOptions - Struct
+///
+/// How page and database mentions are linked, passed as `mention_options` in the event.
+#[derive(Deserialize, Serialize, Debug, Default)]
+#[serde(default)]
+pub struct MentionOptions {
+    /// A URL template for mentioned pages and databases, e.g. `/posts/{id}`, also used for
+    /// links to pages that are not in the `link_map` of the link options.
+    /// `{id}` is replaced with the dashed id and `{compact_id}` with the id without dashes.
+    /// When unset, the public URL (or else the Notion URL) is used.
+    pub page_url_template: Option<String>,
+}
+
+/// ## LinkOptions - Struct
+///
+/// How links to Notion pages are rewritten to our site, passed as `link_options` in the event.
+#[derive(Deserialize, Serialize, Debug, Default)]
+#[serde(default)]
+pub struct LinkOptions {
+    /// Site paths by Notion page id (with or without dashes), e.g. `{ "0123...cdef": "/about" }`.
+    pub link_map: HashMap<String, String>,
+}
+
+/// ## AssetOptions - Struct
+///
+/// Where Notion-hosted files are copied to, passed as `asset_options` in the event.
+/// Files are left on Notion (whose URLs expire after an hour) when no store is configured.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(default)]
+pub struct AssetOptions {
+    pub store: Option<AssetStoreOptions>,
+
+    /// The maximum size of a single downloaded file, in bytes.
+    /// Larger files are left on Notion.
+    pub max_bytes: usize,
+
+    /// Resized copies and placeholders for images. Images are copied as they are when unset.
+    pub images: Option<ImageOptions>,
+
+    /// Time allowed to download a single file, in milliseconds.
+    pub timeout_ms: u64,
+
+    /// The hosts (and their subdomains) files are downloaded from.
+    /// Notion serves uploaded files from S3.
+    pub allowed_hosts: Vec<String>,
+}
+
+impl Default for AssetOptions {
+    fn default() -> Self {
+        AssetOptions {
+            store: None,
+            max_bytes: 50 * 1024 * 1024,
+            images: None,
+            timeout_ms: 60_000,
+            allowed_hosts: vec![
+                String::from("amazonaws.com"),
+                String::from("notion.so"),
+                String::from("notion-static.com"),
+            ],
+        }
+    }
+}
+
+impl AssetOptions {
+    /// The options for downloading files: the timeout, size limit and allowed hosts of the
+    /// assets, and everything else (including the denied hosts) of `fetch_options`.
+    pub fn fetch_options(&self, fetch_options: &FetchOptions) -> FetchOptions {
+        FetchOptions {
+            connect_timeout_ms: fetch_options.connect_timeout_ms,
+            timeout_ms: self.timeout_ms,
+            max_body_bytes: self.max_bytes,
+            max_redirects: fetch_options.max_redirects,
+            user_agent: fetch_options.user_agent.clone(),
+            allowed_hosts: self.allowed_hosts.clone(),
+            denied_hosts: fetch_options.denied_hosts.clone(),
+        }
+    }
+
+    /// The options for inlining files into a document, which may be on Notion or any other
+    /// site: those of [`Self::fetch_options`], limited to `max_bytes`, with the allowed hosts
+    /// of `fetch_options` (if any) and of the assets.
+    pub fn inline_fetch_options(
+        &self,
+        fetch_options: &FetchOptions,
+        max_bytes: usize,
+    ) -> FetchOptions {
+        let allowed_hosts = if fetch_options.allowed_hosts.is_empty() {
+            Vec::new()
+        } else {
+            fetch_options
+                .allowed_hosts
+                .iter()
+                .chain(&self.allowed_hosts)
+                .cloned()
+                .collect()
+        };
+        FetchOptions {
+            max_body_bytes: max_bytes,
+            allowed_hosts,
+            ..self.fetch_options(fetch_options)
+        }
+    }
+}
+
+/// ## ImageOptions - Struct
+///
+/// How localized images are prepared for responsive `<img>` tags.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct ImageOptions {
+    /// Widths of the resized copies, in pixels. Widths at or above the original are skipped.
+    pub widths: Vec<u32>,
+
+    /// Whether a WebP copy is made of every size.
+    pub webp: bool,
+
+    pub placeholder: PlaceholderKind,
+
+    /// The `sizes` attribute of the rendered images.
+    pub sizes: String,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        ImageOptions {
+            widths: vec![480, 960, 1440],
+            webp: true,
+            placeholder: PlaceholderKind::Blurhash,
+            sizes: String::from("(max-width: 720px) 100vw, 720px"),
+        }
+    }
+}
+
+/// ## PlaceholderKind - Enum
+///
+/// What is shown while an image loads.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaceholderKind {
+    None,
+    /// A `data-blurhash` attribute, to be decoded by a script on the page.
+    Blurhash,
+    /// A tiny blurred JPEG inlined as the background of the image.
+    Lqip,
+}
+
+/// ## AssetStoreOptions - Enum
+///
+/// Tagged by `type`, e.g. `{ "type": "s3", "bucket": "my-assets" }`.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AssetStoreOptions {
+    /// Writes files into `directory`, to be served from `base_url`.
+    Local { directory: String, base_url: String },
+
+    /// Uploads files to an S3 bucket.
+    S3 {
+        bucket: String,
+
+        /// A key prefix such as `assets/`.
+        #[serde(default)]
+        prefix: String,
+
+        /// Defaults to the region of the Lambda environment.
+        region: Option<String>,
+
+        /// An S3-compatible endpoint such as a local MinIO or LocalStack.
+        endpoint_url: Option<String>,
+
+        /// Where uploaded files are served from, e.g. a CloudFront distribution.
+        /// Defaults to the bucket URL.
+        public_base_url: Option<String>,
+
+        /// Addresses the bucket as `endpoint/bucket/key`, which most local stand-ins need.
+        #[serde(default)]
+        force_path_style: bool,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_are_only_rendered_as_structural_elements() {
+        let options = HtmlOptions {
+            elements: [
+                ("callout", "ASIDE"),
+                ("paragraph", "script"),
+                ("quote", "iframe"),
+                ("image", "a"),
+                ("code", "div onclick"),
+                ("heading_1", "h6"),
+                ("heading_3", "section"),
+            ]
+            .into_iter()
+            .map(|(block_type, element)| (block_type.to_string(), element.to_string()))
+            .collect(),
+            ..HtmlOptions::default()
+        };
+
+        assert_eq!(options.element("callout", "div"), "aside");
+        assert_eq!(options.element("paragraph", "p"), "p");
+        assert_eq!(options.element("quote", "blockquote"), "blockquote");
+        assert_eq!(options.element("image", "figure"), "figure");
+        assert_eq!(options.element("code", "pre"), "pre");
+        assert_eq!(options.heading_element("heading_1", 1), "h6");
+        assert_eq!(options.heading_element("heading_2", 2), "h2");
+        assert_eq!(options.heading_element("heading_3", 3), "section");
+    }
+
+    #[test]
+    fn only_text_blocks_are_rendered_as_text_elements() {
+        let options = HtmlOptions {
+            elements: [
+                ("paragraph", "div"),
+                ("quote", "p"),
+                ("callout", "h4"),
+                ("column", "p"),
+                ("column_list", "h2"),
+                ("code", "p"),
+                ("embed", "p"),
+                ("image", "h1"),
+                ("bookmark", "article"),
+            ]
+            .into_iter()
+            .map(|(block_type, element)| (block_type.to_string(), element.to_string()))
+            .collect(),
+            ..HtmlOptions::default()
+        };
+
+        assert_eq!(options.element("paragraph", "p"), "div");
+        assert_eq!(options.element("quote", "blockquote"), "p");
+        assert_eq!(options.element("callout", "div"), "h4");
+        assert_eq!(options.element("column", "div"), "div");
+        assert_eq!(options.element("column_list", "div"), "div");
+        assert_eq!(options.element("code", "div"), "div");
+        assert_eq!(options.element("embed", "figure"), "figure");
+        assert_eq!(options.element("image", "figure"), "figure");
+        assert_eq!(options.element("bookmark", "figure"), "article");
+    }
+}
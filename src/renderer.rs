@@ -0,0 +1,532 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use lambda_runtime::Error;
+
+use crate::context::ConvertContext;
+use crate::helpers::fetch_link_metadata::LinkMetadata;
+use crate::helpers::get_all_blocks::get_all_blocks;
+use crate::models::block::{
+    BaseBlock, Block, BookmarkBlock, BreadcrumbBlock, BulletedListItemBlock, CalloutBlock,
+    ChildDatabaseBlock, ChildPageBlock, CodeBlock, ColumnBlock, ColumnListBlock, DividerBlock,
+    EmbedBlock, EquationBlock, FileBlock, Heading1Block, Heading2Block, Heading3Block, ImageBlock,
+    LinkPreviewBlock, MentionBlock, NumberedListItemBlock, ParagraphBlock, PdfBlock, QuoteBlock,
+    SyncedBlock, TableBlock, TableOfContentsBlock, TableRowBlock, TemplateBlock, ToDoBlock,
+    ToggleBlock, UnsupportedBlock, VideoBlock,
+};
+use crate::models::rich_text::RichTextElement;
+
+/// ## Renderer - Trait
+///
+/// Turns blocks into output, with one method per kind of block and rich text element.
+/// `HtmlRenderer` and `MarkdownRenderer` implement every method. Any other renderer
+/// wraps one of them as its [`base`](Renderer::base) and overrides only what it changes;
+/// the rest is delegated to the base.
+///
+/// Child blocks are rendered through [`RenderContext::renderer`], the outermost renderer,
+/// so an override applies at every depth, e.g.
+///
+/// ```ignore
+/// struct PlainCallouts(HtmlRenderer);
+///
+/// #[async_trait]
+/// impl Renderer for PlainCallouts {
+///     fn base(&self) -> &dyn Renderer {
+///         &self.0
+///     }
+///
+///     async fn callout(&self, block: &CalloutBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+///         let text = cx.renderer.rich_text(&block.callout.rich_text, cx);
+///         Ok(format!("<aside>{}</aside>", text))
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait Renderer: Send + Sync {
+    /// The renderer that every method which is not overridden delegates to.
+    fn base(&self) -> &dyn Renderer;
+
+    /// Renders sibling blocks, e.g. with [`render_each`], and joins them.
+    async fn blocks(&self, blocks: &[Block], cx: &RenderContext<'_>) -> Result<String, Error> {
+        self.base().blocks(blocks, cx).await
+    }
+
+    async fn bookmark(
+        &self,
+        block: &BookmarkBlock,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        self.base().bookmark(block, cx).await
+    }
+
+    async fn breadcrumb(
+        &self,
+        block: &BreadcrumbBlock,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        self.base().breadcrumb(block, cx).await
+    }
+
+    async fn bulleted_list_item(
+        &self,
+        block: &BulletedListItemBlock,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        self.base().bulleted_list_item(block, cx).await
+    }
+
+    async fn callout(&self, block: &CalloutBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        self.base().callout(block, cx).await
+    }
+
+    async fn child_database(
+        &self,
+        block: &ChildDatabaseBlock,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        self.base().child_database(block, cx).await
+    }
+
+    async fn child_page(
+        &self,
+        block: &ChildPageBlock,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        self.base().child_page(block, cx).await
+    }
+
+    async fn code(&self, block: &CodeBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        self.base().code(block, cx).await
+    }
+
+    async fn column(&self, block: &ColumnBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        self.base().column(block, cx).await
+    }
+
+    async fn column_list(
+        &self,
+        block: &ColumnListBlock,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        self.base().column_list(block, cx).await
+    }
+
+    async fn divider(&self, block: &DividerBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        self.base().divider(block, cx).await
+    }
+
+    async fn embed(&self, block: &EmbedBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        self.base().embed(block, cx).await
+    }
+
+    async fn equation(
+        &self,
+        block: &EquationBlock,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        self.base().equation(block, cx).await
+    }
+
+    async fn file(&self, block: &FileBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        self.base().file(block, cx).await
+    }
+
+    async fn heading_1(
+        &self,
+        block: &Heading1Block,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        self.base().heading_1(block, cx).await
+    }
+
+    async fn heading_2(
+        &self,
+        block: &Heading2Block,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        self.base().heading_2(block, cx).await
+    }
+
+    async fn heading_3(
+        &self,
+        block: &Heading3Block,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        self.base().heading_3(block, cx).await
+    }
+
+    async fn image(&self, block: &ImageBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        self.base().image(block, cx).await
+    }
+
+    async fn link_preview(
+        &self,
+        block: &LinkPreviewBlock,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        self.base().link_preview(block, cx).await
+    }
+
+    /// `number` is the position of the item in its list, counted from the list's start.
+    async fn numbered_list_item(
+        &self,
+        block: &NumberedListItemBlock,
+        number: u32,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        self.base().numbered_list_item(block, number, cx).await
+    }
+
+    async fn mention(&self, block: &MentionBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        self.base().mention(block, cx).await
+    }
+
+    async fn paragraph(
+        &self,
+        block: &ParagraphBlock,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        self.base().paragraph(block, cx).await
+    }
+
+    async fn pdf(&self, block: &PdfBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        self.base().pdf(block, cx).await
+    }
+
+    async fn quote(&self, block: &QuoteBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        self.base().quote(block, cx).await
+    }
+
+    async fn synced_block(
+        &self,
+        block: &SyncedBlock,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        self.base().synced_block(block, cx).await
+    }
+
+    async fn table(&self, block: &TableBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        self.base().table(block, cx).await
+    }
+
+    async fn table_of_contents(
+        &self,
+        block: &TableOfContentsBlock,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        self.base().table_of_contents(block, cx).await
+    }
+
+    /// Rows are rendered together with their table, so the built-in renderers return nothing.
+    async fn table_row(
+        &self,
+        block: &TableRowBlock,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        self.base().table_row(block, cx).await
+    }
+
+    async fn template(
+        &self,
+        block: &TemplateBlock,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        self.base().template(block, cx).await
+    }
+
+    async fn to_do(&self, block: &ToDoBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        self.base().to_do(block, cx).await
+    }
+
+    async fn toggle(&self, block: &ToggleBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        self.base().toggle(block, cx).await
+    }
+
+    async fn unsupported(
+        &self,
+        block: &UnsupportedBlock,
+        cx: &RenderContext<'_>,
+    ) -> Result<String, Error> {
+        self.base().unsupported(block, cx).await
+    }
+
+    async fn video(&self, block: &VideoBlock, cx: &RenderContext<'_>) -> Result<String, Error> {
+        self.base().video(block, cx).await
+    }
+
+    /// Extra attributes of the element of a block of `block_type`, as names and values.
+    /// Only HTML has attributes, so `MarkdownRenderer` returns none.
+    fn block_attributes(
+        &self,
+        block_type: &str,
+        base: &BaseBlock,
+        cx: &RenderContext<'_>,
+    ) -> Vec<(String, String)> {
+        self.base().block_attributes(block_type, base, cx)
+    }
+
+    /// Renders a run of rich text, e.g. a paragraph or a caption, through the element methods.
+    fn rich_text(&self, rich_text: &[RichTextElement], cx: &RenderContext<'_>) -> String {
+        self.base().rich_text(rich_text, cx)
+    }
+
+    /// The element methods return `None` to leave an element to the default serialization,
+    /// which for Markdown nests annotations shared with its neighbours.
+    fn text_element(&self, element: &RichTextElement, cx: &RenderContext<'_>) -> Option<String> {
+        self.base().text_element(element, cx)
+    }
+
+    fn mention_element(&self, element: &RichTextElement, cx: &RenderContext<'_>) -> Option<String> {
+        self.base().mention_element(element, cx)
+    }
+
+    fn equation_element(
+        &self,
+        element: &RichTextElement,
+        cx: &RenderContext<'_>,
+    ) -> Option<String> {
+        self.base().equation_element(element, cx)
+    }
+}
+
+/// ## RenderContext - Struct
+///
+/// What a [`Renderer`] method gets besides its block.
+pub struct RenderContext<'a> {
+    pub context: &'a ConvertContext,
+    /// The outermost renderer, which child blocks and rich text are rendered through.
+    pub renderer: &'a dyn Renderer,
+    /// The metadata of the bookmarks and link previews among the blocks, by URL.
+    pub link_metadata: &'a HashMap<String, LinkMetadata>,
+}
+
+impl RenderContext<'_> {
+    /// Renders the children of the block `block_id`.
+    pub async fn render_children(&self, block_id: &str) -> Result<String, Error> {
+        render_page(self.renderer, block_id, self.context).await
+    }
+
+    /// Fetches the child blocks of `block_id` and resolves what they link to, for blocks
+    /// that lay out their children themselves, such as the rows of a table.
+    pub async fn child_blocks(&self, block_id: &str) -> Result<Vec<Block>, Error> {
+        let mut blocks = get_all_blocks(&self.context.notion_api_key, block_id).await?;
+        // Rows and cells hold no bookmarks, so the link metadata is not needed here.
+        self.context.resolve_blocks(&mut blocks).await;
+        Ok(blocks)
+    }
+
+    /// Renders a rich text element with the method for its kind, through the outermost renderer.
+    pub fn render_element(&self, element: &RichTextElement) -> Option<String> {
+        match element {
+            RichTextElement::Text { .. } => self.renderer.text_element(element, self),
+            RichTextElement::Mention { .. } => self.renderer.mention_element(element, self),
+            RichTextElement::Equation { .. } => self.renderer.equation_element(element, self),
+        }
+    }
+}
+
+/// Fetches the child blocks of `block_id`, resolves what they link to and renders them.
+pub async fn render_page(
+    renderer: &dyn Renderer,
+    block_id: &str,
+    context: &ConvertContext,
+) -> Result<String, Error> {
+    let mut blocks = get_all_blocks(&context.notion_api_key, block_id).await?;
+    let link_metadata = context.resolve_blocks(&mut blocks).await;
+
+    let cx = RenderContext {
+        context,
+        renderer,
+        link_metadata: &link_metadata,
+    };
+    renderer.blocks(&blocks, &cx).await
+}
+
+/// Renders each block with the method for its kind, numbering the items of numbered lists.
+pub async fn render_each(blocks: &[Block], cx: &RenderContext<'_>) -> Result<Vec<String>, Error> {
+    let renderer = cx.renderer;
+    let mut rendered = Vec::new();
+
+    // The number of the last numbered list item, while a list is going on.
+    let mut list_number: Option<u32> = None;
+
+    for block in blocks {
+        if !matches!(block, Block::NumberedListItem(_)) {
+            list_number = None;
+        }
+
+        let output = match block {
+            Block::Bookmark(block) => renderer.bookmark(block, cx).await?,
+            Block::Breadcrumb(block) => renderer.breadcrumb(block, cx).await?,
+            Block::BulletedListItem(block) => renderer.bulleted_list_item(block, cx).await?,
+            Block::Callout(block) => renderer.callout(block, cx).await?,
+            Block::ChildDatabase(block) => renderer.child_database(block, cx).await?,
+            Block::ChildPage(block) => renderer.child_page(block, cx).await?,
+            Block::Code(block) => renderer.code(block, cx).await?,
+            Block::Column(block) => renderer.column(block, cx).await?,
+            Block::ColumnList(block) => renderer.column_list(block, cx).await?,
+            Block::Divider(block) => renderer.divider(block, cx).await?,
+            Block::Embed(block) => renderer.embed(block, cx).await?,
+            Block::Equation(block) => renderer.equation(block, cx).await?,
+            Block::File(block) => renderer.file(block, cx).await?,
+            Block::Heading1(block) => renderer.heading_1(block, cx).await?,
+            Block::Heading2(block) => renderer.heading_2(block, cx).await?,
+            Block::Heading3(block) => renderer.heading_3(block, cx).await?,
+            Block::Image(block) => renderer.image(block, cx).await?,
+            Block::LinkPreview(block) => renderer.link_preview(block, cx).await?,
+            Block::NumberedListItem(block) => {
+                let number = match list_number {
+                    Some(number) => number + 1,
+                    None => block.numbered_list_item.list_start_index.unwrap_or(1),
+                };
+                list_number = Some(number);
+                renderer.numbered_list_item(block, number, cx).await?
+            }
+            Block::Mention(block) => renderer.mention(block, cx).await?,
+            Block::Paragraph(block) => renderer.paragraph(block, cx).await?,
+            Block::Pdf(block) => renderer.pdf(block, cx).await?,
+            Block::Quote(block) => renderer.quote(block, cx).await?,
+            Block::SyncedBlock(block) => renderer.synced_block(block, cx).await?,
+            Block::Table(block) => renderer.table(block, cx).await?,
+            Block::TableOfContents(block) => renderer.table_of_contents(block, cx).await?,
+            Block::TableRow(block) => renderer.table_row(block, cx).await?,
+            Block::Template(block) => renderer.template(block, cx).await?,
+            Block::ToDo(block) => renderer.to_do(block, cx).await?,
+            Block::Toggle(block) => renderer.toggle(block, cx).await?,
+            Block::Unsupported(block) => renderer.unsupported(block, cx).await?,
+            Block::Video(block) => renderer.video(block, cx).await?,
+        };
+        rendered.push(output);
+    }
+
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::convert_page_to_html::HtmlRenderer;
+    use crate::commands::convert_page_to_markdown::MarkdownRenderer;
+    use crate::fixtures::{annotations, block, context, mention, styled_text};
+    use crate::models::rich_text::Mention;
+    use crate::options::{HtmlOptions, MarkdownOptions};
+    use serde_json::json;
+
+    /// Overrides dividers and page mentions, and leaves everything else to `base`.
+    struct Custom<R: Renderer> {
+        base: R,
+    }
+
+    #[async_trait]
+    impl<R: Renderer> Renderer for Custom<R> {
+        fn base(&self) -> &dyn Renderer {
+            &self.base
+        }
+
+        async fn divider(
+            &self,
+            _block: &DividerBlock,
+            _cx: &RenderContext<'_>,
+        ) -> Result<String, Error> {
+            Ok(String::from("\n* * *\n\n"))
+        }
+
+        fn mention_element(
+            &self,
+            element: &RichTextElement,
+            cx: &RenderContext<'_>,
+        ) -> Option<String> {
+            match element {
+                RichTextElement::Mention {
+                    mention: Mention::Page { page },
+                    ..
+                } => Some(format!("{{{{< ref \"{}\" >}}}}", page.id)),
+                _ => self.base.mention_element(element, cx),
+            }
+        }
+    }
+
+    fn blocks() -> Vec<Block> {
+        let mut page_mention = mention(
+            json!({ "type": "page", "page": { "id": "p1" } }),
+            "Setup",
+            None,
+        );
+        page_mention["annotations"] = annotations(&["bold"]);
+        vec![
+            block(
+                "paragraph",
+                json!({
+                    "rich_text": [styled_text("See ", &["bold"], None), page_mention],
+                    "color": "default"
+                }),
+            ),
+            block("divider", json!({})),
+        ]
+    }
+
+    async fn render(renderer: &dyn Renderer, blocks: &[Block]) -> String {
+        let context = context().await;
+        let link_metadata = HashMap::new();
+        let cx = RenderContext {
+            context: &context,
+            renderer,
+            link_metadata: &link_metadata,
+        };
+        renderer.blocks(blocks, &cx).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn overrides_replace_only_their_own_output() {
+        let markdown = Custom {
+            base: MarkdownRenderer::new(MarkdownOptions::default()),
+        };
+        assert_eq!(
+            render(&markdown, &blocks()).await,
+            "**See** {{< ref \"p1\" >}}\n\n* * *\n"
+        );
+
+        let html = Custom {
+            base: HtmlRenderer::new(HtmlOptions::default()),
+        };
+        assert_eq!(
+            render(&html, &blocks()).await,
+            "<p class='notion-paragraph' id='b'><span class='notion-rich-text'><strong>See </strong></span>{{< ref \"p1\" >}}</p>\n* * *\n\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn html_options_shape_the_markup() {
+        let heading = block(
+            "heading_2",
+            json!({ "rich_text": [], "is_toggleable": false, "color": "blue" }),
+        );
+        let blocks = [heading, blocks().remove(1)];
+
+        let options: HtmlOptions = serde_json::from_value(json!({
+            "class_prefix": "n-",
+            "heading_offset": 1,
+            "elements": { "heading_2": "div", "divider": "section" },
+            "data_block_id": true,
+            "block_attributes": {
+                "heading_2": { "role": "heading", "onclick": "alert(1)", "class": "mt-4" },
+            },
+        }))
+        .unwrap();
+        assert_eq!(
+            render(&HtmlRenderer::new(options), &blocks).await,
+            "<div class='n-heading-2 n-blue mt-4' id='b' data-block-id='b' role='heading'></div><hr class='n-divider' id='b' data-block-id='b' />"
+        );
+
+        let options = HtmlOptions {
+            classes: false,
+            block_ids: false,
+            heading_offset: 5,
+            ..HtmlOptions::default()
+        };
+        assert_eq!(
+            render(&HtmlRenderer::new(options), &blocks).await,
+            "<h6></h6><hr />"
+        );
+    }
+}